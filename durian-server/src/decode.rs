@@ -0,0 +1,38 @@
+use capnp::Error;
+use durian::address::Address;
+use primitive_types::{H256, U256};
+
+fn check_len(name: &str, bytes: &[u8], len: usize) -> Result<(), Error> {
+    if bytes.len() != len {
+        return Err(Error::failed(format!(
+            "Invalid {}: {} bytes, expected {}",
+            name,
+            bytes.len(),
+            len
+        )));
+    }
+    Ok(())
+}
+
+/// Reads an address sent by the client, which must be 20 bytes
+pub fn address(bytes: &[u8]) -> Result<Address, Error> {
+    check_len("address", bytes, 20)?;
+    Ok(Address::from_slice(bytes))
+}
+
+/// Reads a hash sent by the client, which must be 32 bytes
+pub fn h256(bytes: &[u8]) -> Result<H256, Error> {
+    check_len("hash", bytes, 32)?;
+    Ok(H256::from_slice(bytes))
+}
+
+/// Reads a little endian number sent by the client, up to 32 bytes
+pub fn u256(bytes: &[u8]) -> Result<U256, Error> {
+    if bytes.len() > 32 {
+        return Err(Error::failed(format!(
+            "Invalid number: {} bytes, expected at most 32",
+            bytes.len()
+        )));
+    }
+    Ok(U256::from_little_endian(bytes))
+}
@@ -1,30 +1,38 @@
+use crate::decode;
 use crate::durian_capnp;
 use crate::durian_capnp::executor;
 use crate::provider_adaptor::ProviderAdaptor;
 use capnp::capability::Promise;
 use capnp::Error;
-use durian::address::Address;
-use primitive_types::{H256, U256};
-use tokio::sync::oneshot;
-use tokio::sync::oneshot::error::TryRecvError;
+use durian::limits::Limit;
+use durian::outcome::{ExecutionOutcome, TrapKind};
 
 impl<'a> From<durian_capnp::transaction::Reader<'a>>
     for Result<durian::transaction::Transaction, Error>
 {
     fn from(reader: durian_capnp::transaction::Reader<'a>) -> Self {
-        let sender = Address::from_slice(reader.get_sender()?);
-        let value = U256::from_little_endian(reader.get_value()?);
-        let gas = U256::from_little_endian(reader.get_gas()?);
-        let gas_price = U256::from_little_endian(reader.get_gas_price()?);
+        let sender = decode::address(reader.get_sender()?)?;
+        let value = decode::u256(reader.get_value()?)?;
+        let gas = decode::u256(reader.get_gas()?)?;
+        let gas_price = decode::u256(reader.get_gas_price()?)?;
         let args = reader.get_args()?.to_vec();
+        let mut access_list = Vec::new();
+        for entry in reader.get_access_list()?.iter() {
+            let address = decode::address(entry.get_address()?)?;
+            let mut keys = Vec::new();
+            for key in entry.get_keys()?.iter() {
+                keys.push(decode::h256(key?)?);
+            }
+            access_list.push((address, keys));
+        }
         let action = match reader.get_action().which()? {
             durian_capnp::transaction::action::Create(create) => {
                 let code = create.get_code()?.to_vec();
-                let salt = H256::from_slice(create.get_salt()?);
+                let salt = decode::h256(create.get_salt()?)?;
                 durian::transaction::Action::Create(code, salt)
             }
             durian_capnp::transaction::action::Call(call) => {
-                let address = Address::from_slice(call.get_address()?);
+                let address = decode::address(call.get_address()?)?;
                 durian::transaction::Action::Call(address)
             }
         };
@@ -36,6 +44,7 @@ impl<'a> From<durian_capnp::transaction::Reader<'a>>
             gas_price: gas_price,
             action: action,
             args: args,
+            access_list: access_list,
         })
     }
 }
@@ -48,9 +57,6 @@ impl ExecutorImpl {
     }
 }
 
-unsafe impl Send for durian_capnp::provider::Client {}
-//unsafe impl Sync for durian_capnp::provider::Client {}
-
 impl executor::Server for ExecutorImpl {
     fn execute(
         &mut self,
@@ -59,52 +65,99 @@ impl executor::Server for ExecutorImpl {
     ) -> Promise<(), Error> {
         let provider_client = pry!(pry!(params.get()).get_provider());
         let transaction = pry!(pry!(pry!(params.get()).get_transaction()).into());
-        let (tx, mut rx) = oneshot::channel();
-
-        tokio::task::spawn(async move {
-            debug!("provider: {:?}", std::thread::current().id());
-            let mut adaptor = ProviderAdaptor::new(provider_client);
-
-            let result = durian::execute::execute(&mut adaptor, &transaction).unwrap();
-
-            tx.send(result).unwrap();
-        });
-        debug!("executor: {:?}", std::thread::current().id());
 
         Promise::from_future(async move {
-            loop {
-                let msg = rx.try_recv();
-                match msg {
-                    Err(TryRecvError::Empty) => {}
-                    Err(e) => {
-                        return Err(Error::failed(format!("{}", e)));
-                    }
-                    Ok(result_data) => {
-                        tokio::time::delay_for(std::time::Duration::from_millis(10 as u64)).await;
-
-                        let mut tmp = Vec::new();
-                        tmp.resize(32, 0);
-
-                        let mut builder = results.get().get_result_data().unwrap();
-
-                        result_data.gas_left.to_little_endian(&mut tmp);
-                        builder.set_gas_left(&tmp);
-                        builder.set_data(&result_data.data);
-                        builder.set_contract(&result_data.contract.as_bytes());
-
-                        // TODO: Implement it later
-                        //builder.set_logs();
-
-                        break;
-                    }
-                };
-
-                //print!(".");
-                tokio::task::yield_now().await;
-                //tokio::time::delay_for(std::time::Duration::from_millis(10 as u64)).await;
+            let mut adaptor = ProviderAdaptor::new(provider_client);
+            let result_data = durian::execute::execute_async(&mut adaptor, &transaction)
+                .await
+                .map_err(|e| Error::failed(format!("Execution failed: {}", e)))?;
+
+            let mut tmp = Vec::new();
+            tmp.resize(32, 0);
+
+            let mut builder = results.get().init_result_data();
+
+            result_data.gas_left.to_little_endian(&mut tmp);
+            builder.set_gas_left(&tmp);
+            result_data.gas_used.to_little_endian(&mut tmp);
+            builder.set_gas_used(&tmp);
+            build_outcome(builder.reborrow().get_outcome(), &result_data.outcome);
+            builder.set_data(&result_data.data);
+            builder.set_contract(&result_data.contract.as_bytes());
+
+            let mut logs_builder = builder.init_logs(result_data.logs.len() as u32);
+            for (i, log) in result_data.logs.iter().enumerate() {
+                let mut log_builder = logs_builder.reborrow().get(i as u32);
+                log_builder.set_address(log.address.as_bytes());
+                let mut topics_builder = log_builder.reborrow().init_topics(log.topics.len() as u32);
+                for (j, topic) in log.topics.iter().enumerate() {
+                    topics_builder.set(j as u32, topic.as_bytes());
+                }
+                let mut data_builder = log_builder.init_data(log.data.len() as u32);
+                for (j, byte) in log.data.iter().enumerate() {
+                    data_builder.set(j as u32, *byte as i8);
+                }
             }
 
             Ok(())
         })
     }
 }
+
+fn build_outcome(
+    mut builder: durian_capnp::result_data::outcome::Builder,
+    outcome: &ExecutionOutcome,
+) {
+    match outcome {
+        ExecutionOutcome::Success => builder.set_success(()),
+        ExecutionOutcome::Revert(data) => builder.set_revert(&data),
+        ExecutionOutcome::OutOfGas => builder.set_out_of_gas(()),
+        ExecutionOutcome::Trap(kind, location) => {
+            let mut trap = builder.init_trap();
+            let kind = match kind {
+                TrapKind::Unreachable => durian_capnp::TrapKind::Unreachable,
+                TrapKind::MemoryAccessOutOfBounds => durian_capnp::TrapKind::MemoryAccessOutOfBounds,
+                TrapKind::TableAccessOutOfBounds => durian_capnp::TrapKind::TableAccessOutOfBounds,
+                TrapKind::ElemUninitialized => durian_capnp::TrapKind::ElemUninitialized,
+                TrapKind::DivisionByZero => durian_capnp::TrapKind::DivisionByZero,
+                TrapKind::InvalidConversionToInt => durian_capnp::TrapKind::InvalidConversionToInt,
+                TrapKind::UnexpectedSignature => durian_capnp::TrapKind::UnexpectedSignature,
+                TrapKind::StackOverflow => durian_capnp::TrapKind::StackOverflow,
+                TrapKind::Host(msg) => {
+                    trap.set_host_error(&msg);
+                    durian_capnp::TrapKind::Host
+                }
+            };
+            trap.set_kind(kind);
+            if let Some(location) = location {
+                let mut trap_location = trap.init_location();
+                trap_location.set_function(location.function);
+                if let Some(offset) = location.offset {
+                    trap_location.set_offset(offset);
+                    trap_location.set_has_offset(true);
+                }
+                trap_location.set_function_name(location.function_name.as_ref().map(String::as_str).unwrap_or(""));
+            }
+        }
+        ExecutionOutcome::Panic(payload) => {
+            let mut panic = builder.init_panic();
+            panic.set_msg(payload.msg.as_ref().map(String::as_str).unwrap_or(""));
+            panic.set_file(payload.file.as_ref().map(String::as_str).unwrap_or(""));
+            panic.set_line(payload.line.unwrap_or(0));
+            panic.set_col(payload.col.unwrap_or(0));
+        }
+        ExecutionOutcome::InvalidCode(msg) => builder.set_invalid_code(&msg),
+        ExecutionOutcome::LimitExceeded(limit) => builder.set_limit_exceeded(match limit {
+            Limit::MemoryPages => durian_capnp::Limit::MemoryPages,
+            Limit::HostCalls => durian_capnp::Limit::HostCalls,
+            Limit::ReturnData => durian_capnp::Limit::ReturnData,
+            Limit::Deadline => durian_capnp::Limit::Deadline,
+            Limit::FetchRounds => durian_capnp::Limit::FetchRounds,
+        }),
+        ExecutionOutcome::CodeSizeLimit { size, limit } => {
+            let mut code_size_limit = builder.init_code_size_limit();
+            code_size_limit.set_size(*size as u64);
+            code_size_limit.set_limit(*limit as u64);
+        }
+    }
+}
@@ -7,6 +7,7 @@ extern crate log;
 pub mod durian_capnp {
     include!(concat!(env!("OUT_DIR"), "/durian_capnp.rs"));
 }
+mod decode;
 mod executor_impl;
 mod provider_adaptor;
 
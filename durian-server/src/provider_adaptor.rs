@@ -1,7 +1,9 @@
+use crate::decode;
 use crate::durian_capnp;
 use durian::address::Address;
-use durian::provider::{Provider, StateAccount};
+use durian::provider::{AsyncProvider, BlockContext, ProviderFuture, StateAccount, StateChangeSet};
 use primitive_types::{H256, U256};
+use std::future::{self, Future};
 
 struct Error {
     pub failed: String,
@@ -16,7 +18,7 @@ impl From<::capnp::Error> for Error {
 }
 impl From<Error> for durian::error::Error {
     fn from(error: Error) -> Self {
-        durian::error::Error::Other { msg: error.failed }
+        durian::error::Error::ProviderFailure { msg: error.failed }
     }
 }
 
@@ -30,8 +32,24 @@ impl ProviderAdaptor {
     }
 }
 
-impl Provider for ProviderAdaptor {
-    fn exist(&self, address: &Address) -> bool {
+fn boxed<T, F>(handle: F) -> ProviderFuture<T>
+where
+    F: Future<Output = Result<T, Error>> + 'static,
+{
+    Box::pin(async move { handle.await.map_err(|e| e.into()) })
+}
+
+/// The client sends an empty slot as empty data
+fn storage_value(storage: &[u8]) -> Result<Option<H256>, ::capnp::Error> {
+    if storage.is_empty() {
+        Ok(None)
+    } else {
+        decode::h256(storage).map(Some)
+    }
+}
+
+impl AsyncProvider for ProviderAdaptor {
+    fn exist(&self, address: &Address) -> ProviderFuture<bool> {
         let mut request = self.client.exist_request();
         {
             request.get().set_address(address.as_bytes());
@@ -44,14 +62,11 @@ impl Provider for ProviderAdaptor {
 
             Ok(exist)
         };
-        let ret: Result<bool, ::capnp::Error> = futures::executor::block_on(handle);
-        match ret {
-            Ok(exist) => exist,
-            Err(_) => false,
-        }
+
+        boxed(handle)
     }
 
-    fn account(&self, address: &Address) -> Result<StateAccount, durian::error::Error> {
+    fn account(&self, address: &Address) -> ProviderFuture<Option<StateAccount>> {
         let mut request = self.client.account_request();
         {
             request.get().set_address(address.as_bytes());
@@ -59,23 +74,26 @@ impl Provider for ProviderAdaptor {
         let handle = async move {
             debug!("Try ot call `account` method in client");
             let result = request.send().promise.await?;
+            if !result.get()?.get_exist() {
+                return Ok(None);
+            }
             let account = result.get()?.get_account()?;
 
-            Ok(StateAccount {
-                nonce: U256::from_little_endian(account.get_nonce()?),
-                balance: U256::from_little_endian(account.get_balance()?),
-                code: account.get_code()?.to_vec(),
-            })
+            Ok(Some(StateAccount {
+                nonce: decode::u256(account.get_nonce()?)?,
+                balance: decode::u256(account.get_balance()?)?,
+                code_hash: decode::h256(account.get_code_hash()?)?,
+            }))
         };
 
-        futures::executor::block_on(handle).map_err(|e: Error| e.into())
+        boxed(handle)
     }
 
     fn create_contract(
         &mut self,
         address: &Address,
         code: &Vec<u8>,
-    ) -> Result<(), durian::error::Error> {
+    ) -> ProviderFuture<()> {
         let mut request = self.client.create_contract_request();
         {
             request.get().set_address(address.as_bytes());
@@ -88,7 +106,7 @@ impl Provider for ProviderAdaptor {
             Ok(())
         };
 
-        futures::executor::block_on(handle).map_err(|e: Error| e.into())
+        boxed(handle)
     }
 
     fn update_account(
@@ -96,7 +114,7 @@ impl Provider for ProviderAdaptor {
         address: &Address,
         balance: &U256,
         nonce: &U256,
-    ) -> Result<(), durian::error::Error> {
+    ) -> ProviderFuture<()> {
         let mut request = self.client.update_account_request();
         {
             let mut tmp = Vec::new();
@@ -117,10 +135,10 @@ impl Provider for ProviderAdaptor {
             Ok(())
         };
 
-        futures::executor::block_on(handle).map_err(|e: Error| e.into())
+        boxed(handle)
     }
 
-    fn storage_at(&self, address: &Address, key: &H256) -> Result<H256, durian::error::Error> {
+    fn storage_at(&self, address: &Address, key: &H256) -> ProviderFuture<Option<H256>> {
         let mut request = self.client.storage_at_request();
         {
             request.get().set_address(address.as_bytes());
@@ -131,10 +149,10 @@ impl Provider for ProviderAdaptor {
             let result = request.send().promise.await?;
             let storage = result.get()?.get_storage()?;
 
-            Ok(H256::from_slice(storage))
+            Ok(storage_value(storage)?)
         };
 
-        futures::executor::block_on(handle).map_err(|e: Error| e.into())
+        boxed(handle)
     }
 
     fn set_storage(
@@ -142,7 +160,7 @@ impl Provider for ProviderAdaptor {
         address: &Address,
         key: &H256,
         value: &H256,
-    ) -> Result<(), durian::error::Error> {
+    ) -> ProviderFuture<()> {
         let mut request = self.client.set_storage_request();
         {
             request.get().set_address(address.as_bytes());
@@ -156,30 +174,255 @@ impl Provider for ProviderAdaptor {
             Ok(())
         };
 
-        futures::executor::block_on(handle).map_err(|e: Error| e.into())
+        boxed(handle)
     }
 
-    fn block_hash(&self, _num: u64) -> Result<H256, durian::error::Error> {
-        Ok(H256::zero())
+    fn code_by_hash(&self, code_hash: &H256) -> ProviderFuture<Option<Vec<u8>>> {
+        let mut request = self.client.code_by_hash_request();
+        {
+            request.get().set_code_hash(code_hash.as_bytes());
+        }
+        let handle = async move {
+            debug!("Try ot call `code_by_hash` method in client");
+            let result = request.send().promise.await?;
+            if !result.get()?.get_exist() {
+                return Ok(None);
+            }
+            let code = result.get()?.get_code()?;
+
+            Ok(Some(code.to_vec()))
+        };
+
+        boxed(handle)
     }
 
-    fn timestamp(&self) -> u64 {
-        0
+    fn accounts(&self, addresses: &[Address]) -> ProviderFuture<Vec<Option<StateAccount>>> {
+        let mut request = self.client.accounts_request();
+        {
+            let mut list = request.get().init_addresses(addresses.len() as u32);
+            for (i, address) in addresses.iter().enumerate() {
+                list.set(i as u32, address.as_bytes());
+            }
+        }
+        let handle = async move {
+            debug!("Try ot call `accounts` method in client");
+            let result = request.send().promise.await?;
+            let exist = result.get()?.get_exist()?;
+            let accounts = result.get()?.get_accounts()?;
+
+            let mut ret = Vec::with_capacity(accounts.len() as usize);
+            for (i, account) in accounts.iter().enumerate() {
+                if exist.get(i as u32) {
+                    ret.push(Some(StateAccount {
+                        nonce: decode::u256(account.get_nonce()?)?,
+                        balance: decode::u256(account.get_balance()?)?,
+                        code_hash: decode::h256(account.get_code_hash()?)?,
+                    }));
+                } else {
+                    ret.push(None);
+                }
+            }
+
+            Ok(ret)
+        };
+
+        boxed(handle)
     }
 
-    fn block_number(&self) -> u64 {
-        0
+    fn storages(&self, keys: &[(Address, H256)]) -> ProviderFuture<Vec<Option<H256>>> {
+        let mut request = self.client.storages_request();
+        {
+            let mut addresses = request.get().init_addresses(keys.len() as u32);
+            for (i, (address, _)) in keys.iter().enumerate() {
+                addresses.set(i as u32, address.as_bytes());
+            }
+        }
+        {
+            let mut storage_keys = request.get().init_keys(keys.len() as u32);
+            for (i, (_, key)) in keys.iter().enumerate() {
+                storage_keys.set(i as u32, key.as_bytes());
+            }
+        }
+        let handle = async move {
+            debug!("Try ot call `storages` method in client");
+            let result = request.send().promise.await?;
+            let storages = result.get()?.get_storages()?;
+
+            let mut ret = Vec::with_capacity(storages.len() as usize);
+            for storage in storages.iter() {
+                ret.push(storage_value(storage?)?);
+            }
+
+            Ok(ret)
+        };
+
+        boxed(handle)
     }
 
-    fn block_author(&self) -> Result<Address, durian::error::Error> {
-        Err(durian::error::Error::NotSupported)
+    fn apply_changes(&mut self, changes: StateChangeSet) -> ProviderFuture<()> {
+        let mut request = self.client.apply_changes_request();
+        {
+            let mut tmp = Vec::new();
+            tmp.resize(32, 0);
+
+            let mut builder = request.get().init_changes();
+            {
+                let mut created = builder
+                    .reborrow()
+                    .init_created(changes.created.len() as u32);
+                for (i, (address, code)) in changes.created.iter().enumerate() {
+                    let mut contract = created.reborrow().get(i as u32);
+                    contract.set_address(address.as_bytes());
+                    contract.set_code(code);
+                }
+            }
+            {
+                let mut updated = builder
+                    .reborrow()
+                    .init_updated(changes.updated.len() as u32);
+                for (i, (address, balance, nonce)) in changes.updated.iter().enumerate() {
+                    let mut account = updated.reborrow().get(i as u32);
+                    account.set_address(address.as_bytes());
+
+                    balance.to_little_endian(&mut tmp);
+                    account.set_balance(&tmp);
+
+                    nonce.to_little_endian(&mut tmp);
+                    account.set_nonce(&tmp);
+                }
+            }
+            {
+                let mut storage = builder
+                    .reborrow()
+                    .init_storage(changes.storage.len() as u32);
+                for (i, (address, key, value)) in changes.storage.iter().enumerate() {
+                    let mut change = storage.reborrow().get(i as u32);
+                    change.set_address(address.as_bytes());
+                    change.set_key(key.as_bytes());
+                    change.set_value(value.as_bytes());
+                }
+            }
+            {
+                let mut prepared_codes = builder
+                    .reborrow()
+                    .init_prepared_codes(changes.prepared_codes.len() as u32);
+                for (i, (code_hash, version, code)) in changes.prepared_codes.iter().enumerate() {
+                    let mut prepared_code = prepared_codes.reborrow().get(i as u32);
+                    prepared_code.set_code_hash(code_hash.as_bytes());
+
+                    version.to_little_endian(&mut tmp);
+                    prepared_code.set_version(&tmp);
+
+                    prepared_code.set_code(code);
+                }
+            }
+            {
+                let mut deleted = builder
+                    .reborrow()
+                    .init_deleted(changes.deleted.len() as u32);
+                for (i, address) in changes.deleted.iter().enumerate() {
+                    deleted.set(i as u32, address.as_bytes());
+                }
+            }
+        }
+        let handle = async move {
+            debug!("Try ot call `apply_changes` method in client");
+            request.send().promise.await?;
+
+            Ok(())
+        };
+
+        boxed(handle)
     }
 
-    fn difficulty(&self) -> Result<U256, durian::error::Error> {
-        Err(durian::error::Error::NotSupported)
+    fn block_hash(&self, _num: u64) -> ProviderFuture<H256> {
+        Box::pin(future::ready(Ok(H256::zero())))
+    }
+
+    fn block_context(&self) -> ProviderFuture<BlockContext> {
+        let request = self.client.block_context_request();
+        let handle = async move {
+            debug!("Try ot call `block_context` method in client");
+            let result = request.send().promise.await?;
+            let context = result.get()?.get_context()?;
+
+            Ok(BlockContext {
+                number: context.get_number(),
+                timestamp: context.get_timestamp(),
+                author: decode::address(context.get_author()?)?,
+                difficulty: decode::u256(context.get_difficulty()?)?,
+                gas_limit: decode::u256(context.get_gas_limit()?)?,
+                chain_id: context.get_chain_id(),
+                base_fee: if context.get_has_base_fee() {
+                    Some(decode::u256(context.get_base_fee()?)?)
+                } else {
+                    None
+                },
+                prev_randao: if context.get_has_prev_randao() {
+                    Some(decode::h256(context.get_prev_randao()?)?)
+                } else {
+                    None
+                },
+            })
+        };
+
+        boxed(handle)
+    }
+
+    fn prepared_code(
+        &self,
+        code_hash: &H256,
+        version: &U256,
+    ) -> ProviderFuture<Option<Vec<u8>>> {
+        let mut request = self.client.prepared_code_request();
+        {
+            let mut tmp = Vec::new();
+            tmp.resize(32, 0);
+
+            request.get().set_code_hash(code_hash.as_bytes());
+
+            version.to_little_endian(&mut tmp);
+            request.get().set_version(&tmp);
+        }
+        let handle = async move {
+            debug!("Try ot call `prepared_code` method in client");
+            let result = request.send().promise.await?;
+            if !result.get()?.get_exist() {
+                return Ok(None);
+            }
+            let code = result.get()?.get_code()?;
+
+            Ok(Some(code.to_vec()))
+        };
+
+        boxed(handle)
     }
 
-    fn gas_limit(&self) -> Result<U256, durian::error::Error> {
-        Ok(U256::zero())
+    fn set_prepared_code(
+        &mut self,
+        code_hash: &H256,
+        version: &U256,
+        code: &Vec<u8>,
+    ) -> ProviderFuture<()> {
+        let mut request = self.client.set_prepared_code_request();
+        {
+            let mut tmp = Vec::new();
+            tmp.resize(32, 0);
+
+            request.get().set_code_hash(code_hash.as_bytes());
+
+            version.to_little_endian(&mut tmp);
+            request.get().set_version(&tmp);
+
+            request.get().set_code(code);
+        }
+        let handle = async move {
+            debug!("Try ot call `set_prepared_code` method in client");
+            request.send().promise.await?;
+
+            Ok(())
+        };
+
+        boxed(handle)
     }
 }
@@ -0,0 +1,33 @@
+use snafu::Snafu;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Database error: {}", source))]
+    Database { source: sled::Error },
+
+    #[snafu(display("Corrupted data: {}", msg))]
+    Corrupted { msg: String },
+
+    #[snafu(display("Unknown block: {}", number))]
+    UnknownBlock { number: u64 },
+
+    #[snafu(display("Block {} is older than the head block {}", number, head))]
+    OldBlock { number: u64, head: u64 },
+
+    #[snafu(display("State at block {} is read-only", number))]
+    ReadOnly { number: u64 },
+}
+
+impl From<sled::Error> for Error {
+    fn from(source: sled::Error) -> Self {
+        Error::Database { source }
+    }
+}
+
+impl From<Error> for durian::error::Error {
+    fn from(error: Error) -> Self {
+        durian::error::Error::ProviderFailure {
+            msg: error.to_string(),
+        }
+    }
+}
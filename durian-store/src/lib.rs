@@ -0,0 +1,13 @@
+//! Durable `Provider` on top of [sled](https://github.com/spacejam/sled), an embedded key-value store.
+//!
+//! The state is versioned by block: every change is written at the number of the block
+//! it's made in, and reading at a block returns the latest version at or before it.
+//! This keeps the state of the older blocks, e.g. for `eth_call` at an older block.
+//! The state root of each block is kept too, with the nodes of the state trie.
+
+pub mod error;
+pub mod provider;
+pub mod store;
+
+pub use crate::provider::StoreProvider;
+pub use crate::store::Store;
@@ -0,0 +1,146 @@
+use crate::error::Error as StoreError;
+use crate::store::Store;
+use durian::address::Address;
+use durian::error::Error;
+use durian::provider::{BlockContext, Provider, StateAccount, StateChangeSet};
+use primitive_types::{H256, U256};
+
+/// Provider of the state of a `Store` at a block
+///
+/// The provider returned by `Store::provider` writes the changes at its block, atomically
+/// for each executed transaction. The one returned by `Store::provider_at` is read-only.
+pub struct StoreProvider {
+    store: Store,
+    block: BlockContext,
+    read_only: bool,
+}
+
+impl StoreProvider {
+    /// Provider of the state at the `block`, see `Store::provider` and `Store::provider_at`
+    pub fn new(store: Store, block: BlockContext, read_only: bool) -> Self {
+        StoreProvider {
+            store,
+            block,
+            read_only,
+        }
+    }
+
+    pub fn block(&self) -> &BlockContext {
+        &self.block
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Inserts the account with its code at the block, e.g. for the genesis
+    pub fn insert_account(&mut self, address: &Address, nonce: U256, balance: U256, code: Vec<u8>) -> Result<(), StoreError> {
+        if self.read_only {
+            return Err(StoreError::ReadOnly {
+                number: self.block.number,
+            });
+        }
+        self.store
+            .insert_account(address, nonce, balance, code, self.block.number)
+    }
+}
+
+impl Provider for StoreProvider {
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        Ok(self.account(address)?.is_some())
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        Ok(self.store.account(address, self.block.number)?)
+    }
+
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            updated: vec![(*address, *bal, *nonce)],
+            ..Default::default()
+        })
+    }
+
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            created: vec![(*address, code.clone())],
+            ..Default::default()
+        })
+    }
+
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        Ok(self.store.storage(address, key, self.block.number)?)
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            storage: vec![(*address, *key, *value)],
+            ..Default::default()
+        })
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.store.code(code_hash)?)
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.block.timestamp
+    }
+
+    fn block_number(&self) -> u64 {
+        self.block.number
+    }
+
+    /// Returns zero for the blocks without a hash set by `Store::set_block_hash`
+    fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        Ok(self.store.block_hash(block_no)?.unwrap_or_default())
+    }
+
+    fn block_author(&self) -> Result<Address, Error> {
+        Ok(self.block.author)
+    }
+
+    fn difficulty(&self) -> Result<U256, Error> {
+        Ok(self.block.difficulty)
+    }
+
+    fn gas_limit(&self) -> Result<U256, Error> {
+        Ok(self.block.gas_limit)
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.block.chain_id
+    }
+
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        Ok(self.block.base_fee)
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(self.block.prev_randao)
+    }
+
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        Ok(self.block.clone())
+    }
+
+    /// Applies the changes of a transaction atomically at the block.
+    /// The changes made on a read-only provider are discarded.
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.store.apply_changes(changes, self.block.number)
+    }
+
+    fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.store.prepared_code(code_hash, version)?)
+    }
+
+    fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            prepared_codes: vec![(*code_hash, *version, code.clone())],
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,426 @@
+use crate::error::Error;
+use crate::provider::StoreProvider;
+use durian::address::Address;
+use durian::provider::{self, BlockContext, StateAccount, StateChangeSet, EMPTY_CODE_HASH};
+use durian::state_trie::{AccountProof, StateTrie, TrieAccount};
+use durian::trie::{TrieNodes, EMPTY_ROOT};
+use primitive_types::{H256, U256};
+use sled::{Batch, Db, IVec};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+// The keys start with the kind of the entry. The versioned entries end with the
+// big-endian number of the block, so their versions are sorted by the block.
+/// Address and block number, to the nonce, the balance and the code hash. Empty once deleted.
+const ACCOUNT: u8 = b'a';
+/// Address, storage key and block number, to the value. Empty once cleared.
+const STORAGE: u8 = b's';
+/// Code hash, to the code
+const CODE: u8 = b'c';
+/// Code hash and instrumentation version, to the prepared code
+const PREPARED_CODE: u8 = b'p';
+/// Block number, to the block context in JSON
+const BLOCK: u8 = b'b';
+/// Block number, to the block hash
+const BLOCK_HASH: u8 = b'h';
+/// Block number, to the state root at the end of the block
+const STATE_ROOT: u8 = b'r';
+/// Node hash, to the node of the state trie
+const TRIE_NODE: u8 = b'n';
+/// Number of the head block
+const HEAD: &[u8] = b"head";
+
+fn key(kind: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let mut key = vec![kind];
+    for part in parts {
+        key.extend_from_slice(part);
+    }
+    key
+}
+
+fn versioned(mut prefix: Vec<u8>, number: u64) -> Vec<u8> {
+    prefix.extend_from_slice(&number.to_be_bytes());
+    prefix
+}
+
+fn u256_bytes(value: &U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn decode_number(bytes: &[u8]) -> Result<u64, Error> {
+    if bytes.len() != 8 {
+        return Err(Error::Corrupted {
+            msg: format!("invalid block number of {} bytes", bytes.len()),
+        });
+    }
+    let mut number = [0u8; 8];
+    number.copy_from_slice(bytes);
+    Ok(u64::from_be_bytes(number))
+}
+
+fn encode_account(account: &StateAccount) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(96);
+    bytes.extend_from_slice(&u256_bytes(&account.nonce));
+    bytes.extend_from_slice(&u256_bytes(&account.balance));
+    bytes.extend_from_slice(account.code_hash.as_bytes());
+    bytes
+}
+
+fn decode_account(bytes: &[u8]) -> Result<Option<StateAccount>, Error> {
+    match bytes.len() {
+        0 => Ok(None),
+        96 => Ok(Some(StateAccount {
+            nonce: U256::from_big_endian(&bytes[..32]),
+            balance: U256::from_big_endian(&bytes[32..64]),
+            code_hash: H256::from_slice(&bytes[64..]),
+        })),
+        len => Err(Error::Corrupted {
+            msg: format!("invalid account of {} bytes", len),
+        }),
+    }
+}
+
+fn decode_hash(bytes: &[u8], what: &str) -> Result<H256, Error> {
+    if bytes.len() != 32 {
+        return Err(Error::Corrupted {
+            msg: format!("invalid {} of {} bytes", what, bytes.len()),
+        });
+    }
+    Ok(H256::from_slice(bytes))
+}
+
+fn decode_storage(bytes: &[u8]) -> Result<Option<H256>, Error> {
+    match bytes.len() {
+        0 => Ok(None),
+        32 => Ok(Some(H256::from_slice(bytes))),
+        len => Err(Error::Corrupted {
+            msg: format!("invalid storage value of {} bytes", len),
+        }),
+    }
+}
+
+/// Persistent state of the blocks, see `Store::provider` and `Store::provider_at`
+///
+/// Cloning the store is cheap, the clones share the same database.
+#[derive(Clone)]
+pub struct Store {
+    db: Db,
+    /// Held by the writers from reading the state root to writing their batch,
+    /// so the writes of the clones can't be based on the same root
+    writer: Arc<Mutex<()>>,
+}
+
+impl Store {
+    /// Opens the store in the `path` directory, creating it if it doesn't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Store {
+            db: sled::open(path)?,
+            writer: Arc::new(Mutex::new(())),
+        })
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, ()> {
+        // the lock guards no data, a writer that panicked left nothing to recover
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Number of the latest block opened by `provider`, if any
+    pub fn head(&self) -> Result<Option<u64>, Error> {
+        match self.db.get(HEAD)? {
+            Some(bytes) => Ok(Some(decode_number(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn block(&self, number: u64) -> Result<Option<BlockContext>, Error> {
+        match self.db.get(key(BLOCK, &[&number.to_be_bytes()]))? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|err| Error::Corrupted { msg: err.to_string() }),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_block_hash(&self, number: u64, hash: &H256) -> Result<(), Error> {
+        self.db
+            .insert(key(BLOCK_HASH, &[&number.to_be_bytes()]), hash.as_bytes())?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    pub fn block_hash(&self, number: u64) -> Result<Option<H256>, Error> {
+        match self.db.get(key(BLOCK_HASH, &[&number.to_be_bytes()]))? {
+            Some(bytes) => Ok(Some(decode_hash(&bytes, "block hash")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// State root at the end of the block `number`
+    pub fn state_root(&self, number: u64) -> Result<H256, Error> {
+        self.block(number)?.ok_or(Error::UnknownBlock { number })?;
+        self.root_at(number)
+    }
+
+    /// Merkle proof of the account and its storage at the keys, against the state root
+    /// at the end of the block `number`
+    pub fn get_proof(&self, address: &Address, storage_keys: &[H256], number: u64) -> Result<AccountProof, Error> {
+        let root = self.state_root(number)?;
+        let (proof, _) = self.update_trie(root, |trie| trie.get_proof(address, storage_keys))?;
+        Ok(proof)
+    }
+
+    fn root_at(&self, number: u64) -> Result<H256, Error> {
+        match self.latest(key(STATE_ROOT, &[]), number)? {
+            Some(bytes) => decode_hash(&bytes, "state root"),
+            None => Ok(EMPTY_ROOT),
+        }
+    }
+
+    /// Runs `update` on the state trie at `root`, loading the nodes it reads from the database.
+    /// Returns its result and the nodes it added to the trie.
+    fn update_trie<T, F>(&self, root: H256, update: F) -> Result<(T, TrieNodes), Error>
+    where
+        F: Fn(&mut StateTrie) -> Result<T, durian::error::Error>,
+    {
+        let mut loaded = TrieNodes::new();
+        loop {
+            let mut trie = StateTrie::from_nodes(loaded.clone(), root);
+            match update(&mut trie) {
+                Ok(result) => {
+                    let added = trie
+                        .nodes()
+                        .iter()
+                        .filter(|(hash, _)| !loaded.contains_key(*hash))
+                        .map(|(hash, node)| (*hash, node.clone()))
+                        .collect();
+                    return Ok((result, added));
+                }
+                Err(durian::error::Error::MissingTrieNode { hash }) => {
+                    let node = self
+                        .db
+                        .get(key(TRIE_NODE, &[hash.as_bytes()]))?
+                        .ok_or_else(|| Error::Corrupted {
+                            msg: format!("missing trie node {:?}", hash),
+                        })?;
+                    loaded.insert(hash, node.to_vec());
+                }
+                Err(err) => return Err(Error::Corrupted { msg: err.to_string() }),
+            }
+        }
+    }
+
+    /// Updates the state trie at the block `number` and writes its new nodes and root to the batch
+    fn commit_trie<F>(&self, batch: &mut Batch, number: u64, update: F) -> Result<(), Error>
+    where
+        F: Fn(&mut StateTrie) -> Result<H256, durian::error::Error>,
+    {
+        let (root, nodes) = self.update_trie(self.root_at(number)?, update)?;
+        for (hash, node) in nodes {
+            batch.insert(key(TRIE_NODE, &[hash.as_bytes()]), node);
+        }
+        batch.insert(versioned(key(STATE_ROOT, &[]), number), root.as_bytes());
+        Ok(())
+    }
+
+    /// Opens the `block` as the head block and returns a provider executing the transactions in it.
+    /// The block can't be older than the current head block.
+    pub fn provider(&self, block: BlockContext) -> Result<StoreProvider, Error> {
+        self.check_head(block.number)?;
+        let json = serde_json::to_vec(&block).map_err(|err| Error::Corrupted { msg: err.to_string() })?;
+        let mut batch = Batch::default();
+        batch.insert(key(BLOCK, &[&block.number.to_be_bytes()]), json);
+        batch.insert(HEAD, &block.number.to_be_bytes());
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(StoreProvider::new(self.clone(), block, false))
+    }
+
+    /// Returns a read-only provider of the state at the end of the block `number`.
+    /// The changes made by the transactions executed with it are discarded, as for `eth_call`.
+    pub fn provider_at(&self, number: u64) -> Result<StoreProvider, Error> {
+        let block = self.block(number)?.ok_or(Error::UnknownBlock { number })?;
+        Ok(StoreProvider::new(self.clone(), block, true))
+    }
+
+    fn check_head(&self, number: u64) -> Result<(), Error> {
+        match self.head()? {
+            Some(head) if number < head => Err(Error::OldBlock { number, head }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Latest version of the versioned entry at or before the block `number`
+    fn latest(&self, prefix: Vec<u8>, number: u64) -> Result<Option<IVec>, Error> {
+        let end = versioned(prefix.clone(), number);
+        match self.db.range(prefix..=end).next_back() {
+            Some(entry) => Ok(Some(entry?.1)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn account(&self, address: &Address, number: u64) -> Result<Option<StateAccount>, Error> {
+        match self.latest(key(ACCOUNT, &[address.as_bytes()]), number)? {
+            Some(bytes) => decode_account(&bytes),
+            None => Ok(None),
+        }
+    }
+
+    pub fn storage(&self, address: &Address, storage_key: &H256, number: u64) -> Result<Option<H256>, Error> {
+        match self.latest(key(STORAGE, &[address.as_bytes(), storage_key.as_bytes()]), number)? {
+            Some(bytes) => decode_storage(&bytes),
+            None => Ok(None),
+        }
+    }
+
+    /// Keys of the storage of the account with a value at the block `number`
+    fn storage_keys(&self, address: &Address, number: u64) -> Result<BTreeSet<H256>, Error> {
+        let mut keys = BTreeSet::new();
+        for entry in self.db.scan_prefix(key(STORAGE, &[address.as_bytes()])) {
+            let (entry_key, value) = entry?;
+            if entry_key.len() != 61 {
+                return Err(Error::Corrupted {
+                    msg: format!("invalid storage key of {} bytes", entry_key.len()),
+                });
+            }
+            if decode_number(&entry_key[53..])? > number {
+                continue;
+            }
+            // the versions are sorted, the last one at or before the block is kept
+            let storage_key = H256::from_slice(&entry_key[21..53]);
+            if value.is_empty() {
+                keys.remove(&storage_key);
+            } else {
+                keys.insert(storage_key);
+            }
+        }
+        Ok(keys)
+    }
+
+    pub fn code(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        if *code_hash == EMPTY_CODE_HASH {
+            return Ok(Some(vec![]));
+        }
+        Ok(self
+            .db
+            .get(key(CODE, &[code_hash.as_bytes()]))?
+            .map(|code| code.to_vec()))
+    }
+
+    pub fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self
+            .db
+            .get(key(PREPARED_CODE, &[code_hash.as_bytes(), &u256_bytes(version)]))?
+            .map(|code| code.to_vec()))
+    }
+
+    /// Inserts the account at the block `number`, replacing the account at `address`
+    /// and keeping its storage
+    pub fn insert_account(&self, address: &Address, nonce: U256, balance: U256, code: Vec<u8>, number: u64) -> Result<(), Error> {
+        self.check_head(number)?;
+        let _writer = self.lock_writer();
+        let code_hash = provider::code_hash(&code);
+        let account = StateAccount {
+            nonce,
+            balance,
+            code_hash,
+        };
+        let mut batch = Batch::default();
+        if !code.is_empty() {
+            batch.insert(key(CODE, &[code_hash.as_bytes()]), code);
+        }
+        batch.insert(versioned(key(ACCOUNT, &[address.as_bytes()]), number), encode_account(&account));
+        self.commit_trie(&mut batch, number, |trie| {
+            let storage_root = match trie.account(address)? {
+                Some(account) => account.storage_root,
+                None => EMPTY_ROOT,
+            };
+            trie.insert_account(
+                address,
+                &TrieAccount {
+                    nonce,
+                    balance,
+                    storage_root,
+                    code_hash,
+                },
+            )
+        })?;
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Applies the changes at the block `number` in one batch: if one of them can't be applied,
+    /// e.g. updating an account that doesn't exist, none of them is
+    pub fn apply_changes(&self, changes: StateChangeSet, number: u64) -> Result<(), durian::error::Error> {
+        self.check_head(number)?;
+        let _writer = self.lock_writer();
+
+        let mut batch = Batch::default();
+        let mut accounts: BTreeMap<Address, StateAccount> = BTreeMap::new();
+        for (address, code) in &changes.created {
+            let code_hash = provider::code_hash(code);
+            if !code.is_empty() {
+                batch.insert(key(CODE, &[code_hash.as_bytes()]), code.clone());
+            }
+            // the balance sent to the address before the contract was created is kept
+            let mut account = self.account(address, number)?.unwrap_or(StateAccount {
+                nonce: U256::zero(),
+                balance: U256::zero(),
+                code_hash: EMPTY_CODE_HASH,
+            });
+            account.code_hash = code_hash;
+            accounts.insert(*address, account);
+        }
+        for (address, balance, nonce) in &changes.updated {
+            let account = match accounts.remove(address) {
+                Some(account) => account,
+                None => self
+                    .account(address, number)?
+                    .ok_or(durian::error::Error::InvalidAddress { address: *address })?,
+            };
+            accounts.insert(
+                *address,
+                StateAccount {
+                    nonce: *nonce,
+                    balance: *balance,
+                    code_hash: account.code_hash,
+                },
+            );
+        }
+        for (address, account) in &accounts {
+            batch.insert(versioned(key(ACCOUNT, &[address.as_bytes()]), number), encode_account(account));
+        }
+        for (address, storage_key, value) in &changes.storage {
+            let value: &[u8] = if value.is_zero() { &[] } else { value.as_bytes() };
+            batch.insert(
+                versioned(key(STORAGE, &[address.as_bytes(), storage_key.as_bytes()]), number),
+                value,
+            );
+        }
+        self.commit_trie(&mut batch, number, |trie| trie.apply_changes(&changes))?;
+        for (code_hash, version, code) in changes.prepared_codes {
+            batch.insert(key(PREPARED_CODE, &[code_hash.as_bytes(), &u256_bytes(&version)]), code);
+        }
+        for address in &changes.deleted {
+            batch.insert(versioned(key(ACCOUNT, &[address.as_bytes()]), number), &[]);
+            let written = changes
+                .storage
+                .iter()
+                .filter(|(written, _, _)| written == address)
+                .map(|(_, storage_key, _)| *storage_key);
+            for storage_key in self.storage_keys(address, number)?.into_iter().chain(written) {
+                batch.insert(
+                    versioned(key(STORAGE, &[address.as_bytes(), storage_key.as_bytes()]), number),
+                    &[],
+                );
+            }
+        }
+
+        self.db.apply_batch(batch).map_err(Error::from)?;
+        self.db.flush().map_err(Error::from)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute;
+use durian::provider::{self, Provider, StateChangeSet};
+use durian::state_trie::{self, StateTrie, TrieAccount};
+use durian::transaction::Transaction;
+use durian_store::error::Error as StoreError;
+use durian_store::Store;
+use common::{block, call, contract, counter, sender, COUNTER, SUICIDE};
+use primitive_types::{H256, U256};
+
+fn genesis(store: &Store) {
+    let mut provider = store.provider(block(0)).unwrap();
+    let code = wat::parse_str(COUNTER).unwrap();
+    provider
+        .insert_account(&sender(), U256::zero(), U256::from(1000), vec![])
+        .unwrap();
+    provider
+        .insert_account(&contract(), U256::zero(), U256::zero(), code)
+        .unwrap();
+}
+
+/// State trie of the `genesis` accounts
+fn genesis_trie() -> StateTrie {
+    let mut trie = StateTrie::new();
+    let sender_account = TrieAccount {
+        balance: U256::from(1000),
+        ..Default::default()
+    };
+    trie.insert_account(&sender(), &sender_account).unwrap();
+    let contract_account = TrieAccount {
+        code_hash: provider::code_hash(&wat::parse_str(COUNTER).unwrap()),
+        ..Default::default()
+    };
+    trie.insert_account(&contract(), &contract_account).unwrap();
+    trie
+}
+
+#[test]
+fn survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let store = Store::open(dir.path()).unwrap();
+        genesis(&store);
+        let mut provider = store.provider(block(1)).unwrap();
+        assert_eq!(call(&mut provider), 1);
+        assert_eq!(counter(&provider), 1);
+    }
+
+    let store = Store::open(dir.path()).unwrap();
+    assert_eq!(store.head().unwrap(), Some(1));
+    assert_eq!(store.block(1).unwrap(), Some(block(1)));
+    let mut provider = store.provider(block(2)).unwrap();
+    assert_eq!(counter(&provider), 1);
+    assert_eq!(provider.account(&sender()).unwrap().unwrap().balance, U256::from(1000));
+    assert_eq!(call(&mut provider), 2);
+    assert_eq!(counter(&provider), 2);
+}
+
+#[test]
+fn calls_at_older_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    for number in 1..4 {
+        let mut provider = store.provider(block(number)).unwrap();
+        call(&mut provider);
+    }
+
+    let mut provider = store.provider_at(1).unwrap();
+    assert!(provider.is_read_only());
+    assert_eq!(counter(&provider), 1);
+    assert_eq!(call(&mut provider), 1);
+
+    // the changes made at an older block are discarded
+    assert_eq!(counter(&store.provider_at(1).unwrap()), 1);
+    assert_eq!(counter(&store.provider_at(2).unwrap()), 2);
+    assert_eq!(counter(&store.provider_at(3).unwrap()), 3);
+    assert_eq!(counter(&store.provider_at(0).unwrap()), 0);
+    match store.provider_at(4) {
+        Err(StoreError::UnknownBlock { number }) => assert_eq!(number, 4),
+        _ => panic!("unexpected provider at an unknown block"),
+    }
+}
+
+#[test]
+fn rejects_older_block() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    let mut provider = store.provider(block(1)).unwrap();
+    store.provider(block(2)).unwrap();
+
+    match store.provider(block(1)) {
+        Err(StoreError::OldBlock { number, head }) => assert_eq!((number, head), (1, 2)),
+        _ => panic!("unexpected provider at an older block"),
+    }
+    match provider.set_storage(&contract(), &H256::repeat_byte(1), &H256::repeat_byte(2)) {
+        Err(Error::ProviderFailure { msg }) => assert_eq!(msg, "Block 1 is older than the head block 2"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn applies_changes_atomically() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    common::applies_changes_atomically(&mut store.provider(block(1)).unwrap());
+}
+
+#[test]
+fn deletes_account_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    call(&mut store.provider(block(1)).unwrap());
+
+    let mut provider = store.provider(block(2)).unwrap();
+    let changes = StateChangeSet {
+        storage: vec![(contract(), H256::repeat_byte(8), H256::repeat_byte(1))],
+        deleted: vec![contract()],
+        ..Default::default()
+    };
+    provider.apply_changes(changes).unwrap();
+    assert!(!provider.exist(&contract()).unwrap());
+    assert_eq!(counter(&provider), 0);
+    assert_eq!(provider.storage_at(&contract(), &H256::repeat_byte(8)).unwrap(), None);
+
+    let provider = store.provider_at(1).unwrap();
+    assert!(provider.exist(&contract()).unwrap());
+    assert_eq!(counter(&provider), 1);
+}
+
+#[test]
+fn deletes_account_by_suicide() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    let doomed = Address::repeat_byte(0x33);
+    let refund = Address::repeat_byte(0x44);
+    let mut provider = store.provider(block(1)).unwrap();
+    provider
+        .insert_account(&doomed, U256::zero(), U256::from(100), wat::parse_str(SUICIDE).unwrap())
+        .unwrap();
+    provider.set_storage(&doomed, &H256::repeat_byte(1), &H256::repeat_byte(1)).unwrap();
+
+    let mut provider = store.provider(block(2)).unwrap();
+    let tx = Transaction::make_call(sender(), doomed, U256::zero(), U256::from(1_000_000), U256::zero(), refund.as_bytes().to_vec());
+    let result = execute::execute(&mut provider, &tx).unwrap();
+    assert!(result.outcome.is_success(), "{}", result.outcome);
+    assert!(!provider.exist(&doomed).unwrap());
+    assert_eq!(provider.storage_at(&doomed, &H256::repeat_byte(1)).unwrap(), None);
+    assert_eq!(provider.account(&refund).unwrap().unwrap().balance, U256::from(100));
+
+    let provider = store.provider_at(1).unwrap();
+    assert!(provider.exist(&doomed).unwrap());
+}
+
+#[test]
+fn computes_state_root() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+
+    let mut trie = genesis_trie();
+    let genesis_root = trie.root();
+    assert_eq!(store.state_root(0).unwrap(), genesis_root);
+
+    let mut provider = store.provider(block(1)).unwrap();
+    call(&mut provider);
+    let value = provider.storage_at(&contract(), &H256::repeat_byte(7)).unwrap().unwrap();
+    trie.set_storage(&contract(), &H256::repeat_byte(7), &value).unwrap();
+    assert_eq!(store.state_root(1).unwrap(), trie.root());
+
+    // the state of the older block is still proven against its root
+    let proof = store.get_proof(&contract(), &[H256::repeat_byte(7)], 1).unwrap();
+    state_trie::verify_proof(&trie.root(), &proof).unwrap();
+    assert_eq!(proof.storage[0].value, value);
+    let proof = store.get_proof(&contract(), &[H256::repeat_byte(7)], 0).unwrap();
+    state_trie::verify_proof(&genesis_root, &proof).unwrap();
+    assert_eq!(proof.storage[0].value, H256::zero());
+
+    match store.state_root(2) {
+        Err(StoreError::UnknownBlock { number }) => assert_eq!(number, 2),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn rejects_corrupted_block_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let db = sled::open(dir.path()).unwrap();
+        let mut key = vec![b'h'];
+        key.extend_from_slice(&1u64.to_be_bytes());
+        db.insert(key, &[1, 2, 3]).unwrap();
+        db.flush().unwrap();
+    }
+
+    let store = Store::open(dir.path()).unwrap();
+    match store.block_hash(1) {
+        Err(StoreError::Corrupted { msg }) => assert_eq!(msg, "invalid block hash of 3 bytes"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn serializes_concurrent_writers() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = Store::open(dir.path()).unwrap();
+    genesis(&store);
+    store.provider(block(1)).unwrap();
+
+    let writers: Vec<_> = (1..3u8)
+        .map(|writer| {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                for key in 0..20u8 {
+                    let changes = StateChangeSet {
+                        storage: vec![(contract(), H256::repeat_byte(key), H256::repeat_byte(writer))],
+                        ..Default::default()
+                    };
+                    store.apply_changes(changes, 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    // the root has the writes of both writers, whichever was the last to write each key
+    let mut trie = genesis_trie();
+    for key in 0..20u8 {
+        let value = store.storage(&contract(), &H256::repeat_byte(key), 1).unwrap().unwrap();
+        trie.set_storage(&contract(), &H256::repeat_byte(key), &value).unwrap();
+    }
+    assert_eq!(store.state_root(1).unwrap(), trie.root());
+}
@@ -0,0 +1,118 @@
+use outcome::ExecutionOutcome;
+use primitive_types::U256;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Location of an instruction in the contract code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+	/// Index of the function in the module function space, imported functions included
+	pub function: u32,
+	/// Index of the instruction in the function body
+	pub offset: u32,
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "func[{}]+{}", self.function, self.offset)
+	}
+}
+
+/// Value of a local
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+	I32(i32),
+	I64(i64),
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Value::I32(value) => write!(f, "i32:{}", value),
+			Value::I64(value) => write!(f, "i64:{}", value),
+		}
+	}
+}
+
+/// Where the execution should stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+	/// Before the first instruction of the function
+	Function(u32),
+	/// Before the instruction
+	Instruction(Location),
+}
+
+impl Breakpoint {
+	/// Returns true if the execution should stop before the instruction at `location`
+	pub fn matches(&self, location: &Location) -> bool {
+		match *self {
+			Breakpoint::Function(function) => location.function == function && location.offset == 0,
+			Breakpoint::Instruction(ref breakpoint) => breakpoint == location,
+		}
+	}
+}
+
+/// How the execution continues after a stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+	/// Stop before the next instruction
+	Step,
+	/// Run until the next breakpoint
+	Continue,
+}
+
+/// State of the contract when the execution stopped.
+///
+/// The value stack isn't available, wasmi doesn't expose it to the host.
+#[derive(Debug)]
+pub struct DebugState<'a> {
+	/// Location of the instruction the execution stopped before
+	pub location: &'a Location,
+	/// Call stack, the outermost frame first.
+	/// The location of a caller frame is its call instruction.
+	pub call_stack: &'a [Location],
+	/// Locals of the current function, parameters first
+	pub locals: &'a [Value],
+	pub gas_left: U256,
+	/// Linear memory of the contract
+	pub memory: &'a [u8],
+	/// Function names from the `name` section of the contract
+	pub function_names: &'a HashMap<u32, String>,
+	/// Instructions of the contract functions
+	pub code: &'a HashMap<u32, Vec<String>>,
+}
+
+impl<'a> DebugState<'a> {
+	/// Name of the function, if the contract has a `name` section
+	pub fn function_name(&self, function: u32) -> Option<&str> {
+		self.function_names.get(&function).map(String::as_str)
+	}
+
+	/// Instruction at the location
+	pub fn instruction(&self, location: &Location) -> Option<&str> {
+		self.code
+			.get(&location.function)
+			.and_then(|code| code.get(location.offset as usize))
+			.map(String::as_str)
+	}
+}
+
+/// Controls the execution of a contract in the debug mode, see `execute::execute_with_debugger`.
+pub trait Debugger {
+	/// The execution is about to start
+	fn start(&mut self) -> Resume {
+		Resume::Continue
+	}
+
+	/// Breakpoints the execution should stop at, checked before every instruction
+	fn breakpoints(&self) -> &[Breakpoint];
+
+	/// The execution stopped before an instruction
+	fn stop(&mut self, state: &DebugState) -> Resume;
+
+	/// The execution finished. `state` is at the last instruction executed,
+	/// e.g. the trapping one, and has no locals.
+	/// It is `None` if no instruction was executed.
+	fn finish(&mut self, _outcome: &ExecutionOutcome, _state: Option<&DebugState>) {}
+}
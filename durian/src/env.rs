@@ -1,8 +1,9 @@
+use schedule::Schedule;
 use std::cell::RefCell;
 use wasm_cost::WasmCosts;
 use wasmi::{
 	self, memory_units, Error, FuncInstance, FuncRef, MemoryDescriptor, MemoryInstance, MemoryRef,
-	Signature,
+	Signature, ValueType,
 };
 
 /// Internal ids all functions runtime supports. This is just a glue for wasmi interpreter
@@ -32,6 +33,12 @@ pub mod ids {
 	pub const ELOG_FUNC: usize = 210;
 	pub const CREATE2_FUNC: usize = 220;
 	pub const GASLEFT_FUNC: usize = 230;
+	pub const REVERT_FUNC: usize = 240;
+	pub const RETURN_DATA_SIZE_FUNC: usize = 250;
+	pub const RETURN_DATA_COPY_FUNC: usize = 260;
+	pub const CHAIN_ID_FUNC: usize = 270;
+	pub const BASEFEE_FUNC: usize = 280;
+	pub const RANDOM_SEED_FUNC: usize = 290;
 
 	pub const PANIC_FUNC: usize = 1000;
 	pub const DEBUG_FUNC: usize = 1010;
@@ -43,6 +50,7 @@ pub mod signatures {
 	use wasmi::ValueType::*;
 	use wasmi::{self, ValueType};
 
+	#[derive(Clone, Copy)]
 	pub struct StaticSignature(pub &'static [ValueType], pub Option<ValueType>);
 
 	pub const STORAGE_READ: StaticSignature = StaticSignature(&[I32, I32], None);
@@ -51,6 +59,8 @@ pub mod signatures {
 
 	pub const RET: StaticSignature = StaticSignature(&[I32, I32], None);
 
+	pub const REVERT: StaticSignature = StaticSignature(&[I32, I32], None);
+
 	pub const GAS: StaticSignature = StaticSignature(&[I32], None);
 
 	pub const FETCH_INPUT: StaticSignature = StaticSignature(&[I32], None);
@@ -86,6 +96,16 @@ pub mod signatures {
 
 	pub const GASLEFT: StaticSignature = StaticSignature(&[], Some(I64));
 
+	pub const RETURN_DATA_SIZE: StaticSignature = StaticSignature(&[], Some(I32));
+
+	pub const RETURN_DATA_COPY: StaticSignature = StaticSignature(&[I32, I32, I32], None);
+
+	pub const CHAIN_ID: StaticSignature = StaticSignature(&[], Some(I64));
+
+	pub const BASEFEE: StaticSignature = StaticSignature(&[I32], None);
+
+	pub const RANDOM_SEED: StaticSignature = StaticSignature(&[I32], None);
+
 	pub const GASLIMIT: StaticSignature = StaticSignature(&[I32], None);
 
 	pub const TIMESTAMP: StaticSignature = StaticSignature(&[], Some(I64));
@@ -105,6 +125,50 @@ pub mod signatures {
 	}
 }
 
+/// Name, id and signature of all functions runtime supports
+const FUNCS: &[(&str, usize, signatures::StaticSignature)] = &[
+	("storage_read", ids::STORAGE_READ_FUNC, signatures::STORAGE_READ),
+	("storage_write", ids::STORAGE_WRITE_FUNC, signatures::STORAGE_WRITE),
+	("ret", ids::RET_FUNC, signatures::RET),
+	("revert", ids::REVERT_FUNC, signatures::REVERT),
+	("gas", ids::GAS_FUNC, signatures::GAS),
+	("input_length", ids::INPUT_LENGTH_FUNC, signatures::INPUT_LENGTH),
+	("fetch_input", ids::FETCH_INPUT_FUNC, signatures::FETCH_INPUT),
+	("panic", ids::PANIC_FUNC, signatures::PANIC),
+	("debug", ids::DEBUG_FUNC, signatures::DEBUG),
+	("ccall", ids::CCALL_FUNC, signatures::CCALL),
+	("dcall", ids::DCALL_FUNC, signatures::DCALL),
+	("scall", ids::SCALL_FUNC, signatures::SCALL),
+	("value", ids::VALUE_FUNC, signatures::VALUE),
+	("create", ids::CREATE_FUNC, signatures::CREATE),
+	("suicide", ids::SUICIDE_FUNC, signatures::SUICIDE),
+	("block_hash", ids::BLOCK_HASH_FUNC, signatures::BLOCK_HASH),
+	("blocknumber", ids::BLOCK_NUMBER_FUNC, signatures::BLOCK_NUMBER),
+	("coinbase", ids::BLOCK_AUTHOR_FUNC, signatures::BLOCK_AUTHOR),
+	("difficulty", ids::DIFFICULTY_FUNC, signatures::DIFFICULTY),
+	("gaslimit", ids::GASLIMIT_FUNC, signatures::GASLIMIT),
+	("timestamp", ids::TIMESTAMP_FUNC, signatures::TIMESTAMP),
+	("address", ids::ADDRESS_FUNC, signatures::ADDRESS),
+	("sender", ids::SENDER_FUNC, signatures::SENDER),
+	("origin", ids::ORIGIN_FUNC, signatures::ORIGIN),
+	("elog", ids::ELOG_FUNC, signatures::ELOG),
+	("create2", ids::CREATE2_FUNC, signatures::CREATE2),
+	("gasleft", ids::GASLEFT_FUNC, signatures::GASLEFT),
+	("return_data_size", ids::RETURN_DATA_SIZE_FUNC, signatures::RETURN_DATA_SIZE),
+	("return_data_copy", ids::RETURN_DATA_COPY_FUNC, signatures::RETURN_DATA_COPY),
+	("chain_id", ids::CHAIN_ID_FUNC, signatures::CHAIN_ID),
+	("basefee", ids::BASEFEE_FUNC, signatures::BASEFEE),
+	("random_seed", ids::RANDOM_SEED_FUNC, signatures::RANDOM_SEED),
+];
+
+/// Name of the runtime function with the given id
+pub fn func_name(id: usize) -> Option<&'static str> {
+	FUNCS
+		.iter()
+		.find(|(_, func_id, _)| *func_id == id)
+		.map(|(name, _, _)| *name)
+}
+
 fn host(signature: signatures::StaticSignature, idx: usize) -> FuncRef {
 	FuncInstance::alloc_host(signature.into(), idx)
 }
@@ -117,19 +181,21 @@ pub struct ImportResolver {
 	max_memory: u32,
 	memory: RefCell<Option<MemoryRef>>,
 
-	have_create2: bool,
 	have_gasleft: bool,
+	have_return_data: bool,
+	have_chain_id: bool,
 }
 
 impl ImportResolver {
 	/// New import resolver with specifed maximum amount of inital memory (in wasm pages = 64kb)
-	pub fn with_limit(max_memory: u32, schedule: &WasmCosts) -> ImportResolver {
+	pub fn with_limit(max_memory: u32, schedule: &Schedule, wasm_costs: &WasmCosts) -> ImportResolver {
 		ImportResolver {
 			max_memory: max_memory,
 			memory: RefCell::new(None),
 
-			have_create2: schedule.have_create2,
-			have_gasleft: schedule.have_gasleft,
+			have_gasleft: wasm_costs.have_gasleft,
+			have_return_data: schedule.have_return_data,
+			have_chain_id: schedule.have_chain_id,
 		}
 	}
 
@@ -158,46 +224,49 @@ impl ImportResolver {
 	pub fn memory_size(&self) -> Result<u32, Error> {
 		Ok(self.memory_ref().current_size().0 as u32)
 	}
+
+	/// Checks whether the runtime provides function `field_name` with the given signature
+	pub fn has_func(&self, field_name: &str, params: &[ValueType], return_type: Option<ValueType>) -> bool {
+		match self.func_entry(field_name) {
+			Some((signature, _)) => signature.0 == params && signature.1 == return_type,
+			None => false,
+		}
+	}
+
+	/// Maximum amount of memory (in wasm pages = 64kb) a module can request
+	pub fn max_memory(&self) -> u32 {
+		self.max_memory
+	}
+
+	fn func_entry(&self, field_name: &str) -> Option<(signatures::StaticSignature, usize)> {
+		let enabled = match field_name {
+			// not implemented by `Runtime` yet
+			"dcall" | "scall" | "create" | "create2" => false,
+			"gasleft" => self.have_gasleft,
+			"return_data_size" | "return_data_copy" => self.have_return_data,
+			"chain_id" => self.have_chain_id,
+			_ => true,
+		};
+		if !enabled {
+			return None;
+		}
+
+		FUNCS
+			.iter()
+			.find(|(name, _, _)| *name == field_name)
+			.map(|(_, id, signature)| (*signature, *id))
+	}
 }
 
 impl wasmi::ModuleImportResolver for ImportResolver {
 	fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, Error> {
-		let func_ref = match field_name {
-			"storage_read" => host(signatures::STORAGE_READ, ids::STORAGE_READ_FUNC),
-			"storage_write" => host(signatures::STORAGE_WRITE, ids::STORAGE_WRITE_FUNC),
-			"ret" => host(signatures::RET, ids::RET_FUNC),
-			"gas" => host(signatures::GAS, ids::GAS_FUNC),
-			"input_length" => host(signatures::INPUT_LENGTH, ids::INPUT_LENGTH_FUNC),
-			"fetch_input" => host(signatures::FETCH_INPUT, ids::FETCH_INPUT_FUNC),
-			"panic" => host(signatures::PANIC, ids::PANIC_FUNC),
-			"debug" => host(signatures::DEBUG, ids::DEBUG_FUNC),
-			"ccall" => host(signatures::CCALL, ids::CCALL_FUNC),
-			"dcall" => host(signatures::DCALL, ids::DCALL_FUNC),
-			"scall" => host(signatures::SCALL, ids::SCALL_FUNC),
-			"value" => host(signatures::VALUE, ids::VALUE_FUNC),
-			"create" => host(signatures::CREATE, ids::CREATE_FUNC),
-			"suicide" => host(signatures::SUICIDE, ids::SUICIDE_FUNC),
-			"block_hash" => host(signatures::BLOCK_HASH, ids::BLOCK_HASH_FUNC),
-			"blocknumber" => host(signatures::BLOCK_NUMBER, ids::BLOCK_NUMBER_FUNC),
-			"coinbase" => host(signatures::BLOCK_AUTHOR, ids::BLOCK_AUTHOR_FUNC),
-			"difficulty" => host(signatures::DIFFICULTY, ids::DIFFICULTY_FUNC),
-			"gaslimit" => host(signatures::GASLIMIT, ids::GASLIMIT_FUNC),
-			"timestamp" => host(signatures::TIMESTAMP, ids::TIMESTAMP_FUNC),
-			"address" => host(signatures::ADDRESS, ids::ADDRESS_FUNC),
-			"sender" => host(signatures::SENDER, ids::SENDER_FUNC),
-			"origin" => host(signatures::ORIGIN, ids::ORIGIN_FUNC),
-			"elog" => host(signatures::ELOG, ids::ELOG_FUNC),
-			"create2" if self.have_create2 => host(signatures::CREATE2, ids::CREATE2_FUNC),
-			"gasleft" if self.have_gasleft => host(signatures::GASLEFT, ids::GASLEFT_FUNC),
-			_ => {
-				return Err(wasmi::Error::Instantiation(format!(
-					"Export {} not found",
-					field_name
-				)))
-			}
-		};
-
-		Ok(func_ref)
+		match self.func_entry(field_name) {
+			Some((signature, idx)) => Ok(host(signature, idx)),
+			None => Err(wasmi::Error::Instantiation(format!(
+				"Export {} not found",
+				field_name
+			))),
+		}
 	}
 
 	fn resolve_memory(
@@ -1,6 +1,7 @@
-use primitive_types::H256;
+use primitive_types::{H256, U256};
 use snafu::Snafu;
 use address::Address;
+use limits::Limit;
 use wasmi::TrapKind;
 
 #[derive(Debug, Clone, Snafu)]
@@ -26,6 +27,9 @@ pub enum Error {
     #[snafu(display("Return result"))]
     Return,
 
+    #[snafu(display("Revert result"))]
+    Revert,
+
     #[snafu(display("Suicide result"))]
     SuicideAbort,
 
@@ -76,6 +80,54 @@ pub enum Error {
 
     #[snafu(display("Invalid storage key: {}", key))]
     InvalidStorageKey { key: H256 },
+
+    #[snafu(display("Invalid code: {}", msg))]
+    InvalidCode { msg: String },
+
+    #[snafu(display("Module doesn't export {}", name))]
+    MissingExport { name: String },
+
+    #[snafu(display("Unsupported code version: {}", version))]
+    UnsupportedCodeVersion { version: U256 },
+
+    #[snafu(display("Wasm costs are not set in the schedule"))]
+    MissingWasmCosts,
+
+    #[snafu(display("Return data out of bounds: {} bytes at {} of {}", len, offset, size))]
+    ReturnDataOutOfBounds { offset: u32, len: u32, size: usize },
+
+    #[snafu(display("Execution limit exceeded: {}", limit))]
+    LimitExceeded { limit: Limit },
+
+    #[snafu(display("Provider failure: {}", msg))]
+    ProviderFailure { msg: String },
+
+    #[snafu(display("Invalid genesis: {}", msg))]
+    InvalidGenesis { msg: String },
+
+    #[snafu(display("Missing trie node: {}", hash))]
+    MissingTrieNode { hash: H256 },
+
+    #[snafu(display("Invalid trie node: {}", msg))]
+    InvalidTrieNode { msg: String },
+
+    #[snafu(display("Invalid proof: {}", msg))]
+    InvalidProof { msg: String },
+
+    #[snafu(display("Invalid witness: {}", msg))]
+    InvalidWitness { msg: String },
+}
+
+impl Error {
+    /// Wraps an error returned by the provider, which aborts the execution
+    pub fn provider_failure(err: Error) -> Self {
+        match err {
+            Error::ProviderFailure { .. } => err,
+            err => Error::ProviderFailure {
+                msg: err.to_string(),
+            },
+        }
+    }
 }
 
 impl From<wasmi::Trap> for Error {
@@ -1,27 +1,261 @@
 use address::Address;
-use env;
+use debugger::Debugger;
 use error::Error;
+use frame;
+use hooks::Observer;
+use limits::Limit;
 use log_entry::LogEntry;
-use parser;
-use primitive_types::U256;
-use provider::Provider;
-use runtime::Runtime;
+use outcome::ExecutionOutcome;
+use prefetch::{Fetch, Prefetched};
+use primitive_types::{H256, U256};
+use profiler::GasProfile;
+use provider::{AsyncProvider, Provider, ProviderFuture};
+use runtime::TransactionUsage;
 use schedule::Schedule;
 use state::State;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracer::{NoopTracer, Tracer};
 use transaction::{Action, Transaction};
 use types::{ActionParams, ActionType};
 use utils;
-use wasm_cost::WasmCosts;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ResultData {
+	/// How the execution ended
+	pub outcome: ExecutionOutcome,
+	/// Gas consumed by the execution
+	pub gas_used: U256,
 	pub gas_left: U256,
 	pub data: Vec<u8>,
 	pub contract: Address,
 	pub logs: Vec<LogEntry>,
 }
 
+/// Executes the transaction against the state provided by `provider`.
+///
+/// For `Action::Create` the constructor module is validated first. If it exports `deploy`,
+/// `deploy` is invoked once and the module itself, without the `deploy` export, is stored
+/// as the contract code. Otherwise (pwasm convention) `call` is invoked and
+/// the data it returns is stored as the contract code.
+/// For `Action::Call` the `call` export of the contract code is invoked.
+///
+/// Failures of the contract are reported by `ResultData::outcome`, the state is only
+/// updated if the execution succeeded. `Err` is returned if the transaction can't be
+/// executed at all, e.g. the provider failed.
 pub fn execute(provider: &mut dyn Provider, transaction: &Transaction) -> Result<ResultData, Error> {
+	execute_with_tracer(provider, transaction, &mut NoopTracer)
+}
+
+/// Executes the transaction like `execute`, reporting the execution events to `tracer`.
+pub fn execute_with_tracer(
+	provider: &mut dyn Provider,
+	transaction: &Transaction,
+	tracer: &mut dyn Tracer,
+) -> Result<ResultData, Error> {
+	execute_with_schedule(provider, transaction, &Schedule::new_wasm(), tracer)
+}
+
+/// Executes the transaction like `execute_with_tracer`, using the given schedule.
+///
+/// The schedule should have the wasm costs set.
+/// If the transaction can't be executed at all and `Err` is returned,
+/// the frames already entered are not exited.
+pub fn execute_with_schedule(
+	provider: &mut dyn Provider,
+	transaction: &Transaction,
+	schedule: &Schedule,
+	tracer: &mut dyn Tracer,
+) -> Result<ResultData, Error> {
+	execute_transaction(provider, transaction, schedule, tracer, None)
+}
+
+/// Executes the transaction like `execute` in the debug mode,
+/// where `debugger` can stop the execution before every instruction of the contract.
+///
+/// The code of the called contract is instrumented again instead of loading
+/// the prepared code, so the execution is much slower.
+pub fn execute_with_debugger(
+	provider: &mut dyn Provider,
+	transaction: &Transaction,
+	debugger: &mut dyn Debugger,
+) -> Result<ResultData, Error> {
+	execute_transaction(
+		provider,
+		transaction,
+		&Schedule::new_wasm(),
+		&mut NoopTracer,
+		Some(Observer::Debugger(debugger)),
+	)
+}
+
+/// Executes the transaction like `execute`, writing the gas charged per call stack to `profile`.
+///
+/// Like in the debug mode, the code of the called contract is instrumented again
+/// to track the call stack.
+pub fn execute_with_profiler(
+	provider: &mut dyn Provider,
+	transaction: &Transaction,
+	profile: &mut GasProfile,
+) -> Result<ResultData, Error> {
+	execute_transaction(
+		provider,
+		transaction,
+		&Schedule::new_wasm(),
+		&mut NoopTracer,
+		Some(Observer::Profiler(profile)),
+	)
+}
+
+/// Executes the transaction like `execute` against the state of a remote backend,
+/// without blocking the thread while the state is fetched.
+///
+/// The wasm execution can't be suspended in the middle, so the transaction is executed against
+/// the state fetched so far. If it read a part of the state that wasn't fetched yet, all the
+/// missing parts are fetched concurrently and the transaction is executed again, until
+/// an execution reads only the fetched state. Then its changes are applied to `provider`
+/// with one call of `AsyncProvider::apply_changes`, if the execution succeeded.
+/// If the state is missed more than `ExecutionLimits::max_fetch_rounds` times,
+/// the execution fails with `Error::LimitExceeded`.
+/// The accounts and the storage are fetched with one call of `AsyncProvider::accounts`
+/// and one of `AsyncProvider::storages` at a time, starting with the access list of the transaction.
+pub fn execute_async<'a>(provider: &'a mut dyn AsyncProvider, transaction: &'a Transaction) -> ExecuteAsync<'a> {
+	execute_async_with_schedule(provider, transaction, Schedule::new_wasm())
+}
+
+/// Executes the transaction like `execute_async`, using the given schedule.
+pub fn execute_async_with_schedule<'a>(
+	provider: &'a mut dyn AsyncProvider,
+	transaction: &'a Transaction,
+	schedule: Schedule,
+) -> ExecuteAsync<'a> {
+	let (addresses, keys) = access_list(transaction);
+	let mut fetches = Fetch::batch(&*provider, addresses, keys);
+	fetches.push(Fetch::BlockContext(provider.block_context()));
+	ExecuteAsync {
+		provider,
+		transaction,
+		schedule,
+		state: Prefetched::default(),
+		fetches,
+		rounds: 0,
+		result: None,
+		applying: None,
+	}
+}
+
+/// Future returned by `execute_async`
+pub struct ExecuteAsync<'a> {
+	provider: &'a mut dyn AsyncProvider,
+	transaction: &'a Transaction,
+	schedule: Schedule,
+	state: Prefetched,
+	fetches: Vec<Fetch>,
+	/// Number of the executions that missed state
+	rounds: usize,
+	/// Result of the last execution, set once it read only the fetched state
+	result: Option<ResultData>,
+	/// Application of the changes of the last execution
+	applying: Option<ProviderFuture<()>>,
+}
+
+impl<'a> ExecuteAsync<'a> {
+	fn poll_fetches(&mut self, cx: &mut Context) -> Poll<Result<(), Error>> {
+		let mut i = 0;
+		while i < self.fetches.len() {
+			match self.fetches[i].poll(cx, &mut self.state) {
+				Poll::Ready(Ok(())) => {
+					self.fetches.swap_remove(i);
+				}
+				Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::provider_failure(err))),
+				Poll::Pending => i += 1,
+			}
+		}
+
+		if self.fetches.is_empty() {
+			Poll::Ready(Ok(()))
+		} else {
+			Poll::Pending
+		}
+	}
+}
+
+impl<'a> Future for ExecuteAsync<'a> {
+	type Output = Result<ResultData, Error>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		while this.result.is_none() {
+			match this.poll_fetches(cx) {
+				Poll::Ready(Ok(())) => {}
+				Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+				Poll::Pending => return Poll::Pending,
+			}
+
+			let result = execute_with_schedule(&mut this.state, this.transaction, &this.schedule, &mut NoopTracer);
+			let missing = this.state.take_missing();
+			let changes = this.state.take_changes();
+			if !missing.is_empty() {
+				this.rounds += 1;
+				if this.rounds > this.schedule.limits.max_fetch_rounds {
+					return Poll::Ready(Err(Error::LimitExceeded {
+						limit: Limit::FetchRounds,
+					}));
+				}
+				this.fetches = Fetch::missing(&*this.provider, missing);
+				continue;
+			}
+
+			match result {
+				Ok(result) => {
+					if !changes.is_empty() {
+						this.applying = Some(this.provider.apply_changes(changes));
+					}
+					this.result = Some(result);
+				}
+				Err(err) => return Poll::Ready(Err(err)),
+			}
+		}
+
+		if let Some(applying) = &mut this.applying {
+			match applying.as_mut().poll(cx) {
+				Poll::Ready(Ok(())) => this.applying = None,
+				Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::provider_failure(err))),
+				Poll::Pending => return Poll::Pending,
+			}
+		}
+		Poll::Ready(Ok(this.result.take().unwrap()))
+	}
+}
+
+fn execute_transaction(
+	provider: &mut dyn Provider,
+	transaction: &Transaction,
+	schedule: &Schedule,
+	tracer: &mut dyn Tracer,
+	observer: Option<Observer>,
+) -> Result<ResultData, Error> {
+	let wasm_costs = schedule.wasm()?;
+	// initial memory charge cannot overflow if initial_mem < 2^16,
+	// see the charge below
+	if wasm_costs.initial_mem >= 1 << 16 {
+		return Err(Error::Wasm {
+			msg: "Wasm schedule initial_mem should be less than 2^16".to_owned(),
+		});
+	}
+
+	let mut state = State::new(provider)?;
+	let (addresses, keys) = access_list(transaction);
+	state.prefetch(&addresses, &keys)?;
+
+	// the called account is prefetched
+	if let Action::Call(address) = &transaction.action {
+		if !state.exist(address)? {
+			return Err(Error::InvalidAddress { address: *address });
+		}
+	}
+
 	let params = match &transaction.action {
 		Action::Create(code, salt) => {
 			let new_address = utils::contract_address(&transaction.sender, &code, &salt);
@@ -37,13 +271,13 @@ pub fn execute(provider: &mut dyn Provider, transaction: &Transaction) -> Result
 				action_type: ActionType::Create,
 				code: code.clone(),
 				args: transaction.args.clone(),
-				code_hash: None,
-				code_version: U256::zero(),
+				code_hash: Some(utils::keccak(&code)),
+				code_version: schedule.latest_version,
+				depth: 0,
 			}
 		}
 		Action::Call(address) => {
-			let acc = provider.account(&address)?;
-			let code = acc.code.clone();
+			let code = state.code(&address)?;
 			ActionParams {
 				code_address: address.clone(),
 				address: address.clone(),
@@ -53,114 +287,37 @@ pub fn execute(provider: &mut dyn Provider, transaction: &Transaction) -> Result
 				gas_price: transaction.gas_price,
 				value: transaction.value,
 				action_type: ActionType::Call,
+				code_hash: Some(state.code_hash(&address)?),
 				code: code,
 				args: transaction.args.clone(),
-				code_hash: None,
-				code_version: U256::zero(),
+				code_version: schedule.latest_version,
+				depth: 0,
 			}
 		}
 	};
 
-	let mut schedule = Schedule::default();
-	let wasm = WasmCosts::default();
-	schedule.wasm = Some(wasm);
-
-	let module = parser::payload(&params, schedule.wasm())?;
-	let loaded_module = wasmi::Module::from_parity_wasm_module(module)?;
-	let instantiation_resolver = env::ImportResolver::with_limit(16, schedule.wasm());
-	let module_instance = wasmi::ModuleInstance::new(
-		&loaded_module,
-		&wasmi::ImportsBuilder::new().with_resolver("env", &instantiation_resolver),
-	)?;
-
-	let adjusted_gas = params.gas * U256::from(schedule.wasm().opcodes_div)
-		/ U256::from(schedule.wasm().opcodes_mul);
-
-	if adjusted_gas > ::std::u64::MAX.into() {
-		return Err(Error::Wasm {
-			msg: "Wasm interpreter cannot run contracts with gas (wasm adjusted) >= 2^64"
-				.to_owned(),
-		});
+	let mut usage = TransactionUsage::new(&schedule.limits);
+	let result = frame::execute(&mut state, &params, schedule, &mut usage, tracer, observer)?;
+	if result.outcome.is_success() {
+		state.update_state()?;
 	}
 
-	let initial_memory = instantiation_resolver.memory_size()?;
-	trace!(target: "wasm", "Contract requested {:?} pages of initial memory", initial_memory);
-
-	let mut state = State::new(provider);
-	let mut runtime = Runtime::new(
-		&params,
-		&schedule,
-		&mut state,
-		instantiation_resolver.memory_ref(),
-		// cannot overflow, checked above
-		adjusted_gas.low_u64(),
-	);
-
-	// cannot overflow if static_region < 2^16,
-	// initial_memory ∈ [0..2^32)
-	// total_charge <- static_region * 2^32 * 2^16
-	// total_charge ∈ [0..2^64) if static_region ∈ [0..2^16)
-	// qed
-	assert!(runtime.schedule().wasm().initial_mem < 1 << 16);
-	runtime.charge(|s| initial_memory as u64 * s.wasm().initial_mem as u64)?;
-
-	let instance = module_instance.run_start(&mut runtime)?;
-	let invoke_result = instance.invoke_export("call", &[], &mut runtime);
-
-	if let Err(wasmi::Error::Trap(ref trap)) = invoke_result {
-		if let wasmi::TrapKind::Host(ref boxed) = *trap.kind() {
-			let ref runtime_err = boxed
-				.downcast_ref::<Error>()
-				.expect("Host errors other than runtime::Error never produced; qed");
-
-			let mut have_error = false;
-			match **runtime_err {
-				Error::Suicide => {
-					debug!("Contract suicided.");
-				}
-				Error::Return => {
-					debug!("Contract returned.");
-				}
-				_ => {
-					have_error = true;
-				}
-			}
-			if let (true, Err(e)) = (have_error, invoke_result) {
-				trace!(target: "wasm", "Error executing contract: {:?}", e);
-				return Err(Error::from(e));
-			}
-		}
-	}
+	tracer.outcome(&result);
 
-	let gas_left = runtime
-		.gas_left()
-		.expect("Cannot fail since it was not updated since last charge");
-	let result = runtime.into_result();
-	let gas_left_adj = U256::from(gas_left) * U256::from(schedule.wasm().opcodes_mul)
-		/ U256::from(schedule.wasm().opcodes_div);
-
-	if result.is_empty() {
-		trace!(target: "wasm", "Contract execution result is empty.");
-		Ok(ResultData {
-			gas_left: gas_left_adj,
-			data: vec![],
-			contract: params.address,
-			// TODO::::: logs????
-			logs: vec![], // ext.logs().to_vec(),
-		})
-	} else {
-		if let Action::Create(_, _) = &transaction.action {
-			runtime.init_code(&params.address, result.to_vec());
-		}
-
-		runtime.update_state()?;
+	Ok(result)
+}
 
-		Ok(ResultData {
-			gas_left: gas_left_adj,
-			data: result.to_vec(),
-			contract: params.address,
-			// TODO::::: logs????
-			logs: vec![], // ext.logs().to_vec(),
-		})
+/// Accounts and storage keys to prefetch for the transaction,
+/// the account it executes and its access list
+fn access_list(transaction: &Transaction) -> (Vec<Address>, Vec<(Address, H256)>) {
+	let mut addresses = vec![match &transaction.action {
+		Action::Create(code, salt) => utils::contract_address(&transaction.sender, code, salt),
+		Action::Call(address) => *address,
+	}];
+	let mut keys = vec![];
+	for (address, storage_keys) in &transaction.access_list {
+		addresses.push(*address);
+		keys.extend(storage_keys.iter().map(|key| (*address, *key)));
 	}
+	(addresses, keys)
 }
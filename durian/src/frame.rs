@@ -0,0 +1,415 @@
+//! Execution of a single frame, the transaction itself or a nested call.
+
+use env;
+use error::Error;
+use execute::ResultData;
+use hooks::{self, DebugInfo, HookSession, HookSites, Observer};
+use outcome::{ExecutionOutcome, TrapKind};
+use parity_wasm::elements;
+use parser;
+use primitive_types::U256;
+use runtime::{Runtime, RuntimeContext, TransactionUsage};
+use schedule::Schedule;
+use state::State;
+use tracer::{Frame, FrameKind, Tracer};
+use trap_site;
+use types::{ActionParams, ActionType};
+use utils;
+use wasmi;
+use Bytes;
+
+/// Executes the frame described by `params` on top of `state`.
+///
+/// The changes of the frame are kept in `state` only if the execution succeeded.
+/// `usage` counts the limits of the whole transaction.
+pub fn execute(
+	state: &mut State,
+	params: &ActionParams,
+	schedule: &Schedule,
+	usage: &mut TransactionUsage,
+	tracer: &mut dyn Tracer,
+	observer: Option<Observer>,
+) -> Result<ResultData, Error> {
+	let wasm_costs = schedule.wasm()?;
+	let adjusted_gas = params
+		.gas
+		.checked_mul(U256::from(wasm_costs.opcodes_div))
+		.map(|gas| gas / U256::from(wasm_costs.opcodes_mul));
+	let adjusted_gas = match adjusted_gas {
+		Some(gas) if gas <= u64::MAX.into() => gas.low_u64(),
+		_ => {
+			return Err(Error::Wasm {
+				msg: "Wasm interpreter cannot run contracts with gas (wasm adjusted) >= 2^64"
+					.to_owned(),
+			});
+		}
+	};
+
+	let frame = Frame {
+		kind: match params.action_type {
+			ActionType::Create => FrameKind::Create,
+			ActionType::Call => FrameKind::Call,
+		},
+		depth: params.depth,
+		sender: params.sender,
+		address: params.address,
+		value: params.value,
+		gas: params.gas,
+		code: match params.action_type {
+			ActionType::Create => &params.code,
+			ActionType::Call => &[],
+		},
+		input: &params.args,
+	};
+	tracer.enter_frame(&frame);
+
+	let result = run(state, params, schedule, adjusted_gas, usage, tracer, observer)?;
+
+	tracer.exit_frame(&result.outcome, &result.gas_used, &result.data);
+
+	Ok(result)
+}
+
+/// Executes the contract code, `adjusted_gas` is the gas limit in wasm gas units
+fn run(
+	state: &mut State,
+	params: &ActionParams,
+	schedule: &Schedule,
+	adjusted_gas: u64,
+	usage: &mut TransactionUsage,
+	tracer: &mut dyn Tracer,
+	observer: Option<Observer>,
+) -> Result<ResultData, Error> {
+	let wasm_costs = schedule.wasm()?;
+	let prepared = match (&params.action_type, &params.code_hash, &observer) {
+		(ActionType::Call, Some(code_hash), None) => {
+			state.prepared_code(code_hash, &params.code_version)?
+		}
+		_ => None,
+	};
+
+	let instantiation_resolver =
+		env::ImportResolver::with_limit(schedule.limits.max_memory_pages, schedule, wasm_costs);
+
+	let loaded = load_module(
+		params,
+		prepared,
+		schedule,
+		&instantiation_resolver,
+		observer.as_ref().map(Observer::hook_sites),
+	);
+	let loaded = match loaded {
+		Ok(loaded) => loaded,
+		Err(err @ Error::UnsupportedCodeVersion { .. }) => return Err(err),
+		Err(Error::LimitExceeded { limit }) => {
+			return Ok(aborted(params, ExecutionOutcome::LimitExceeded(limit)))
+		}
+		Err(err) => return Ok(invalid_code(params, err)),
+	};
+	let trap_global = trap_site::global_index(&loaded.module);
+	let loaded_module = match wasmi::Module::from_parity_wasm_module(loaded.module) {
+		Ok(loaded_module) => loaded_module,
+		Err(err) => return Ok(invalid_code(params, Error::from(err))),
+	};
+	let mut imports =
+		wasmi::ImportsBuilder::new().with_resolver("env", &instantiation_resolver);
+	if loaded.debug_info.is_some() {
+		imports.push_resolver(hooks::HOOKS_MODULE, &hooks::HookResolver);
+	}
+	let module_instance = match wasmi::ModuleInstance::new(&loaded_module, &imports) {
+		Ok(module_instance) => module_instance,
+		Err(err) => return Ok(invalid_code(params, Error::from(err))),
+	};
+	let instance = module_instance.not_started_instance().clone();
+
+	let initial_memory = instantiation_resolver.memory_size()?;
+	trace!(target: "wasm", "Contract requested {:?} pages of initial memory", initial_memory);
+
+	let mut session = match (observer, loaded.debug_info) {
+		(Some(observer), Some(debug_info)) => Some(HookSession::new(
+			observer,
+			debug_info,
+			wasm_costs,
+			instantiation_resolver.memory_ref(),
+		)),
+		_ => None,
+	};
+	let mut frame_state = state.nested();
+	let checkpoint = frame_state.checkpoint();
+	let context = RuntimeContext {
+		schedule,
+		wasm_costs,
+		usage,
+		tracer,
+	};
+	let mut runtime = Runtime::new(params, context, &mut frame_state, instantiation_resolver.memory_ref(), adjusted_gas);
+
+	// cannot overflow if static_region < 2^16,
+	// initial_memory ∈ [0..2^32)
+	// total_charge <- static_region * 2^32 * 2^16
+	// total_charge ∈ [0..2^64) if static_region ∈ [0..2^16)
+	// qed
+	let initial_charge = runtime.charge(|_| initial_memory as u64 * wasm_costs.initial_mem as u64);
+	if let (Ok(()), Some(ref mut session)) = (&initial_charge, &mut session) {
+		session.charged("[initial memory]", initial_memory as u64 * wasm_costs.initial_mem as u64);
+	}
+	let mut outcome = match initial_charge {
+		Ok(()) => {
+			let invoke_result = match session {
+				Some(ref mut session) => invoke(
+					module_instance,
+					loaded.entry,
+					&mut session.externals(&mut runtime),
+				),
+				None => invoke(module_instance, loaded.entry, &mut runtime),
+			};
+			if let Some(err) = provider_failure(&invoke_result) {
+				return Err(err);
+			}
+			execution_outcome(&runtime, invoke_result)
+		}
+		Err(_) => ExecutionOutcome::OutOfGas,
+	};
+	if let ExecutionOutcome::Trap(_, ref mut location) = outcome {
+		*location = trap_global
+			.and_then(|global| trap_site::last_site(&instance, global))
+			.map(|site| trap_site::resolve(&params.code, site));
+	}
+	trace!(target: "wasm", "Execution outcome: {:?}", outcome);
+
+	let result = runtime.result();
+	if outcome.is_success() && params.action_type == ActionType::Create {
+		let code = match loaded.runtime_code {
+			Some(code) => code,
+			None => result.clone(),
+		};
+		let gas_left = runtime.gas_left()?;
+		outcome = deploy_code(&mut runtime, params, &instantiation_resolver, code)?;
+		if let Some(ref mut session) = session {
+			session.charged("[code deposit]", gas_left - runtime.gas_left()?);
+		}
+	}
+
+	if let Some(ref mut session) = session {
+		session.finish(&outcome, &runtime);
+	}
+
+	let gas_left_adj = if outcome.consumes_all_gas() {
+		U256::zero()
+	} else {
+		let gas_left = runtime.gas_left()?;
+		U256::from(gas_left) * U256::from(wasm_costs.opcodes_mul)
+			/ U256::from(wasm_costs.opcodes_div)
+	};
+
+	let logs = runtime.logs().to_vec();
+	if !outcome.is_success() {
+		runtime.revert_state(checkpoint);
+	}
+	let changes = runtime.take_state_changes();
+	state.commit(changes);
+
+	Ok(ResultData {
+		outcome,
+		gas_used: params.gas.saturating_sub(gas_left_adj),
+		gas_left: gas_left_adj,
+		data: result,
+		contract: params.address,
+		logs,
+	})
+}
+
+/// Runs the start function of the module and invokes `entry`
+fn invoke<E: wasmi::Externals>(
+	module_instance: wasmi::NotStartedModuleRef,
+	entry: &str,
+	externals: &mut E,
+) -> Result<Option<wasmi::RuntimeValue>, wasmi::Error> {
+	module_instance
+		.run_start(externals)
+		.map_err(wasmi::Error::Trap)
+		.and_then(|instance| instance.invoke_export(entry, &[], externals))
+}
+
+/// Module to execute
+struct LoadedModule {
+	module: elements::Module,
+	/// Export to invoke
+	entry: &'static str,
+	/// Code to deploy, for constructors exporting `deploy`
+	runtime_code: Option<Bytes>,
+	/// Set if the hooks are injected
+	debug_info: Option<DebugInfo>,
+}
+
+/// Validates and instruments the module to execute.
+///
+/// `prepared` is the artifact stored at deployment of the called contract, if any.
+/// If `hook_sites` is set, the hooks are injected.
+fn load_module(
+	params: &ActionParams,
+	prepared: Option<Bytes>,
+	schedule: &Schedule,
+	resolver: &env::ImportResolver,
+	hook_sites: Option<HookSites>,
+) -> Result<LoadedModule, Error> {
+	// Constructor exporting `deploy` is deployed as the contract code itself,
+	// otherwise (pwasm convention) `call` returns the contract code.
+	let mut runtime_code = None;
+	let mut entry = parser::CALL_EXPORT;
+	let (module, debug_info) = match (&params.action_type, hook_sites) {
+		(ActionType::Create, _) => {
+			let module = parser::validate(&params.code, &ActionType::Create, resolver)?;
+			if parser::has_export(&module, parser::DEPLOY_EXPORT) {
+				entry = parser::DEPLOY_EXPORT;
+				runtime_code = Some(parser::runtime_code(module.clone())?);
+			}
+			match hook_sites {
+				Some(hook_sites) => {
+					let (module, debug_info) = parser::instrument_with_hooks(
+						module,
+						&params.code_version,
+						schedule.wasm()?,
+						hook_sites,
+					)?;
+					(module, Some(debug_info))
+				}
+				None => (
+					parser::instrument(module, &params.code_version, schedule.wasm()?)?,
+					None,
+				),
+			}
+		}
+		(ActionType::Call, Some(hook_sites)) => {
+			let (module, debug_info) =
+				parser::payload_with_hooks(params, schedule.wasm()?, hook_sites)?;
+			(module, Some(debug_info))
+		}
+		(ActionType::Call, None) => match prepared {
+			Some(prepared) => (parser::load_prepared(&prepared, resolver)?, None),
+			// Contract was deployed without storing the prepared code
+			None => (parser::payload(params, schedule.wasm()?)?, None),
+		},
+	};
+	parser::check_memory_limit(&module, resolver.max_memory())?;
+	if !parser::has_export(&module, entry) {
+		return Err(Error::MissingExport {
+			name: format!("`{}`", entry),
+		});
+	}
+
+	Ok(LoadedModule {
+		module,
+		entry,
+		runtime_code,
+		debug_info,
+	})
+}
+
+/// Returns the provider failure that trapped the contract, which aborts the execution
+/// instead of being an outcome of the contract
+fn provider_failure(invoke_result: &Result<Option<wasmi::RuntimeValue>, wasmi::Error>) -> Option<Error> {
+	match invoke_result {
+		Err(wasmi::Error::Trap(trap)) => match trap.kind() {
+			wasmi::TrapKind::Host(boxed) => match boxed.downcast_ref::<Error>() {
+				Some(err @ Error::ProviderFailure { .. }) => Some(err.clone()),
+				_ => None,
+			},
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Maps the result of invoking the contract to the execution outcome
+fn execution_outcome(
+	runtime: &Runtime,
+	invoke_result: Result<Option<wasmi::RuntimeValue>, wasmi::Error>,
+) -> ExecutionOutcome {
+	match invoke_result {
+		Ok(_) => ExecutionOutcome::Success,
+		Err(wasmi::Error::Trap(ref trap)) => {
+			let runtime_err = match *trap.kind() {
+				wasmi::TrapKind::Host(ref boxed) => boxed.downcast_ref::<Error>(),
+				_ => None,
+			};
+			match runtime_err {
+				Some(&Error::Return) => ExecutionOutcome::Success,
+				Some(&Error::Suicide) => {
+					debug!("Contract suicided.");
+					ExecutionOutcome::Success
+				}
+				Some(&Error::Revert) => ExecutionOutcome::Revert(runtime.result()),
+				Some(&Error::GasLimit) => ExecutionOutcome::OutOfGas,
+				Some(&Error::LimitExceeded { limit }) => ExecutionOutcome::LimitExceeded(limit),
+				Some(&Error::Panic { .. }) => match runtime.panic_payload() {
+					Some(payload) => ExecutionOutcome::Panic(payload.clone()),
+					// Raised by an unsupported host function, not by the contract
+					None => ExecutionOutcome::Trap(TrapKind::from(trap.kind()), None),
+				},
+				_ => ExecutionOutcome::Trap(TrapKind::from(trap.kind()), None),
+			}
+		}
+		Err(err) => ExecutionOutcome::InvalidCode(format!("{}", Error::from(err))),
+	}
+}
+
+/// Stores the code returned by the constructor, charging the code deposit.
+/// The code can't be larger than `create_data_limit`, the constructor itself isn't limited.
+fn deploy_code(
+	runtime: &mut Runtime,
+	params: &ActionParams,
+	resolver: &env::ImportResolver,
+	code: Bytes,
+) -> Result<ExecutionOutcome, Error> {
+	let limit = runtime.schedule().create_data_limit;
+	if code.len() > limit {
+		return Ok(ExecutionOutcome::CodeSizeLimit { size: code.len(), limit });
+	}
+
+	let code_hash = utils::keccak(&code);
+	let prepared = parser::prepare(&code, &params.code_version, runtime.schedule(), resolver);
+	let prepared = match prepared {
+		Ok(prepared) => prepared,
+		Err(err) => return Ok(ExecutionOutcome::InvalidCode(format!("{}", err))),
+	};
+
+	let deposit = runtime.adjusted_overflow_charge(|schedule| {
+		(schedule.create_data_gas as u64).checked_mul(code.len() as u64)
+	});
+	match deposit {
+		Ok(()) => {
+			runtime.init_code(&params.address, code)?;
+			runtime.set_prepared_code(&code_hash, &params.code_version, prepared);
+		}
+		Err(_) if runtime.schedule().exceptional_failed_code_deposit => {
+			return Ok(ExecutionOutcome::OutOfGas);
+		}
+		Err(_) => {
+			// Frontier rules: the contract is created without code
+			debug!("Not enough gas to pay the code deposit, contract code is not stored.");
+			runtime.init_code(&params.address, vec![])?;
+		}
+	}
+
+	Ok(ExecutionOutcome::Success)
+}
+
+/// Result of the execution of the code that can't be executed
+fn invalid_code(params: &ActionParams, err: Error) -> ResultData {
+	trace!(target: "wasm", "Invalid contract code: {:?}", err);
+
+	aborted(params, ExecutionOutcome::InvalidCode(format!("{}", err)))
+}
+
+/// Result of the execution that failed before the contract was invoked
+fn aborted(params: &ActionParams, outcome: ExecutionOutcome) -> ResultData {
+	ResultData {
+		outcome,
+		gas_used: params.gas,
+		gas_left: U256::zero(),
+		data: vec![],
+		contract: params.address,
+		logs: vec![],
+	}
+}
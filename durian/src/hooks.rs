@@ -0,0 +1,537 @@
+//! Hooks, injected into the contract code executed in the debug or profiling mode.
+//!
+//! A hook is called before every instruction of the contract, or only before
+//! the first instruction of every function and the calls when profiling.
+//! If the execution should stop there, the locals of the function are passed
+//! to the host one by one and the debugger is invoked.
+
+use debugger::{DebugState, Debugger, Location, Resume, Value};
+use env::{self, ids::GAS_FUNC};
+use error::Error;
+use outcome::ExecutionOutcome;
+use parser;
+use parity_wasm::builder;
+use parity_wasm::elements::{self, BlockType, Instruction};
+use primitive_types::U256;
+use profiler::GasProfile;
+use runtime::Runtime;
+use std::collections::{BTreeMap, HashMap};
+use trap_site;
+use wasm_cost::WasmCosts;
+use wasmi::{
+	self, Externals, FuncInstance, FuncRef, MemoryRef, RuntimeArgs, RuntimeValue, Signature, Trap,
+};
+
+/// Module the hooks are imported from.
+/// Contracts can't import it, only `env` imports pass the validation.
+pub const HOOKS_MODULE: &str = "debug";
+
+const STEP_FUNC: usize = 3000;
+const LOCAL_I32_FUNC: usize = 3010;
+const LOCAL_I64_FUNC: usize = 3020;
+const PAUSE_FUNC: usize = 3030;
+const RETURNED_FUNC: usize = 3040;
+
+/// Hooks in the import order: name, params, result and the internal id
+const HOOKS: &[(&str, &[elements::ValueType], Option<elements::ValueType>, usize)] = &[
+	// (site) -> 1 if the execution should stop
+	("step", &[elements::ValueType::I32], Some(elements::ValueType::I32), STEP_FUNC),
+	("local_i32", &[elements::ValueType::I32], None, LOCAL_I32_FUNC),
+	("local_i64", &[elements::ValueType::I64], None, LOCAL_I64_FUNC),
+	("pause", &[], None, PAUSE_FUNC),
+	// a contract function called from the last call site returned
+	("returned", &[], None, RETURNED_FUNC),
+];
+
+fn to_wasmi_type(value_type: &elements::ValueType) -> wasmi::ValueType {
+	match *value_type {
+		elements::ValueType::I32 => wasmi::ValueType::I32,
+		elements::ValueType::I64 => wasmi::ValueType::I64,
+		elements::ValueType::F32 => wasmi::ValueType::F32,
+		elements::ValueType::F64 => wasmi::ValueType::F64,
+	}
+}
+
+/// Resolves the hook imports of the instrumented module
+pub struct HookResolver;
+
+impl wasmi::ModuleImportResolver for HookResolver {
+	fn resolve_func(&self, field_name: &str, _signature: &Signature) -> Result<FuncRef, wasmi::Error> {
+		match HOOKS.iter().find(|hook| hook.0 == field_name) {
+			Some(&(_, params, result, idx)) => {
+				let params: Vec<wasmi::ValueType> = params.iter().map(to_wasmi_type).collect();
+				let signature = Signature::new(params, result.as_ref().map(to_wasmi_type));
+				Ok(FuncInstance::alloc_host(signature, idx))
+			}
+			None => Err(wasmi::Error::Instantiation(format!(
+				"Hook {} not found",
+				field_name
+			))),
+		}
+	}
+}
+
+/// Instructions the hooks are called before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookSites {
+	/// Every instruction
+	Instructions,
+	/// The first instruction of every function and the calls,
+	/// enough to track the call stack
+	Calls,
+}
+
+/// Instruction of the contract a hook is called before
+struct Site {
+	location: Location,
+	/// Calls a contract function, which is a new frame
+	is_call: bool,
+}
+
+/// What the hooks of an instrumented module refer to
+pub struct DebugInfo {
+	sites: Vec<Site>,
+	function_names: HashMap<u32, String>,
+	code: HashMap<u32, Vec<String>>,
+	trap_global: u32,
+}
+
+impl DebugInfo {
+	/// Index of the global the trap sites are recorded in
+	pub fn trap_global(&self) -> u32 {
+		self.trap_global
+	}
+}
+
+/// Types of the parameters and locals of the function with the body `body_idx`
+fn local_types(module: &elements::Module, body_idx: usize) -> Result<Vec<elements::ValueType>, Error> {
+	let type_ref = module
+		.function_section()
+		.and_then(|fs| fs.entries().get(body_idx))
+		.map(|func| func.type_ref())
+		.ok_or(Error::Wasm {
+			msg: format!("Missing signature of function body {}", body_idx),
+		})?;
+	let mut types = match module
+		.type_section()
+		.and_then(|ts| ts.types().get(type_ref as usize))
+	{
+		Some(elements::Type::Function(func_type)) => func_type.params().to_vec(),
+		None => {
+			return Err(Error::Wasm {
+				msg: format!("Type index {} out of bounds", type_ref),
+			});
+		}
+	};
+
+	let body = &module.code_section().map(|cs| cs.bodies()).unwrap_or(&[])[body_idx];
+	for local in body.locals() {
+		for _ in 0..local.count() {
+			types.push(local.value_type());
+		}
+	}
+
+	Ok(types)
+}
+
+/// Adds `shift` to the indices of the functions that are not imported
+fn shift_functions(module: &mut elements::Module, first: u32, shift: u32) {
+	let update = |idx: &mut u32| {
+		if *idx >= first {
+			*idx += shift
+		}
+	};
+
+	for section in module.sections_mut() {
+		match *section {
+			elements::Section::Code(ref mut code_section) => {
+				for body in code_section.bodies_mut() {
+					for instruction in body.code_mut().elements_mut() {
+						if let Instruction::Call(ref mut idx) = *instruction {
+							update(idx);
+						}
+					}
+				}
+			}
+			elements::Section::Export(ref mut export_section) => {
+				for export in export_section.entries_mut() {
+					if let elements::Internal::Function(ref mut idx) = *export.internal_mut() {
+						update(idx);
+					}
+				}
+			}
+			elements::Section::Element(ref mut elements_section) => {
+				for segment in elements_section.entries_mut() {
+					for idx in segment.members_mut() {
+						update(idx);
+					}
+				}
+			}
+			elements::Section::Start(ref mut idx) => update(idx),
+			_ => {}
+		}
+	}
+}
+
+/// Injects the hooks into `module`, which is `original` with the gas counter injected.
+///
+/// Hooks are not metered, so the contract is charged as in the normal execution.
+/// The trap sites are recorded as in `parser::instrument`.
+pub fn inject(original: &elements::Module, module: elements::Module, hook_sites: HookSites) -> Result<(elements::Module, DebugInfo), Error> {
+	let imported = original.import_count(elements::ImportCountType::Function) as u32;
+	// the gas counter is imported after the contract imports
+	let gas_func = imported;
+	let first_hook = gas_func + 1;
+
+	let mut mbuilder = builder::from_module(module);
+	for &(name, params, result, _) in HOOKS {
+		let signature = mbuilder.push_signature(
+			builder::signature()
+				.with_params(params.to_vec())
+				.with_return_type(result)
+				.build_sig(),
+		);
+		mbuilder.push_import(
+			builder::import()
+				.module(HOOKS_MODULE)
+				.field(name)
+				.external()
+				.func(signature)
+				.build(),
+		);
+	}
+	let (mut module, trap_global) = trap_site::add_global(mbuilder.build());
+	shift_functions(&mut module, first_hook, HOOKS.len() as u32);
+
+	let step = first_hook;
+	let local_i32 = first_hook + 1;
+	let local_i64 = first_hook + 2;
+	let pause = first_hook + 3;
+	let returned = first_hook + 4;
+
+	let mut sites = Vec::new();
+	// types of the locals of the current function body
+	let mut locals = (None, Vec::new());
+	parser::rewrite_contract_code(original, &mut module, |body_idx, location, original_instruction, instruction, code| {
+		if locals.0 != Some(body_idx) {
+			locals = (Some(body_idx), local_types(original, body_idx)?);
+		}
+		let is_call = match *original_instruction {
+			Instruction::Call(idx) => idx >= imported,
+			Instruction::CallIndirect(..) => true,
+			_ => false,
+		};
+
+		if hook_sites == HookSites::Instructions || location.offset == 0 || is_call {
+			code.push(Instruction::I32Const(sites.len() as i32));
+			code.push(Instruction::Call(step));
+			code.push(Instruction::If(BlockType::NoResult));
+			for (local_idx, local_type) in locals.1.iter().enumerate() {
+				let hook = match *local_type {
+					elements::ValueType::I32 => local_i32,
+					elements::ValueType::I64 => local_i64,
+					// floats are forbidden in contracts
+					_ => continue,
+				};
+				code.push(Instruction::GetLocal(local_idx as u32));
+				code.push(Instruction::Call(hook));
+			}
+			code.push(Instruction::Call(pause));
+			code.push(Instruction::End);
+
+			sites.push(Site { location, is_call });
+		}
+
+		trap_site::record(trap_global, location, original_instruction, code);
+		code.push(instruction);
+		if is_call {
+			code.push(Instruction::Call(returned));
+		}
+		Ok(())
+	})?;
+
+	let mut code = HashMap::new();
+	for (body_idx, body) in original.code_section().map(|cs| cs.bodies()).unwrap_or(&[]).iter().enumerate() {
+		code.insert(
+			imported + body_idx as u32,
+			body.code().elements().iter().map(|instruction| format!("{}", instruction)).collect(),
+		);
+	}
+
+	Ok((
+		module,
+		DebugInfo {
+			sites,
+			function_names: parser::function_names(original),
+			code,
+			trap_global,
+		},
+	))
+}
+
+/// What observes the execution through the hooks
+pub enum Observer<'a> {
+	Debugger(&'a mut dyn Debugger),
+	Profiler(&'a mut GasProfile),
+}
+
+impl<'a> Observer<'a> {
+	/// Instructions the hooks should be called before
+	pub fn hook_sites(&self) -> HookSites {
+		match *self {
+			Observer::Debugger(_) => HookSites::Instructions,
+			Observer::Profiler(_) => HookSites::Calls,
+		}
+	}
+}
+
+/// Gas charged with the call stack it is charged at, in wasm gas units.
+/// The host function that charged the gas, if any, is the leaf of the stack.
+type Samples = HashMap<(Vec<u32>, Option<String>), u64>;
+
+/// State of the hooks during the execution
+pub struct HookSession<'a> {
+	observer: Observer<'a>,
+	info: DebugInfo,
+	/// Conversion of wasm gas units to gas
+	opcodes_mul: u32,
+	opcodes_div: u32,
+	memory: MemoryRef,
+	/// Location of every frame, the current frame last
+	frames: Vec<Location>,
+	/// Number of frames at the pending call sites
+	calls: Vec<usize>,
+	locals: Vec<Value>,
+	stepping: bool,
+	samples: Samples,
+}
+
+impl<'a> HookSession<'a> {
+	pub fn new(
+		mut observer: Observer<'a>,
+		info: DebugInfo,
+		wasm_costs: &WasmCosts,
+		memory: MemoryRef,
+	) -> Self {
+		let stepping = match observer {
+			Observer::Debugger(ref mut debugger) => debugger.start() == Resume::Step,
+			Observer::Profiler(_) => false,
+		};
+
+		HookSession {
+			observer,
+			info,
+			opcodes_mul: wasm_costs.opcodes_mul,
+			opcodes_div: wasm_costs.opcodes_div,
+			memory,
+			frames: Vec::new(),
+			calls: Vec::new(),
+			locals: Vec::new(),
+			stepping,
+			samples: Samples::new(),
+		}
+	}
+
+	/// Externals that handle the hooks and pass the other host calls to `runtime`
+	pub fn externals<'r, 'b>(&'r mut self, runtime: &'r mut Runtime<'b>) -> HookExternals<'r, 'a, 'b> {
+		HookExternals {
+			session: self,
+			runtime,
+		}
+	}
+
+	/// Records gas charged outside of the contract code, e.g. the code deposit
+	pub fn charged(&mut self, label: &'static str, amount: u64) {
+		if amount > 0 {
+			*self.samples.entry((vec![], Some(label.to_owned()))).or_insert(0) += amount;
+		}
+	}
+
+	/// Reports the outcome to the debugger or writes the profile
+	pub fn finish(&mut self, outcome: &ExecutionOutcome, runtime: &Runtime) {
+		let gas_left = if outcome.consumes_all_gas() {
+			U256::zero()
+		} else {
+			self.gas_left(runtime)
+		};
+		let HookSession {
+			ref mut observer,
+			ref info,
+			ref memory,
+			ref frames,
+			ref samples,
+			opcodes_mul,
+			opcodes_div,
+			..
+		} = *self;
+
+		let debugger = match *observer {
+			Observer::Debugger(ref mut debugger) => debugger,
+			Observer::Profiler(ref mut profile) => {
+				profile.stacks = profile_stacks(samples, info, opcodes_mul, opcodes_div);
+				return;
+			}
+		};
+		match frames.last() {
+			Some(location) => memory.with_direct_access(|memory| {
+				let state = DebugState {
+					location,
+					call_stack: frames,
+					locals: &[],
+					gas_left,
+					memory,
+					function_names: &info.function_names,
+					code: &info.code,
+				};
+				debugger.finish(outcome, Some(&state));
+			}),
+			None => debugger.finish(outcome, None),
+		}
+	}
+
+	fn gas_left(&self, runtime: &Runtime) -> U256 {
+		let gas_left = runtime.gas_left().unwrap_or(0);
+		U256::from(gas_left) * U256::from(self.opcodes_mul) / U256::from(self.opcodes_div)
+	}
+
+	/// The execution reached the site, returns true if it should stop there
+	fn step(&mut self, site: usize) -> Result<bool, Error> {
+		let site = self.info.sites.get(site).ok_or(Error::Unknown)?;
+		match self.frames.last_mut() {
+			// the first instruction is executed once per invocation
+			Some(frame) if site.location.offset != 0 => *frame = site.location,
+			_ => self.frames.push(site.location),
+		}
+		if site.is_call {
+			self.calls.push(self.frames.len());
+		}
+
+		let breakpoint = match self.observer {
+			Observer::Debugger(ref debugger) => debugger
+				.breakpoints()
+				.iter()
+				.any(|breakpoint| breakpoint.matches(&site.location)),
+			Observer::Profiler(_) => false,
+		};
+
+		Ok(self.stepping || breakpoint)
+	}
+
+	fn returned(&mut self) {
+		if let Some(depth) = self.calls.pop() {
+			self.frames.truncate(depth);
+		}
+	}
+
+	/// Records gas charged by a host function, `GAS_FUNC` charges for the contract code
+	fn sample(&mut self, index: usize, amount: u64) {
+		if amount == 0 {
+			return;
+		}
+		let stack = self.frames.iter().map(|frame| frame.function).collect();
+		let host = match index {
+			GAS_FUNC => None,
+			_ => env::func_name(index).map(|name| format!("env.{}", name)),
+		};
+		*self.samples.entry((stack, host)).or_insert(0) += amount;
+	}
+
+	fn pause(&mut self, gas_left: U256) {
+		let resume = {
+			let HookSession {
+				ref mut observer,
+				ref info,
+				ref memory,
+				ref frames,
+				ref locals,
+				..
+			} = *self;
+			let location = match frames.last() {
+				Some(location) => location,
+				None => return,
+			};
+
+			let debugger = match *observer {
+				Observer::Debugger(ref mut debugger) => debugger,
+				Observer::Profiler(_) => return,
+			};
+
+			memory.with_direct_access(|memory| {
+				debugger.stop(&DebugState {
+					location,
+					call_stack: frames,
+					locals,
+					gas_left,
+					memory,
+					function_names: &info.function_names,
+					code: &info.code,
+				})
+			})
+		};
+
+		self.stepping = resume == Resume::Step;
+		self.locals.clear();
+	}
+}
+
+/// Host functions of the contract executed with the hooks
+pub struct HookExternals<'r, 'a: 'r, 'b: 'r> {
+	session: &'r mut HookSession<'a>,
+	runtime: &'r mut Runtime<'b>,
+}
+
+impl<'r, 'a, 'b> Externals for HookExternals<'r, 'a, 'b> {
+	fn invoke_index(&mut self, index: usize, args: RuntimeArgs) -> Result<Option<RuntimeValue>, Trap> {
+		match index {
+			STEP_FUNC => {
+				let site: u32 = args.nth_checked(0)?;
+				let stop = self.session.step(site as usize)?;
+				Ok(Some(RuntimeValue::I32(stop as i32)))
+			}
+			LOCAL_I32_FUNC => {
+				self.session.locals.push(Value::I32(args.nth_checked(0)?));
+				Ok(None)
+			}
+			LOCAL_I64_FUNC => {
+				self.session.locals.push(Value::I64(args.nth_checked(0)?));
+				Ok(None)
+			}
+			PAUSE_FUNC => {
+				let gas_left = self.session.gas_left(self.runtime);
+				self.session.pause(gas_left);
+				Ok(None)
+			}
+			RETURNED_FUNC => {
+				self.session.returned();
+				Ok(None)
+			}
+			_ => {
+				let gas_left = self.runtime.gas_left().unwrap_or(0);
+				let result = self.runtime.invoke_index(index, args);
+				let charged = gas_left.saturating_sub(self.runtime.gas_left().unwrap_or(0));
+				self.session.sample(index, charged);
+				result
+			}
+		}
+	}
+}
+
+/// Converts the samples to gas per call stack of function names
+fn profile_stacks(samples: &Samples, info: &DebugInfo, opcodes_mul: u32, opcodes_div: u32) -> BTreeMap<Vec<String>, U256> {
+	let mut stacks = BTreeMap::new();
+	for ((functions, leaf), amount) in samples {
+		let mut stack: Vec<String> = functions
+			.iter()
+			.map(|function| match info.function_names.get(function) {
+				Some(name) => name.clone(),
+				None => format!("func[{}]", function),
+			})
+			.collect();
+		stack.extend(leaf.clone());
+
+		let gas = U256::from(*amount) * U256::from(opcodes_mul) / U256::from(opcodes_div);
+		*stacks.entry(stack).or_insert_with(U256::zero) += gas;
+	}
+
+	stacks
+}
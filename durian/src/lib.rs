@@ -3,28 +3,47 @@ extern crate byteorder;
 #[macro_use]
 extern crate log;
 
+extern crate impl_serde;
 extern crate keccak_hash;
 extern crate parity_wasm;
 extern crate primitive_types;
 extern crate pwasm_utils;
+extern crate rlp;
+extern crate serde;
+extern crate serde_json;
 extern crate snafu;
 extern crate wasmi;
 
+#[cfg(test)]
+extern crate proptest;
+
 pub mod address;
+pub mod debugger;
 pub mod error;
 pub mod execute;
+pub mod limits;
 pub mod log_entry;
+pub mod outcome;
+pub mod panic_payload;
+pub mod profiler;
 pub mod provider;
+pub mod schedule;
+pub mod state_trie;
+pub mod tracer;
 pub mod transaction;
+pub mod trie;
+pub mod wasm_cost;
+pub mod witness;
 
 mod env;
-mod panic_payload;
+mod frame;
+mod hooks;
 mod parser;
+mod prefetch;
 mod runtime;
-mod schedule;
 mod state;
+mod trap_site;
 mod types;
 mod utils;
-mod wasm_cost;
 
 pub type Bytes = Vec<u8>;
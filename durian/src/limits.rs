@@ -0,0 +1,64 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Resource the execution ran out of, see `ExecutionLimits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+	/// The contract requested more linear memory than `max_memory_pages`
+	MemoryPages,
+	/// The contract called the host functions more than `max_host_calls` times
+	HostCalls,
+	/// The contract returned or reverted with more than `max_return_data` bytes
+	ReturnData,
+	/// The execution didn't finish before the deadline
+	Deadline,
+	/// The asynchronous execution missed state more than `max_fetch_rounds` times
+	FetchRounds,
+}
+
+impl fmt::Display for Limit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Limit::MemoryPages => write!(f, "memory pages"),
+			Limit::HostCalls => write!(f, "host calls"),
+			Limit::ReturnData => write!(f, "return data size"),
+			Limit::Deadline => write!(f, "deadline"),
+			Limit::FetchRounds => write!(f, "fetch rounds"),
+		}
+	}
+}
+
+/// Resource limits of the execution, a safety net in addition to the gas.
+///
+/// The gas should make the limits unreachable for a well-behaved contract,
+/// but a mispriced instruction or host function can't stall the execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionLimits {
+	/// Maximum linear memory of a contract, in wasm pages (64KB), initial or grown
+	pub max_memory_pages: u32,
+	/// Maximum number of the host function calls in a transaction, gas metering excluded
+	pub max_host_calls: u64,
+	/// Maximum size of the data a contract returns or reverts with
+	pub max_return_data: usize,
+	/// Wall-clock time the execution should finish in, counted from the start of the execution.
+	///
+	/// Unlike the other limits, reaching the deadline depends on the machine,
+	/// so the transaction shouldn't be included in a block.
+	pub deadline: Option<Duration>,
+	/// Maximum number of times `execute::execute_async` fetches the missing state and executes
+	/// the transaction again. Past the limit it fails with `Error::LimitExceeded`.
+	pub max_fetch_rounds: usize,
+}
+
+impl Default for ExecutionLimits {
+	fn default() -> Self {
+		ExecutionLimits {
+			max_memory_pages: 16,
+			max_host_calls: 1_000_000,
+			// all the memory
+			max_return_data: 16 * 64 * 1024,
+			deadline: None,
+			max_fetch_rounds: 32,
+		}
+	}
+}
@@ -0,0 +1,166 @@
+use debugger::Location;
+use limits::Limit;
+use panic_payload::PanicPayload;
+use std::fmt;
+use wasmi;
+
+/// Reason of a wasm trap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrapKind {
+	/// `unreachable` instruction was executed
+	Unreachable,
+	/// Memory was accessed out of its bounds
+	MemoryAccessOutOfBounds,
+	/// Table was accessed out of its bounds
+	TableAccessOutOfBounds,
+	/// Indirect call to an uninitialized table element
+	ElemUninitialized,
+	/// Integer division by zero
+	DivisionByZero,
+	/// Float to integer conversion overflowed
+	InvalidConversionToInt,
+	/// Indirect call with an unexpected signature
+	UnexpectedSignature,
+	/// Call stack was exhausted
+	StackOverflow,
+	/// A host function rejected the call, e.g. the contract passed an invalid pointer
+	Host(String),
+}
+
+impl<'a> From<&'a wasmi::TrapKind> for TrapKind {
+	fn from(kind: &'a wasmi::TrapKind) -> Self {
+		match *kind {
+			wasmi::TrapKind::Unreachable => TrapKind::Unreachable,
+			wasmi::TrapKind::MemoryAccessOutOfBounds => TrapKind::MemoryAccessOutOfBounds,
+			wasmi::TrapKind::TableAccessOutOfBounds => TrapKind::TableAccessOutOfBounds,
+			wasmi::TrapKind::ElemUninitialized => TrapKind::ElemUninitialized,
+			wasmi::TrapKind::DivisionByZero => TrapKind::DivisionByZero,
+			wasmi::TrapKind::InvalidConversionToInt => TrapKind::InvalidConversionToInt,
+			wasmi::TrapKind::UnexpectedSignature => TrapKind::UnexpectedSignature,
+			wasmi::TrapKind::StackOverflow => TrapKind::StackOverflow,
+			wasmi::TrapKind::Host(ref err) => TrapKind::Host(format!("{}", err)),
+		}
+	}
+}
+
+impl fmt::Display for TrapKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			TrapKind::Unreachable => write!(f, "unreachable"),
+			TrapKind::MemoryAccessOutOfBounds => write!(f, "memory access out of bounds"),
+			TrapKind::TableAccessOutOfBounds => write!(f, "table access out of bounds"),
+			TrapKind::ElemUninitialized => write!(f, "uninitialized table element"),
+			TrapKind::DivisionByZero => write!(f, "division by zero"),
+			TrapKind::InvalidConversionToInt => write!(f, "invalid conversion to integer"),
+			TrapKind::UnexpectedSignature => write!(f, "unexpected signature"),
+			TrapKind::StackOverflow => write!(f, "stack overflow"),
+			TrapKind::Host(ref msg) => write!(f, "host error: {}", msg),
+		}
+	}
+}
+
+/// Instruction of the contract code that trapped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrapLocation {
+	/// Index of the function in the module function space, imported functions included
+	pub function: u32,
+	/// Index of the instruction in the function body, recorded only in the debug and the profiling modes
+	pub offset: Option<u32>,
+	/// Name of the function from the `name` section of the contract, if any
+	pub function_name: Option<String>,
+}
+
+impl fmt::Display for TrapLocation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.offset {
+			Some(offset) => write!(f, "{}", Location { function: self.function, offset })?,
+			None => write!(f, "func[{}]", self.function)?,
+		}
+		if let Some(ref name) = self.function_name {
+			write!(f, " <{}>", name)?;
+		}
+		Ok(())
+	}
+}
+
+/// How the execution of a transaction ended.
+///
+/// Only `Success` changes the state. `Revert` and `Panic` refund the gas left,
+/// the other failures consume all the gas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+	/// Contract returned (or suicided) normally
+	Success,
+	/// Contract reverted with the given data
+	Revert(Vec<u8>),
+	/// Execution ran out of gas, including failing to pay the code deposit
+	OutOfGas,
+	/// Execution trapped, at the location if it is known.
+	///
+	/// A trap raised by a host function is located at the call to the host function.
+	Trap(TrapKind, Option<TrapLocation>),
+	/// Contract panicked
+	Panic(PanicPayload),
+	/// Contract code can't be executed, e.g. it is malformed or doesn't export the entry point
+	InvalidCode(String),
+	/// Execution exceeded a limit of `Schedule::limits`
+	LimitExceeded(Limit),
+	/// Code returned by the constructor is larger than `Schedule::create_data_limit`
+	CodeSizeLimit { size: usize, limit: usize },
+}
+
+impl ExecutionOutcome {
+	/// Returns true if the execution succeeded
+	pub fn is_success(&self) -> bool {
+		*self == ExecutionOutcome::Success
+	}
+
+	/// Returns true if the execution failure consumes all the gas
+	pub fn consumes_all_gas(&self) -> bool {
+		match *self {
+			ExecutionOutcome::Success
+			| ExecutionOutcome::Revert(_)
+			| ExecutionOutcome::Panic(_) => false,
+			ExecutionOutcome::OutOfGas
+			| ExecutionOutcome::Trap(..)
+			| ExecutionOutcome::InvalidCode(_)
+			| ExecutionOutcome::LimitExceeded(_)
+			| ExecutionOutcome::CodeSizeLimit { .. } => true,
+		}
+	}
+}
+
+impl fmt::Display for ExecutionOutcome {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ExecutionOutcome::Success => write!(f, "Success"),
+			ExecutionOutcome::Revert(_) => write!(f, "Reverted"),
+			ExecutionOutcome::OutOfGas => write!(f, "Out of gas"),
+			ExecutionOutcome::Trap(ref kind, None) => write!(f, "Trap: {}", kind),
+			ExecutionOutcome::Trap(ref kind, Some(ref location)) => {
+				write!(f, "Trap: {} in {}", kind, location)
+			}
+			ExecutionOutcome::Panic(ref payload) => write!(
+				f,
+				"Panic: {}, {}:{}:{}",
+				payload
+					.msg
+					.as_ref()
+					.map(String::as_ref)
+					.unwrap_or("<msg was stripped>"),
+				payload
+					.file
+					.as_ref()
+					.map(String::as_ref)
+					.unwrap_or("<unknown>"),
+				payload.line.unwrap_or(0),
+				payload.col.unwrap_or(0)
+			),
+			ExecutionOutcome::InvalidCode(ref msg) => write!(f, "Invalid code: {}", msg),
+			ExecutionOutcome::LimitExceeded(ref limit) => write!(f, "Limit exceeded: {}", limit),
+			ExecutionOutcome::CodeSizeLimit { size, limit } => {
+				write!(f, "Code size {} exceeds the limit {}", size, limit)
+			}
+		}
+	}
+}
@@ -1,7 +1,8 @@
 use byteorder::{LittleEndian, ReadBytesExt};
 use std::io::{self, Read};
 
-#[derive(Debug, PartialEq, Eq)]
+/// Panic message and location, as encoded by the contract
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PanicPayload {
 	pub msg: Option<String>,
 	pub file: Option<String>,
@@ -1,8 +1,24 @@
+use debugger::Location;
+use env::ImportResolver;
 use error::Error;
+use hooks::{self, DebugInfo, HookSites};
+use limits::Limit;
 use parity_wasm::elements::{self, Deserialize};
+use primitive_types::U256;
 use pwasm_utils::{self, rules};
-use types::ActionParams;
+use schedule::Schedule;
+use std::collections::HashMap;
+use std::mem;
+use trap_site;
+use types::{ActionParams, ActionType};
 use wasm_cost::WasmCosts;
+use wasmi;
+use super::Bytes;
+
+/// Entry point of the deployed contract, invoked on every call
+pub const CALL_EXPORT: &str = "call";
+/// Entry point of the contract constructor, invoked once at creation
+pub const DEPLOY_EXPORT: &str = "deploy";
 
 fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
 	rules::Set::new(wasm_costs.regular, {
@@ -29,40 +45,414 @@ fn gas_rules(wasm_costs: &WasmCosts) -> rules::Set {
 	.with_forbidden_floats()
 }
 
-/// Splits payload to code and data according to params_type, also
-/// loads the module instance from payload and injects gas counter according
-/// to schedule.
-pub fn payload<'a>(params: &'a ActionParams, wasm_costs: &WasmCosts) -> Result<elements::Module, Error> {
-	let mut cursor = ::std::io::Cursor::new(&params.code[..]);
+fn deserialize(code: &[u8]) -> Result<elements::Module, Error> {
+	let mut cursor = ::std::io::Cursor::new(code);
 
 	let deserialized_module =
 		elements::Module::deserialize(&mut cursor).map_err(|err| Error::Wasm {
 			msg: format!("Error deserializing contract code ({:?})", err),
 		})?;
+	check_internal_memory(&deserialized_module)?;
 
-	if deserialized_module
-		.memory_section()
-		.map_or(false, |ms| ms.entries().len() > 0)
-	{
-		// According to WebAssembly spec, internal memory is hidden from embedder and should not
-		// be interacted with. So we disable this kind of modules at decoding level.
+	Ok(deserialized_module)
+}
+
+fn check_internal_memory(module: &elements::Module) -> Result<(), Error> {
+	// According to WebAssembly spec, internal memory is hidden from embedder and should not
+	// be interacted with. So we disable this kind of modules at decoding level.
+	let internal_memories = module.memory_section().map_or(0, |ms| ms.entries().len());
+	if internal_memories != 0 {
 		return Err(Error::Wasm {
-			msg: format!("Malformed wasm module: internal memory"),
+			msg: "Malformed wasm module: internal memory".to_string(),
 		});
 	}
 
-	let contract_module =
-		pwasm_utils::inject_gas_counter(deserialized_module, &gas_rules(wasm_costs)).map_err(
-			|_| Error::Wasm {
-				msg: format!("Wasm contract error: bytecode invalid"),
-			},
-		)?;
+	Ok(())
+}
+
+fn invalid_code(msg: String) -> Error {
+	Error::InvalidCode { msg }
+}
+
+fn is_float_type(value_type: &elements::ValueType) -> bool {
+	match *value_type {
+		elements::ValueType::F32 | elements::ValueType::F64 => true,
+		_ => false,
+	}
+}
+
+fn is_float_instruction(instruction: &elements::Instruction) -> bool {
+	match rules::InstructionType::op(instruction) {
+		rules::InstructionType::Float
+		| rules::InstructionType::FloatConst
+		| rules::InstructionType::FloatComparsion
+		| rules::InstructionType::FloatConversion
+		| rules::InstructionType::Reinterpretation => true,
+		_ => false,
+	}
+}
+
+fn to_wasmi_type(value_type: &elements::ValueType) -> wasmi::ValueType {
+	match *value_type {
+		elements::ValueType::I32 => wasmi::ValueType::I32,
+		elements::ValueType::I64 => wasmi::ValueType::I64,
+		elements::ValueType::F32 => wasmi::ValueType::F32,
+		elements::ValueType::F64 => wasmi::ValueType::F64,
+	}
+}
+
+fn function_type(module: &elements::Module, type_idx: u32) -> Result<&elements::FunctionType, Error> {
+	let types = module.type_section().map(|ts| ts.types()).unwrap_or(&[]);
+	match types.get(type_idx as usize) {
+		Some(elements::Type::Function(func_type)) => Ok(func_type),
+		None => Err(invalid_code(format!("type index {} out of bounds", type_idx))),
+	}
+}
+
+fn validate_imports(module: &elements::Module, resolver: &ImportResolver) -> Result<(), Error> {
+	let imports = module.import_section().map(|is| is.entries()).unwrap_or(&[]);
+	for import in imports {
+		if import.module() != "env" {
+			return Err(invalid_code(format!(
+				"import {}.{} is not provided by the runtime",
+				import.module(),
+				import.field()
+			)));
+		}
+
+		match *import.external() {
+			elements::External::Function(type_idx) => {
+				let func_type = function_type(module, type_idx)?;
+				let params: Vec<wasmi::ValueType> =
+					func_type.params().iter().map(to_wasmi_type).collect();
+				let return_type = func_type.return_type().as_ref().map(to_wasmi_type);
+				if !resolver.has_func(import.field(), &params, return_type) {
+					return Err(invalid_code(format!(
+						"function import {} is not provided by the runtime",
+						import.field()
+					)));
+				}
+			}
+			elements::External::Memory(ref memory_type) => {
+				let limits = memory_type.limits();
+				if import.field() != "memory" {
+					return Err(invalid_code(format!(
+						"memory imported under unknown name {}",
+						import.field()
+					)));
+				}
+				check_memory(limits, resolver.max_memory())?;
+			}
+			_ => {
+				return Err(invalid_code(format!(
+					"import {} is neither a function nor memory",
+					import.field()
+				)));
+			}
+		}
+	}
 
-	let contract_module =
-		pwasm_utils::stack_height::inject_limiter(contract_module, wasm_costs.max_stack_height)
-			.map_err(|_| Error::Wasm {
-				msg: format!("Wasm contract error: stack limiter failure"),
-			})?;
+	Ok(())
+}
+
+fn check_memory(limits: &elements::ResizableLimits, max_memory: u32) -> Result<(), Error> {
+	if limits.initial() > max_memory || limits.maximum().map_or(false, |max| max > max_memory) {
+		return Err(Error::LimitExceeded {
+			limit: Limit::MemoryPages,
+		});
+	}
+
+	Ok(())
+}
+
+/// Checks that the memory imported by the module is within `max_memory` pages.
+/// `validate` checks it too, the limit may be lower when the contract is called.
+pub fn check_memory_limit(module: &elements::Module, max_memory: u32) -> Result<(), Error> {
+	let imports = module.import_section().map(|is| is.entries()).unwrap_or(&[]);
+	for import in imports {
+		if let elements::External::Memory(ref memory_type) = *import.external() {
+			check_memory(memory_type.limits(), max_memory)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn validate_floats(module: &elements::Module) -> Result<(), Error> {
+	let types = module.type_section().map(|ts| ts.types()).unwrap_or(&[]);
+	for elements::Type::Function(func_type) in types {
+		if func_type.params().iter().any(is_float_type)
+			|| func_type.return_type().as_ref().map_or(false, is_float_type)
+		{
+			return Err(invalid_code(format!("floating point types are forbidden")));
+		}
+	}
+
+	let globals = module.global_section().map(|gs| gs.entries()).unwrap_or(&[]);
+	if globals
+		.iter()
+		.any(|global| is_float_type(&global.global_type().content_type()))
+	{
+		return Err(invalid_code(format!("floating point globals are forbidden")));
+	}
+
+	let bodies = module.code_section().map(|cs| cs.bodies()).unwrap_or(&[]);
+	for body in bodies {
+		if body.locals().iter().any(|local| is_float_type(&local.value_type())) {
+			return Err(invalid_code(format!("floating point locals are forbidden")));
+		}
+		if body.code().elements().iter().any(is_float_instruction) {
+			return Err(invalid_code(format!("floating point instructions are forbidden")));
+		}
+	}
+
+	Ok(())
+}
+
+fn validate_exports(module: &elements::Module, action_type: &ActionType) -> Result<(), Error> {
+	let exports = module.export_section().map(|es| es.entries()).unwrap_or(&[]);
+	for export in exports {
+		match (export.field(), export.internal()) {
+			(CALL_EXPORT, elements::Internal::Function(_)) => {}
+			(DEPLOY_EXPORT, elements::Internal::Function(_)) => {}
+			(CALL_EXPORT, _) | (DEPLOY_EXPORT, _) => {
+				return Err(invalid_code(format!(
+					"export {} is not a function",
+					export.field()
+				)));
+			}
+			_ => {}
+		}
+	}
+
+	match *action_type {
+		// A constructor exporting `deploy` is stored as the contract code, so it needs `call` too
+		ActionType::Create => {
+			if !has_export(module, CALL_EXPORT) {
+				return Err(Error::MissingExport {
+					name: format!("`{}`", CALL_EXPORT),
+				});
+			}
+		}
+		ActionType::Call => {
+			if !has_export(module, CALL_EXPORT) {
+				return Err(Error::MissingExport {
+					name: format!("`{}`", CALL_EXPORT),
+				});
+			}
+			if has_export(module, DEPLOY_EXPORT) {
+				return Err(invalid_code(format!(
+					"contract code should not export `{}`",
+					DEPLOY_EXPORT
+				)));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Checks whether the module exports a function called `name`
+pub fn has_export(module: &elements::Module, name: &str) -> bool {
+	module
+		.export_section()
+		.map(|es| es.entries())
+		.unwrap_or(&[])
+		.iter()
+		.any(|export| match (export.field(), export.internal()) {
+			(field, elements::Internal::Function(_)) => field == name,
+			_ => false,
+		})
+}
+
+/// Validates contract code before it is executed for the first time.
+///
+/// The module should import only what the runtime provides and use neither internal memory nor floats.
+/// Constructor code (`ActionType::Create`) should export `call` and may export `deploy`,
+/// contract code (`ActionType::Call`) should export `call` and must not export `deploy`.
+pub fn validate(code: &[u8], action_type: &ActionType, resolver: &ImportResolver) -> Result<elements::Module, Error> {
+	let module = deserialize(code)?;
+	validate_imports(&module, resolver)?;
+	validate_floats(&module)?;
+	validate_exports(&module, action_type)?;
+
+	Ok(module)
+}
+
+fn inject_gas_counter(module: elements::Module, version: &U256, wasm_costs: &WasmCosts) -> Result<elements::Module, Error> {
+	if !version.is_zero() {
+		return Err(Error::UnsupportedCodeVersion { version: *version });
+	}
+
+	pwasm_utils::inject_gas_counter(module, &gas_rules(wasm_costs)).map_err(|_| Error::Wasm {
+		msg: format!("Wasm contract error: bytecode invalid"),
+	})
+}
+
+fn inject_stack_limiter(module: elements::Module, wasm_costs: &WasmCosts) -> Result<elements::Module, Error> {
+	pwasm_utils::stack_height::inject_limiter(module, wasm_costs.max_stack_height).map_err(|_| {
+		Error::Wasm {
+			msg: format!("Wasm contract error: stack limiter failure"),
+		}
+	})
+}
+
+/// Injects gas counter and stack limiter according to the instrumentation `version`,
+/// recording the function a trap happens in, see `trap_site`.
+pub fn instrument(module: elements::Module, version: &U256, wasm_costs: &WasmCosts) -> Result<elements::Module, Error> {
+	let contract_module = inject_gas_counter(module.clone(), version, wasm_costs)?;
+	let (contract_module, trap_global) = trap_site::record_functions(&module, contract_module)?;
+	let mut contract_module = inject_stack_limiter(contract_module, wasm_costs)?;
+	trap_site::set_global_index(&mut contract_module, trap_global);
 
 	Ok(contract_module)
 }
+
+/// Instruments the module like `instrument`, also injecting the hooks for the debugger
+/// or the profiler and recording the trap sites, see `trap_site`.
+///
+/// The gas is charged as in the normal execution, but the hooks use the stack,
+/// so the stack limit may be reached a bit earlier.
+pub fn instrument_with_hooks(module: elements::Module, version: &U256, wasm_costs: &WasmCosts, hook_sites: HookSites) -> Result<(elements::Module, DebugInfo), Error> {
+	let contract_module = inject_gas_counter(module.clone(), version, wasm_costs)?;
+	let (contract_module, debug_info) = hooks::inject(&module, contract_module, hook_sites)?;
+	let mut contract_module = inject_stack_limiter(contract_module, wasm_costs)?;
+	trap_site::set_global_index(&mut contract_module, debug_info.trap_global());
+
+	Ok((contract_module, debug_info))
+}
+
+/// Rewrites the contract functions of `module`, which is `original` with the gas counter injected.
+///
+/// `rewrite` is called for every instruction of the original code with the index of its
+/// function body, its location and the original instruction. It gets the instruction
+/// to execute and should push it to the body along with the injected instructions.
+/// Metering instructions are kept as they are.
+pub fn rewrite_contract_code<F>(original: &elements::Module, module: &mut elements::Module, mut rewrite: F) -> Result<(), Error>
+where
+	F: FnMut(usize, Location, &elements::Instruction, elements::Instruction, &mut Vec<elements::Instruction>) -> Result<(), Error>,
+{
+	let imported = original.import_count(elements::ImportCountType::Function) as u32;
+	// the gas counter is imported after the contract imports
+	let gas_func = imported;
+	let original_bodies = original.code_section().map(|cs| cs.bodies()).unwrap_or(&[]);
+	let bodies = match module.code_section_mut() {
+		Some(code_section) => &mut code_section.bodies_mut()[..],
+		None => &mut [][..],
+	};
+
+	// the gas counter may add a function, it is not a part of the contract
+	for (body_idx, body) in bodies.iter_mut().enumerate().take(original_bodies.len()) {
+		let function = imported + body_idx as u32;
+		let original_code = original_bodies[body_idx].code().elements();
+		let mismatch = || Error::Wasm {
+			msg: format!("Function {} doesn't match the original code", function),
+		};
+
+		let instructions = mem::take(body.code_mut().elements_mut());
+		let mut rewritten = Vec::with_capacity(instructions.len());
+		let mut offset = 0;
+		let mut iter = instructions.into_iter().peekable();
+		while let Some(instruction) = iter.next() {
+			// metering instructions are not a part of the contract
+			if let elements::Instruction::I32Const(_) = instruction {
+				if iter.peek() == Some(&elements::Instruction::Call(gas_func)) {
+					rewritten.push(instruction);
+					rewritten.extend(iter.next());
+					continue;
+				}
+			}
+
+			let original_instruction = original_code.get(offset).ok_or_else(mismatch)?;
+			let location = Location {
+				function,
+				offset: offset as u32,
+			};
+			rewrite(body_idx, location, original_instruction, instruction, &mut rewritten)?;
+			offset += 1;
+		}
+
+		if offset != original_code.len() {
+			return Err(mismatch());
+		}
+		*body.code_mut().elements_mut() = rewritten;
+	}
+
+	Ok(())
+}
+
+/// Names of the functions from the `name` section of the module
+pub fn function_names(module: &elements::Module) -> HashMap<u32, String> {
+	let module = match module.clone().parse_names() {
+		Ok(module) => module,
+		Err((_, module)) => module,
+	};
+
+	module
+		.names_section()
+		.and_then(|names| names.functions())
+		.map(|functions| {
+			functions
+				.names()
+				.iter()
+				.map(|(idx, name)| (idx, name.clone()))
+				.collect()
+		})
+		.unwrap_or_default()
+}
+
+/// Validates and instruments contract code, returning the artifact
+/// that should be stored along with the contract.
+pub fn prepare(code: &[u8], version: &U256, schedule: &Schedule, resolver: &ImportResolver) -> Result<Bytes, Error> {
+	let module = validate(code, &ActionType::Call, resolver)?;
+	let module = instrument(module, version, schedule.wasm()?)?;
+
+	module.to_bytes().map_err(|err| Error::Wasm {
+		msg: format!("Error serializing prepared code ({:?})", err),
+	})
+}
+
+/// Turns a constructor module into the contract code by removing the `deploy` export,
+/// so the constructor can't be invoked once the contract is deployed.
+pub fn runtime_code(mut module: elements::Module) -> Result<Bytes, Error> {
+	if let Some(export_section) = module.export_section_mut() {
+		export_section
+			.entries_mut()
+			.retain(|export| export.field() != DEPLOY_EXPORT);
+	}
+
+	module.to_bytes().map_err(|err| Error::Wasm {
+		msg: format!("Error serializing contract code ({:?})", err),
+	})
+}
+
+/// Loads an artifact that was created by `prepare`.
+/// The artifact is read back from the provider, so it is validated again like the contract code.
+pub fn load_prepared(artifact: &[u8], resolver: &ImportResolver) -> Result<elements::Module, Error> {
+	let module = elements::Module::from_bytes(artifact).map_err(|err| Error::Wasm {
+		msg: format!("Error deserializing prepared code ({:?})", err),
+	})?;
+	check_internal_memory(&module)?;
+	validate_imports(&module, resolver)?;
+	validate_floats(&module)?;
+	validate_exports(&module, &ActionType::Call)?;
+
+	Ok(module)
+}
+
+/// Splits payload to code and data according to params_type, also
+/// loads the module instance from payload and injects gas counter according
+/// to schedule.
+pub fn payload<'a>(params: &'a ActionParams, wasm_costs: &WasmCosts) -> Result<elements::Module, Error> {
+	let deserialized_module = deserialize(&params.code[..])?;
+
+	instrument(deserialized_module, &params.code_version, wasm_costs)
+}
+
+/// Loads the contract code of the `Call` action, instrumenting it like `payload`
+/// and injecting the hooks.
+pub fn payload_with_hooks<'a>(params: &'a ActionParams, wasm_costs: &WasmCosts, hook_sites: HookSites) -> Result<(elements::Module, DebugInfo), Error> {
+	let deserialized_module = deserialize(&params.code[..])?;
+
+	instrument_with_hooks(deserialized_module, &params.code_version, wasm_costs, hook_sites)
+}
@@ -0,0 +1,268 @@
+//! State fetched from an `AsyncProvider`, see `execute::execute_async`.
+//!
+//! The transaction is executed against the state fetched so far. Reading a part of the state
+//! that isn't fetched yet records it as missing, so it can be fetched before executing
+//! the transaction again. The changes of the execution are kept to be applied
+//! to the `AsyncProvider` afterwards.
+
+use address::Address;
+use error::Error;
+use primitive_types::{H256, U256};
+use provider::{AsyncProvider, BlockContext, Provider, ProviderFuture, StateAccount, StateChangeSet};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::task::{Context, Poll};
+
+/// Part of the state that wasn't fetched yet
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Exist(Address),
+    Account(Address),
+    Storage(Address, H256),
+    BlockHash(u64),
+    Code(H256),
+    PreparedCode(H256, U256),
+}
+
+/// Fetch of a part of the state from the `AsyncProvider`
+pub enum Fetch {
+    Exist(Address, ProviderFuture<bool>),
+    Accounts(Vec<Address>, ProviderFuture<Vec<Option<StateAccount>>>),
+    Storages(Vec<(Address, H256)>, ProviderFuture<Vec<Option<H256>>>),
+    BlockHash(u64, ProviderFuture<H256>),
+    Code(H256, ProviderFuture<Option<Vec<u8>>>),
+    PreparedCode(H256, U256, ProviderFuture<Option<Vec<u8>>>),
+    BlockContext(ProviderFuture<BlockContext>),
+}
+
+impl Fetch {
+    /// Fetches the missing parts of the state, the accounts and the storage in one batch each
+    pub fn missing(provider: &dyn AsyncProvider, missing: HashSet<Key>) -> Vec<Fetch> {
+        let mut fetches = vec![];
+        let mut addresses = vec![];
+        let mut keys = vec![];
+        for key in missing {
+            match key {
+                Key::Exist(address) => fetches.push(Fetch::Exist(address, provider.exist(&address))),
+                Key::Account(address) => addresses.push(address),
+                Key::Storage(address, key) => keys.push((address, key)),
+                Key::BlockHash(block_no) => fetches.push(Fetch::BlockHash(block_no, provider.block_hash(block_no))),
+                Key::Code(code_hash) => fetches.push(Fetch::Code(code_hash, provider.code_by_hash(&code_hash))),
+                Key::PreparedCode(code_hash, version) => {
+                    let future = provider.prepared_code(&code_hash, &version);
+                    fetches.push(Fetch::PreparedCode(code_hash, version, future));
+                }
+            }
+        }
+        fetches.extend(Fetch::batch(provider, addresses, keys));
+        fetches
+    }
+
+    /// Fetches the accounts and the storage in one batch each
+    pub fn batch(provider: &dyn AsyncProvider, addresses: Vec<Address>, keys: Vec<(Address, H256)>) -> Vec<Fetch> {
+        let mut fetches = vec![];
+        if !addresses.is_empty() {
+            let future = provider.accounts(&addresses);
+            fetches.push(Fetch::Accounts(addresses, future));
+        }
+        if !keys.is_empty() {
+            let future = provider.storages(&keys);
+            fetches.push(Fetch::Storages(keys, future));
+        }
+        fetches
+    }
+
+    /// Polls the fetch, adding the fetched value to `state` when it's ready
+    pub fn poll(&mut self, cx: &mut Context, state: &mut Prefetched) -> Poll<Result<(), Error>> {
+        match self {
+            Fetch::Exist(address, future) => future.as_mut().poll(cx).map_ok(|exist| {
+                state.exist.insert(*address, exist);
+            }),
+            Fetch::Accounts(addresses, future) => future.as_mut().poll(cx).map_ok(|accounts| {
+                for (address, account) in addresses.iter().zip(accounts) {
+                    state.accounts.insert(*address, account);
+                }
+            }),
+            Fetch::Storages(keys, future) => future.as_mut().poll(cx).map_ok(|values| {
+                for (key, value) in keys.iter().zip(values) {
+                    state.storage.insert(*key, value);
+                }
+            }),
+            Fetch::BlockHash(block_no, future) => future.as_mut().poll(cx).map_ok(|hash| {
+                state.block_hashes.insert(*block_no, hash);
+            }),
+            Fetch::Code(code_hash, future) => future.as_mut().poll(cx).map_ok(|code| {
+                state.codes.insert(*code_hash, code);
+            }),
+            Fetch::PreparedCode(code_hash, version, future) => future.as_mut().poll(cx).map_ok(|code| {
+                state.prepared_codes.insert((*code_hash, *version), code);
+            }),
+            Fetch::BlockContext(future) => future.as_mut().poll(cx).map_ok(|block| {
+                state.block = Some(block);
+            }),
+        }
+    }
+}
+
+/// State fetched so far, read by the execution as a `Provider`
+#[derive(Default)]
+pub struct Prefetched {
+    block: Option<BlockContext>,
+    exist: HashMap<Address, bool>,
+    accounts: HashMap<Address, Option<StateAccount>>,
+    storage: HashMap<(Address, H256), Option<H256>>,
+    block_hashes: HashMap<u64, H256>,
+    codes: HashMap<H256, Option<Vec<u8>>>,
+    prepared_codes: HashMap<(H256, U256), Option<Vec<u8>>>,
+    missing: RefCell<HashSet<Key>>,
+    changes: StateChangeSet,
+}
+
+impl Prefetched {
+    /// Returns the state missing in the last execution
+    pub fn take_missing(&mut self) -> HashSet<Key> {
+        mem::take(self.missing.get_mut())
+    }
+
+    /// Returns the changes of the last execution
+    pub fn take_changes(&mut self) -> StateChangeSet {
+        mem::take(&mut self.changes)
+    }
+
+    fn block(&self) -> &BlockContext {
+        self.block
+            .as_ref()
+            .expect("the block context is fetched before the execution")
+    }
+
+    fn missing(&self, key: Key) {
+        self.missing.borrow_mut().insert(key);
+    }
+}
+
+impl Provider for Prefetched {
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        match self.exist.get(address) {
+            Some(exist) => Ok(*exist),
+            None => {
+                self.missing(Key::Exist(*address));
+                Ok(false)
+            }
+        }
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        match self.accounts.get(address) {
+            Some(account) => Ok(account.clone()),
+            None => {
+                self.missing(Key::Account(*address));
+                Ok(None)
+            }
+        }
+    }
+
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.changes.updated.push((*address, *bal, *nonce));
+        Ok(())
+    }
+
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+        self.changes.created.push((*address, code.clone()));
+        Ok(())
+    }
+
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        match self.storage.get(&(*address, *key)) {
+            Some(value) => Ok(*value),
+            None => {
+                self.missing(Key::Storage(*address, *key));
+                Ok(None)
+            }
+        }
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.changes.storage.push((*address, *key, *value));
+        Ok(())
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        match self.codes.get(code_hash) {
+            Some(code) => Ok(code.clone()),
+            None => {
+                // the execution goes on without the code, to find the rest of the missing state
+                self.missing(Key::Code(*code_hash));
+                Ok(Some(vec![]))
+            }
+        }
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.block().timestamp
+    }
+
+    fn block_number(&self) -> u64 {
+        self.block().number
+    }
+
+    fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        match self.block_hashes.get(&block_no) {
+            Some(hash) => Ok(*hash),
+            None => {
+                self.missing(Key::BlockHash(block_no));
+                Ok(H256::zero())
+            }
+        }
+    }
+
+    fn block_author(&self) -> Result<Address, Error> {
+        Ok(self.block().author)
+    }
+
+    fn difficulty(&self) -> Result<U256, Error> {
+        Ok(self.block().difficulty)
+    }
+
+    fn gas_limit(&self) -> Result<U256, Error> {
+        Ok(self.block().gas_limit)
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.block().chain_id
+    }
+
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        Ok(self.block().base_fee)
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(self.block().prev_randao)
+    }
+
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        Ok(self.block().clone())
+    }
+
+    fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        match self.prepared_codes.get(&(*code_hash, *version)) {
+            Some(code) => Ok(code.clone()),
+            None => {
+                self.missing(Key::PreparedCode(*code_hash, *version));
+                Ok(None)
+            }
+        }
+    }
+
+    fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+        self.changes
+            .prepared_codes
+            .push((*code_hash, *version, code.clone()));
+        Ok(())
+    }
+
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        self.changes = changes;
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use primitive_types::U256;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// Gas attributed to a contract function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionGas {
+	pub name: String,
+	/// Gas charged while the function was executing, excluding its callees
+	pub self_gas: U256,
+	/// Gas charged while the function was on the call stack
+	pub total_gas: U256,
+}
+
+/// Gas charged by the transaction per call stack, see `execute::execute_with_profiler`.
+///
+/// Functions are named after the `name` section of the contract, or `func[<index>]`.
+/// Gas charged by a host function, e.g. `env.storage_write`, is attributed to the host function
+/// called by the contract function. Gas charged outside of the contract code is attributed to
+/// `[initial memory]` and `[code deposit]`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasProfile {
+	/// Gas per call stack, the outermost frame first.
+	/// Gas is converted from wasm gas units per stack, so the sum may be slightly less than
+	/// the gas used by the transaction.
+	pub stacks: BTreeMap<Vec<String>, U256>,
+}
+
+impl GasProfile {
+	pub fn new() -> Self {
+		GasProfile::default()
+	}
+
+	/// Total gas in the profile
+	pub fn total_gas(&self) -> U256 {
+		self.stacks
+			.values()
+			.fold(U256::zero(), |total, gas| total + *gas)
+	}
+
+	/// Gas per function, host functions included, the most expensive function first
+	pub fn functions(&self) -> Vec<FunctionGas> {
+		let mut functions: HashMap<&str, (U256, U256)> = HashMap::new();
+		for (stack, gas) in &self.stacks {
+			if let Some(leaf) = stack.last() {
+				functions.entry(leaf).or_default().0 += *gas;
+			}
+
+			let mut seen = Vec::new();
+			for name in stack {
+				// recursive calls are counted once
+				if !seen.contains(&name) {
+					seen.push(name);
+					functions.entry(name).or_default().1 += *gas;
+				}
+			}
+		}
+
+		let mut functions: Vec<FunctionGas> = functions
+			.into_iter()
+			.map(|(name, (self_gas, total_gas))| FunctionGas {
+				name: name.to_owned(),
+				self_gas,
+				total_gas,
+			})
+			.collect();
+		functions.sort_by(|a, b| b.self_gas.cmp(&a.self_gas).then_with(|| a.name.cmp(&b.name)));
+		functions
+	}
+
+	/// Writes the profile in the folded stack format, a line per call stack:
+	/// frames separated by `;` and the gas, which flamegraph tools can read.
+	pub fn write_folded(&self, out: &mut dyn Write) -> io::Result<()> {
+		for (stack, gas) in &self.stacks {
+			let frames: Vec<String> = stack.iter().map(|frame| frame.replace(';', ":")).collect();
+			writeln!(out, "{} {}", frames.join(";"), gas)?;
+		}
+
+		Ok(())
+	}
+}
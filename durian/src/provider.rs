@@ -1,24 +1,331 @@
 use error::Error;
 use primitive_types::{H256, U256};
 use address::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use utils;
 
+pub mod memory;
+
+/// Hash of the empty code, the code hash of the accounts without code
+pub const EMPTY_CODE_HASH: H256 = H256([
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+]);
+
+/// Returns the keccak hash of the code, which providers store the code by
+pub fn code_hash(code: &[u8]) -> H256 {
+    utils::keccak(code)
+}
+
+#[derive(Clone)]
 pub struct StateAccount {
     pub nonce: U256,
     pub balance: U256,
-    pub code: Vec<u8>,
+    /// Keccak hash of the code, see `Provider::code_by_hash`
+    pub code_hash: H256,
+}
+
+/// Context of the block the transaction is executed in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockContext {
+    pub number: u64,
+    /// Unix time of the block, in seconds
+    pub timestamp: u64,
+    pub author: Address,
+    pub difficulty: U256,
+    pub gas_limit: U256,
+    pub chain_id: u64,
+    #[serde(default)]
+    pub base_fee: Option<U256>,
+    #[serde(default)]
+    pub prev_randao: Option<H256>,
 }
 
+/// Changes of the state made by a successful execution, see `Provider::apply_changes`.
+/// The changes are sorted by the address, then by the key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateChangeSet {
+    /// Contracts created by the execution and the accounts whose code it changed, with their code
+    pub created: Vec<(Address, Vec<u8>)>,
+    /// Existing accounts changed by the execution, with their balance and nonce
+    pub updated: Vec<(Address, U256, U256)>,
+    /// Storage written by the execution, as the address, the key and the value
+    pub storage: Vec<(Address, H256, H256)>,
+    /// Prepared code for the code hash and the instrumentation version
+    pub prepared_codes: Vec<(H256, U256, Vec<u8>)>,
+    /// Accounts deleted by the execution, no other change of the set is for them
+    pub deleted: Vec<Address>,
+}
+
+impl StateChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty()
+            && self.updated.is_empty()
+            && self.storage.is_empty()
+            && self.prepared_codes.is_empty()
+            && self.deleted.is_empty()
+    }
+}
+
+/// State and block context the transactions are executed against.
+///
+/// The lookups return `Ok(None)` for the state that doesn't exist. An `Err` is a failure
+/// of the provider, e.g. a lost connection, which aborts the execution
+/// with `Error::ProviderFailure`.
 pub trait Provider {
-    fn exist(&self, address: &Address) -> bool;
-    fn account(&self, address: &Address) -> Result<StateAccount, Error>;
+    fn exist(&self, address: &Address) -> Result<bool, Error>;
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error>;
     fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error>;
     fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error>;
-    fn storage_at(&self, address: &Address, key: &H256) -> Result<H256, Error>;
+    /// Returns `None` for an empty storage slot
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error>;
     fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error>;
+    /// Returns the code with the keccak hash `code_hash`, stored once for all the contracts
+    /// with the same code. The code of the contracts is stored by `create_contract`.
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error>;
     fn timestamp(&self) -> u64;
     fn block_number(&self) -> u64;
     fn block_hash(&self, block_no: u64) -> Result<H256, Error>;
     fn block_author(&self) -> Result<Address, Error>;
     fn difficulty(&self) -> Result<U256, Error>;
     fn gas_limit(&self) -> Result<U256, Error>;
+    /// Chain ID of the chain the transaction is executed on, see EIP-155
+    fn chain_id(&self) -> u64;
+
+    /// Base fee per gas of the block, for chains with EIP-1559 fees
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        Ok(None)
+    }
+
+    /// Random beacon value of the block (`prevRandao` of EIP-4399),
+    /// for chains that provide one. Otherwise contracts get the difficulty.
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(None)
+    }
+
+    /// Returns the context of the block, fetched once per execution.
+    /// The default collects it from the methods above, remote providers
+    /// can override it to fetch the context in one round trip.
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        Ok(BlockContext {
+            number: self.block_number(),
+            timestamp: self.timestamp(),
+            author: self.block_author()?,
+            difficulty: self.difficulty()?,
+            gas_limit: self.gas_limit()?,
+            chain_id: self.chain_id(),
+            base_fee: self.base_fee()?,
+            prev_randao: self.prev_randao()?,
+        })
+    }
+
+    /// Returns the accounts at `addresses`, `None` for the accounts that don't exist.
+    /// The default calls `account` for each address, remote providers can override it
+    /// to fetch the accounts in one round trip.
+    fn accounts(&self, addresses: &[Address]) -> Result<Vec<Option<StateAccount>>, Error> {
+        addresses
+            .iter()
+            .map(|address| self.account(address))
+            .collect()
+    }
+
+    /// Returns the storage values at `keys`, given as the account address and the storage key,
+    /// `None` for the empty slots. The default calls `storage_at` for each key,
+    /// remote providers can override it to fetch the values in one round trip.
+    fn storages(&self, keys: &[(Address, H256)]) -> Result<Vec<Option<H256>>, Error> {
+        keys.iter()
+            .map(|(address, key)| self.storage_at(address, key))
+            .collect()
+    }
+
+    /// Applies the changes of a successful execution in one call.
+    ///
+    /// The default isn't atomic: it checks that the updated accounts exist, deletes the accounts
+    /// with `delete_account`, then applies the other changes one by one with the methods above.
+    /// A failure of the provider in the middle leaves the changes applied before it.
+    /// Providers should override it to apply the changes atomically, remote providers
+    /// to send them in one round trip.
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        for (address, _, _) in &changes.updated {
+            let created = changes.created.iter().any(|(created, _)| created == address);
+            if !created && !self.exist(address)? {
+                return Err(Error::InvalidAddress { address: *address });
+            }
+        }
+
+        // no other change of the set is for the deleted accounts, deleting them first
+        // leaves the state unchanged for the providers not supporting it
+        for address in &changes.deleted {
+            self.delete_account(address)?;
+        }
+        for (address, code) in &changes.created {
+            self.create_contract(address, code)?;
+        }
+        for (address, balance, nonce) in &changes.updated {
+            self.update_account(address, balance, nonce)?;
+        }
+        for (address, key, value) in &changes.storage {
+            self.set_storage(address, key, value)?;
+        }
+        for (code_hash, version, code) in &changes.prepared_codes {
+            self.set_prepared_code(code_hash, version, code)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the account with its code and storage, for the contracts calling `suicide`.
+    /// Providers that can't delete accounts keep the default, the transactions deleting
+    /// an account then fail with `Error::NotSupported`.
+    fn delete_account(&mut self, _address: &Address) -> Result<(), Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Returns the prepared (validated and instrumented) code stored for `code_hash`
+    /// with the given instrumentation `version`. Providers that don't store
+    /// prepared code can keep the default, durian will prepare the code on each call.
+    fn prepared_code(&self, _code_hash: &H256, _version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(None)
+    }
+
+    /// Stores the prepared code for `code_hash` with the given instrumentation `version`.
+    fn set_prepared_code(&mut self, _code_hash: &H256, _version: &U256, _code: &Vec<u8>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Future returned by `AsyncProvider`
+pub type ProviderFuture<T> = Pin<Box<dyn Future<Output = Result<T, Error>>>>;
+
+/// Provider for remote state backends, see `execute::execute_async`.
+///
+/// The methods are the ones of `Provider` returning futures, which must not borrow
+/// the provider, e.g. a request sent to the remote backend.
+pub trait AsyncProvider {
+    fn exist(&self, address: &Address) -> ProviderFuture<bool>;
+    fn account(&self, address: &Address) -> ProviderFuture<Option<StateAccount>>;
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> ProviderFuture<()>;
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> ProviderFuture<()>;
+    fn storage_at(&self, address: &Address, key: &H256) -> ProviderFuture<Option<H256>>;
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> ProviderFuture<()>;
+    fn code_by_hash(&self, code_hash: &H256) -> ProviderFuture<Option<Vec<u8>>>;
+    fn block_hash(&self, block_no: u64) -> ProviderFuture<H256>;
+    fn block_context(&self) -> ProviderFuture<BlockContext>;
+
+    /// Returns the accounts at `addresses` like `Provider::accounts`.
+    /// The default calls `account` for each address concurrently.
+    fn accounts(&self, addresses: &[Address]) -> ProviderFuture<Vec<Option<StateAccount>>> {
+        let futures = addresses.iter().map(|address| self.account(address)).collect();
+        Box::pin(Join::new(futures))
+    }
+
+    /// Returns the storage values at `keys` like `Provider::storages`.
+    /// The default calls `storage_at` for each key concurrently.
+    fn storages(&self, keys: &[(Address, H256)]) -> ProviderFuture<Vec<Option<H256>>> {
+        let futures = keys
+            .iter()
+            .map(|(address, key)| self.storage_at(address, key))
+            .collect();
+        Box::pin(Join::new(futures))
+    }
+
+    /// Applies the changes like `Provider::apply_changes`.
+    /// The default isn't atomic either: it calls the methods above for all the changes at once,
+    /// deletions first, and awaits the returned futures one after the other,
+    /// stopping at the first error.
+    fn apply_changes(&mut self, changes: StateChangeSet) -> ProviderFuture<()> {
+        let mut futures = VecDeque::new();
+        for address in &changes.deleted {
+            futures.push_back(self.delete_account(address));
+        }
+        for (address, code) in &changes.created {
+            futures.push_back(self.create_contract(address, code));
+        }
+        for (address, balance, nonce) in &changes.updated {
+            futures.push_back(self.update_account(address, balance, nonce));
+        }
+        for (address, key, value) in &changes.storage {
+            futures.push_back(self.set_storage(address, key, value));
+        }
+        for (code_hash, version, code) in &changes.prepared_codes {
+            futures.push_back(self.set_prepared_code(code_hash, version, code));
+        }
+        Box::pin(Sequence { futures })
+    }
+
+    /// Deletes the account like `Provider::delete_account`.
+    fn delete_account(&mut self, _address: &Address) -> ProviderFuture<()> {
+        Box::pin(future::ready(Err(Error::NotSupported)))
+    }
+
+    fn prepared_code(&self, _code_hash: &H256, _version: &U256) -> ProviderFuture<Option<Vec<u8>>> {
+        Box::pin(future::ready(Ok(None)))
+    }
+
+    fn set_prepared_code(&mut self, _code_hash: &H256, _version: &U256, _code: &Vec<u8>) -> ProviderFuture<()> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+/// Future of the outputs of `futures` in their order
+struct Join<T> {
+    futures: Vec<ProviderFuture<T>>,
+    outputs: Vec<Option<T>>,
+}
+
+impl<T> Join<T> {
+    fn new(futures: Vec<ProviderFuture<T>>) -> Self {
+        let outputs = futures.iter().map(|_| None).collect();
+        Join { futures, outputs }
+    }
+}
+
+// the outputs are never pinned
+impl<T> Unpin for Join<T> {}
+
+impl<T> Future for Join<T> {
+    type Output = Result<Vec<T>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        for (future, output) in this.futures.iter_mut().zip(this.outputs.iter_mut()) {
+            if output.is_none() {
+                if let Poll::Ready(result) = future.as_mut().poll(cx) {
+                    *output = Some(result?);
+                }
+            }
+        }
+
+        if this.outputs.iter().all(Option::is_some) {
+            Poll::Ready(Ok(this.outputs.iter_mut().map(|output| output.take().unwrap()).collect()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Future awaiting `futures` one after the other
+struct Sequence {
+    futures: VecDeque<ProviderFuture<()>>,
+}
+
+impl Future for Sequence {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while let Some(future) = this.futures.front_mut() {
+            match future.as_mut().poll(cx) {
+                Poll::Ready(Ok(())) => {
+                    this.futures.pop_front();
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
 }
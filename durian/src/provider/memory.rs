@@ -0,0 +1,390 @@
+//! In-memory `Provider`, for tests and tools.
+//!
+//! The state is kept in `BTreeMap`s keyed by the address, so iterating over it is
+//! deterministic. It can be loaded from a genesis in JSON, and saved and restored
+//! with `snapshot` and `restore`. The state trie is built when it is first needed
+//! and kept until the state changes.
+
+use address::Address;
+use error::Error;
+use impl_serde;
+use primitive_types::{H256, U256};
+use provider::{self, BlockContext, Provider, StateAccount, StateChangeSet, EMPTY_CODE_HASH};
+use serde::Deserialize;
+use serde_json;
+use state_trie::{AccountProof, StateTrie, TrieAccount};
+use std::cell::RefCell;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+
+/// Account kept by `MemoryProvider`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryAccount {
+    pub nonce: U256,
+    pub balance: U256,
+    pub code_hash: H256,
+    pub storage: BTreeMap<H256, H256>,
+}
+
+impl Default for MemoryAccount {
+    fn default() -> Self {
+        MemoryAccount {
+            nonce: U256::zero(),
+            balance: U256::zero(),
+            code_hash: EMPTY_CODE_HASH,
+            storage: BTreeMap::new(),
+        }
+    }
+}
+
+/// Saved state of a `MemoryProvider`, see `MemoryProvider::snapshot`
+#[derive(Debug, Clone)]
+pub struct Snapshot(MemoryProvider);
+
+/// Genesis of a `MemoryProvider` in JSON, see `MemoryProvider::from_genesis_json`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Genesis {
+    #[serde(default)]
+    block: Option<BlockContext>,
+    #[serde(default)]
+    accounts: BTreeMap<Address, GenesisAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenesisAccount {
+    #[serde(default)]
+    nonce: U256,
+    #[serde(default)]
+    balance: U256,
+    #[serde(default, with = "impl_serde::serialize")]
+    code: Vec<u8>,
+    #[serde(default)]
+    storage: BTreeMap<H256, H256>,
+}
+
+/// Provider keeping the state and the block context in memory
+#[derive(Debug, Clone)]
+pub struct MemoryProvider {
+    block: BlockContext,
+    block_hashes: BTreeMap<u64, H256>,
+    accounts: BTreeMap<Address, MemoryAccount>,
+    codes: BTreeMap<H256, Vec<u8>>,
+    prepared_codes: BTreeMap<(H256, U256), Vec<u8>>,
+    /// Trie of the accounts, cleared when they change
+    trie: RefCell<Option<StateTrie>>,
+}
+
+/// Compares the state and the block context, the cached trie is left out
+impl PartialEq for MemoryProvider {
+    fn eq(&self, other: &Self) -> bool {
+        self.block == other.block
+            && self.block_hashes == other.block_hashes
+            && self.accounts == other.accounts
+            && self.codes == other.codes
+            && self.prepared_codes == other.prepared_codes
+    }
+}
+
+impl Default for MemoryProvider {
+    fn default() -> Self {
+        MemoryProvider::new(BlockContext {
+            number: 0,
+            timestamp: 0,
+            author: Address::zero(),
+            difficulty: U256::zero(),
+            gas_limit: U256::from(10_000_000),
+            chain_id: 1,
+            base_fee: None,
+            prev_randao: None,
+        })
+    }
+}
+
+impl MemoryProvider {
+    /// Creates an empty provider executing the transactions in the `block`
+    pub fn new(block: BlockContext) -> Self {
+        MemoryProvider {
+            block,
+            block_hashes: BTreeMap::new(),
+            accounts: BTreeMap::new(),
+            codes: BTreeMap::new(),
+            prepared_codes: BTreeMap::new(),
+            trie: RefCell::new(None),
+        }
+    }
+
+    /// Loads the provider from a genesis in JSON: an optional `block` context and
+    /// the `accounts` by their address, with an optional `nonce`, `balance`, `code`
+    /// and `storage`. The numbers and the data are hex strings.
+    pub fn from_genesis_json(json: &str) -> Result<Self, Error> {
+        let genesis: Genesis = serde_json::from_str(json).map_err(|err| Error::InvalidGenesis {
+            msg: err.to_string(),
+        })?;
+
+        let mut memory = match genesis.block {
+            Some(block) => MemoryProvider::new(block),
+            None => MemoryProvider::default(),
+        };
+        for (address, account) in genesis.accounts {
+            let code_hash = memory.insert_code(account.code);
+            memory.accounts.insert(
+                address,
+                MemoryAccount {
+                    nonce: account.nonce,
+                    balance: account.balance,
+                    code_hash,
+                    storage: account.storage,
+                },
+            );
+        }
+        Ok(memory)
+    }
+
+    pub fn block(&self) -> &BlockContext {
+        &self.block
+    }
+
+    /// Context of the block the next transactions are executed in
+    pub fn set_block(&mut self, block: BlockContext) {
+        self.block = block;
+    }
+
+    pub fn set_block_hash(&mut self, block_no: u64, hash: H256) {
+        self.block_hashes.insert(block_no, hash);
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&MemoryAccount> {
+        self.accounts.get(address)
+    }
+
+    /// Inserts the account, replacing the account at `address`.
+    /// Its code hash must be `EMPTY_CODE_HASH` or the hash of a code inserted with `insert_code`.
+    pub fn insert_account(&mut self, address: Address, account: MemoryAccount) {
+        self.clear_trie();
+        self.accounts.insert(address, account);
+    }
+
+    pub fn remove_account(&mut self, address: &Address) -> Option<MemoryAccount> {
+        self.clear_trie();
+        self.accounts.remove(address)
+    }
+
+    /// Stores the code and returns its hash
+    pub fn insert_code(&mut self, code: Vec<u8>) -> H256 {
+        if code.is_empty() {
+            return EMPTY_CODE_HASH;
+        }
+
+        let code_hash = provider::code_hash(&code);
+        self.codes.insert(code_hash, code);
+        code_hash
+    }
+
+    /// Iterates over the accounts in the order of their address
+    pub fn iter<'a>(&'a self) -> btree_map::Iter<'a, Address, MemoryAccount> {
+        self.accounts.iter()
+    }
+
+    /// Tries of the state, to compute the state root
+    pub fn state_trie(&self) -> Result<StateTrie, Error> {
+        self.with_trie(|trie| Ok(trie.clone()))
+    }
+
+    /// Returns the proof of the account and its storage at the `storage_keys`,
+    /// against the root of `state_trie`
+    pub fn get_proof(&self, address: &Address, storage_keys: &[H256]) -> Result<AccountProof, Error> {
+        self.with_trie(|trie| trie.get_proof(address, storage_keys))
+    }
+
+    /// Calls `f` with the trie of the state, building it if the state changed since it was last built
+    fn with_trie<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&StateTrie) -> Result<T, Error>,
+    {
+        if let Some(ref trie) = *self.trie.borrow() {
+            return f(trie);
+        }
+
+        let trie = self.build_trie()?;
+        let result = f(&trie);
+        *self.trie.borrow_mut() = Some(trie);
+        result
+    }
+
+    fn build_trie(&self) -> Result<StateTrie, Error> {
+        let mut state = StateTrie::new();
+        for (address, account) in &self.accounts {
+            let account_trie = TrieAccount {
+                nonce: account.nonce,
+                balance: account.balance,
+                code_hash: account.code_hash,
+                ..Default::default()
+            };
+            state.insert_account(address, &account_trie)?;
+            for (key, value) in &account.storage {
+                state.set_storage(address, key, value)?;
+            }
+        }
+        Ok(state)
+    }
+
+    fn clear_trie(&mut self) {
+        *self.trie.get_mut() = None;
+    }
+
+    /// Saves the state and the block context, to be restored with `restore`
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot(self.clone())
+    }
+
+    /// Restores the state and the block context saved by `snapshot`
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        *self = snapshot.0;
+    }
+}
+
+impl Provider for MemoryProvider {
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        Ok(self.accounts.contains_key(address))
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        Ok(self.accounts.get(address).map(|account| StateAccount {
+            nonce: account.nonce,
+            balance: account.balance,
+            code_hash: account.code_hash,
+        }))
+    }
+
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.clear_trie();
+        let account = self
+            .accounts
+            .get_mut(address)
+            .ok_or(Error::InvalidAddress { address: *address })?;
+        account.balance = *bal;
+        account.nonce = *nonce;
+        Ok(())
+    }
+
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+        self.clear_trie();
+        let code_hash = self.insert_code(code.clone());
+        // the balance sent to the address before the contract was created is kept
+        self.accounts.entry(*address).or_default().code_hash = code_hash;
+        Ok(())
+    }
+
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        Ok(self
+            .accounts
+            .get(address)
+            .and_then(|account| account.storage.get(key))
+            .cloned())
+    }
+
+    /// A zero value removes the key, it doesn't create the account
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.clear_trie();
+        if value.is_zero() {
+            if let Some(account) = self.accounts.get_mut(address) {
+                account.storage.remove(key);
+            }
+        } else {
+            self.accounts.entry(*address).or_default().storage.insert(*key, *value);
+        }
+        Ok(())
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        if *code_hash == EMPTY_CODE_HASH {
+            return Ok(Some(vec![]));
+        }
+        Ok(self.codes.get(code_hash).cloned())
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.block.timestamp
+    }
+
+    fn block_number(&self) -> u64 {
+        self.block.number
+    }
+
+    /// Returns zero for the blocks without a hash set by `set_block_hash`
+    fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        Ok(self
+            .block_hashes
+            .get(&block_no)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn block_author(&self) -> Result<Address, Error> {
+        Ok(self.block.author)
+    }
+
+    fn difficulty(&self) -> Result<U256, Error> {
+        Ok(self.block.difficulty)
+    }
+
+    fn gas_limit(&self) -> Result<U256, Error> {
+        Ok(self.block.gas_limit)
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.block.chain_id
+    }
+
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        Ok(self.block.base_fee)
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(self.block.prev_randao)
+    }
+
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        Ok(self.block.clone())
+    }
+
+    /// Applies the changes atomically: if one of them can't be applied,
+    /// e.g. updating an account that doesn't exist, none of them is
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        for (address, _, _) in &changes.updated {
+            let created = changes.created.iter().any(|(created, _)| created == address);
+            if !created && !self.accounts.contains_key(address) {
+                return Err(Error::InvalidAddress { address: *address });
+            }
+        }
+
+        for (address, code) in &changes.created {
+            self.create_contract(address, code)?;
+        }
+        for (address, balance, nonce) in &changes.updated {
+            self.update_account(address, balance, nonce)?;
+        }
+        for (address, key, value) in &changes.storage {
+            self.set_storage(address, key, value)?;
+        }
+        for (code_hash, version, code) in changes.prepared_codes {
+            self.prepared_codes.insert((code_hash, version), code);
+        }
+        for address in &changes.deleted {
+            self.remove_account(address);
+        }
+        Ok(())
+    }
+
+    fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.prepared_codes.get(&(*code_hash, *version)).cloned())
+    }
+
+    fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+        self.prepared_codes
+            .insert((*code_hash, *version), code.clone());
+        Ok(())
+    }
+}
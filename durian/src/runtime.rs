@@ -1,42 +1,84 @@
 use error::{Error};
+use frame;
+use limits::{ExecutionLimits, Limit};
 use log_entry::LogEntry;
-use panic_payload;
+use outcome::ExecutionOutcome;
+use panic_payload::{self, PanicPayload};
 use primitive_types::{H256, U256};
 use schedule::Schedule;
-use state::State;
+use state::{State, StateChanges};
+use std::cmp;
+use std::time::Instant;
+use tracer::{HostEvent, Tracer};
 use address::Address;
 use types::{ActionParams, ActionType};
-use wasmi::{MemoryRef, RuntimeArgs, RuntimeValue};
+use wasm_cost::WasmCosts;
+use wasmi::{memory_units, MemoryRef, RuntimeArgs, RuntimeValue};
+
+/// Usage of the limits counted for the whole transaction, shared by the nested frames
+pub struct TransactionUsage {
+	host_calls: u64,
+	deadline: Option<Instant>,
+}
+
+impl TransactionUsage {
+	/// Starts counting, the deadline is counted from now
+	pub fn new(limits: &ExecutionLimits) -> Self {
+		TransactionUsage {
+			host_calls: 0,
+			deadline: limits.deadline.map(|timeout| Instant::now() + timeout),
+		}
+	}
+}
+
+/// What a frame shares with the rest of the transaction, given to `Runtime::new`
+pub struct RuntimeContext<'a> {
+	pub schedule: &'a Schedule,
+	pub wasm_costs: &'a WasmCosts,
+	pub usage: &'a mut TransactionUsage,
+	pub tracer: &'a mut dyn Tracer,
+}
 
 pub struct Runtime<'a> {
 	schedule: &'a Schedule,
+	wasm_costs: &'a WasmCosts,
 	gas_counter: u64,
 	gas_limit: u64,
 	params: &'a ActionParams,
 	memory: MemoryRef,
 	result: Vec<u8>,
+	/// Data returned or reverted with by the last nested call of the frame
+	return_data: Vec<u8>,
 	state: &'a mut State<'a>,
+	usage: &'a mut TransactionUsage,
+	tracer: &'a mut dyn Tracer,
 	logs: Vec<LogEntry>,
+	panic_payload: Option<PanicPayload>,
 }
 
 impl<'a> Runtime<'a> {
 	/// New runtime for wasm contract with specified params
 	pub fn new(
 		params: &'a ActionParams,
-		schedule: &'a Schedule,
+		context: RuntimeContext<'a>,
 		state: &'a mut State<'a>,
 		memory: MemoryRef,
 		gas_limit: u64,
 	) -> Self {
 		Runtime {
-			schedule: schedule,
+			schedule: context.schedule,
+			wasm_costs: context.wasm_costs,
 			gas_counter: 0,
-			gas_limit: gas_limit,
-			memory: memory,
-			params: params,
-			state: state,
+			gas_limit,
+			memory,
+			params,
+			state,
+			usage: context.usage,
+			tracer: context.tracer,
 			logs: Vec::new(),
 			result: Vec::new(),
+			return_data: Vec::new(),
+			panic_payload: None,
 		}
 	}
 
@@ -76,6 +118,8 @@ impl<'a> Runtime<'a> {
 			Some(val) if val > self.gas_limit => false,
 			Some(_) => {
 				self.gas_counter = prev + amount;
+				self.tracer
+					.gas_charge(amount, self.gas_limit - self.gas_counter);
 				true
 			}
 		}
@@ -87,7 +131,7 @@ impl<'a> Runtime<'a> {
 		F: FnOnce(&Schedule) -> u64,
 	{
 		let amount = f(self.schedule());
-		if !self.charge_gas(amount as u64) {
+		if !self.charge_gas(amount) {
 			Err(Error::GasLimit)
 		} else {
 			Ok(())
@@ -99,9 +143,7 @@ impl<'a> Runtime<'a> {
 	where
 		F: FnOnce(&Schedule) -> u64,
 	{
-		self.charge(|schedule| {
-			f(schedule) * schedule.wasm().opcodes_div as u64 / schedule.wasm().opcodes_mul as u64
-		})
+		self.adjusted_overflow_charge(|schedule| Some(f(schedule)))
 	}
 
 	/// Charge gas provided by the closure
@@ -114,12 +156,12 @@ impl<'a> Runtime<'a> {
 		let amount = match f(self.schedule) {
 			Some(amount) => amount,
 			None => {
-				return Err(Error::GasLimit.into());
+				return Err(Error::GasLimit);
 			}
 		};
 
-		if !self.charge_gas(amount as u64) {
-			Err(Error::GasLimit.into())
+		if !self.charge_gas(amount) {
+			Err(Error::GasLimit)
 		} else {
 			Ok(())
 		}
@@ -130,10 +172,12 @@ impl<'a> Runtime<'a> {
 	where
 		F: FnOnce(&Schedule) -> Option<u64>,
 	{
+		let opcodes_div = self.wasm_costs.opcodes_div as u64;
+		let opcodes_mul = self.wasm_costs.opcodes_mul as u64;
 		self.overflow_charge(|schedule| {
 			f(schedule)
-				.and_then(|x| x.checked_mul(schedule.wasm().opcodes_div as u64))
-				.map(|x| x / schedule.wasm().opcodes_mul as u64)
+				.and_then(|x| x.checked_mul(opcodes_div))
+				.map(|x| x / opcodes_mul)
 		})
 	}
 
@@ -143,10 +187,15 @@ impl<'a> Runtime<'a> {
 		let val_ptr: u32 = args.nth_checked(1)?;
 
 		let val = self.state.storage_at(&self.params.address, &key)?;
+		self.tracer.host_event(&HostEvent::StorageRead {
+			address: self.params.address,
+			key,
+			value: val,
+		});
 
 		self.adjusted_charge(|schedule| schedule.sload_gas as u64)?;
 
-		self.memory.set(val_ptr as u32, val.as_bytes())?;
+		self.memory.set(val_ptr, val.as_bytes())?;
 
 		Ok(())
 	}
@@ -165,7 +214,12 @@ impl<'a> Runtime<'a> {
 			self.adjusted_charge(|schedule| schedule.sstore_reset_gas as u64)?;
 		}
 
-		self.state.set_storage(&self.params.address, &key, &val);
+		self.state.set_storage(&self.params.address, &key, &val)?;
+		self.tracer.host_event(&HostEvent::StorageWrite {
+			address: self.params.address,
+			key,
+			value: val,
+		});
 
 		if former_val != H256::zero() && val == H256::zero() {
 			let sstore_clears_schedule = self.schedule().sstore_refund_gas;
@@ -175,6 +229,38 @@ impl<'a> Runtime<'a> {
 		Ok(())
 	}
 
+	/// Counts the host function call and checks the deadline.
+	/// Calls to the gas counter are not counted, but checking the deadline
+	/// there stops the loops without host function calls.
+	fn check_limits(&mut self, is_gas_counter: bool) -> Result<(), Error> {
+		if !is_gas_counter {
+			self.usage.host_calls += 1;
+			if self.usage.host_calls > self.schedule.limits.max_host_calls {
+				return Err(Error::LimitExceeded {
+					limit: Limit::HostCalls,
+				});
+			}
+		}
+
+		match self.usage.deadline {
+			Some(deadline) if Instant::now() >= deadline => Err(Error::LimitExceeded {
+				limit: Limit::Deadline,
+			}),
+			_ => Ok(()),
+		}
+	}
+
+	/// Fails if the contract returns more data than the limit
+	fn check_return_data(&self, len: u32) -> Result<(), Error> {
+		if len as usize > self.schedule.limits.max_return_data {
+			return Err(Error::LimitExceeded {
+				limit: Limit::ReturnData,
+			});
+		}
+
+		Ok(())
+	}
+
 	/// Return currently used schedule
 	pub fn schedule(&self) -> &Schedule {
 		self.schedule
@@ -191,16 +277,44 @@ impl<'a> Runtime<'a> {
 
 		trace!(target: "wasm", "Contract ret: {} bytes @ {}", len, ptr);
 
+		self.check_return_data(len)?;
 		self.result = self.memory.get(ptr, len as usize)?;
 
 		Err(Error::Return)
 	}
 
-	/// Destroy the runtime, returning currently recorded result of the execution
-	pub fn into_result(&self) -> Vec<u8> {
+	/// Reverts the call, discarding all the state changes
+	///
+	/// Syscall takes 2 arguments:
+	/// * pointer in sandboxed memory where revert data is
+	/// * the length of the revert data
+	pub fn revert(&mut self, args: RuntimeArgs) -> Result<(), Error> {
+		let ptr: u32 = args.nth_checked(0)?;
+		let len: u32 = args.nth_checked(1)?;
+
+		trace!(target: "wasm", "Contract revert: {} bytes @ {}", len, ptr);
+
+		self.check_return_data(len)?;
+		self.result = self.memory.get(ptr, len as usize)?;
+
+		Err(Error::Revert)
+	}
+
+	/// Currently recorded result of the execution
+	pub fn result(&self) -> Vec<u8> {
 		self.result.clone()
 	}
 
+	/// Logs emitted by the contract so far
+	pub fn logs(&self) -> &[LogEntry] {
+		&self.logs
+	}
+
+	/// Payload of the contract panic, if the contract panicked
+	pub fn panic_payload(&self) -> Option<&PanicPayload> {
+		self.panic_payload.as_ref()
+	}
+
 	/// Query current gas left for execution
 	pub fn gas_left(&self) -> Result<u64, Error> {
 		if self.gas_counter > self.gas_limit {
@@ -215,7 +329,7 @@ impl<'a> Runtime<'a> {
 		if self.charge_gas(amount as u64) {
 			Ok(())
 		} else {
-			Err(Error::GasLimit.into())
+			Err(Error::GasLimit)
 		}
 	}
 
@@ -229,7 +343,8 @@ impl<'a> Runtime<'a> {
 		let ptr: u32 = args.nth_checked(0)?;
 
 		let args_len = self.params.args.len() as u64;
-		self.charge(|s| args_len * s.wasm().memcpy as u64)?;
+		let memcpy = self.wasm_costs.memcpy as u64;
+		self.overflow_charge(|_| args_len.checked_mul(memcpy))?;
 
 		self.memory.set(ptr, &self.params.args[..])?;
 		Ok(())
@@ -261,6 +376,7 @@ impl<'a> Runtime<'a> {
 		);
 		trace!(target: "wasm", "Contract custom panic message: {}", msg);
 
+		self.panic_payload = Some(payload);
 		Err(Error::Panic { msg })
 	}
 
@@ -298,8 +414,15 @@ impl<'a> Runtime<'a> {
 		let result_alloc_len: u32 = args.nth_checked(5 + vofs)?;
 		trace!(target: "wasm", "    result_len: {:?}", result_alloc_len);
 
+		// Cleared by every call, the calls that fail don't return data
+		self.return_data.clear();
+
 		if let Some(val) = val {
 			let address_balance = self.state.balance(&self.params.address)?;
+			self.tracer.host_event(&HostEvent::Balance {
+				address: self.params.address,
+				balance: address_balance,
+			});
 
 			if address_balance < val {
 				trace!(target: "wasm", "runtime: call failed due to balance check");
@@ -309,15 +432,20 @@ impl<'a> Runtime<'a> {
 
 		self.adjusted_charge(|schedule| schedule.call_gas as u64)?;
 
-		let mut result = Vec::with_capacity(result_alloc_len as usize);
-		result.resize(result_alloc_len as usize, 0);
+		// The result is written back to the memory, don't allocate more than the memory size
+		let memory_size: memory_units::Bytes = self.memory.current_size().into();
+		if result_ptr as usize + result_alloc_len as usize > memory_size.0 {
+			return Err(Error::MemoryAccessViolation);
+		}
+
+		let mut result = vec![0u8; result_alloc_len as usize];
 
 		// todo: optimize to use memory views once it's in
-		let _payload = self.memory.get(input_ptr, input_len as usize)?;
+		let payload = self.memory.get(input_ptr, input_len as usize)?;
 
 		let adjusted_gas = match gas
-			.checked_mul(self.schedule.wasm().opcodes_div as u64)
-			.map(|x| x / self.schedule.wasm().opcodes_mul as u64)
+			.checked_mul(self.wasm_costs.opcodes_div as u64)
+			.map(|x| x / self.wasm_costs.opcodes_mul as u64)
 		{
 			Some(x) => x,
 			None => {
@@ -326,62 +454,104 @@ impl<'a> Runtime<'a> {
 			}
 		};
 
-		self.charge(|_| adjusted_gas)?;
+		// The callee would be deeper than the limit
+		if self.params.depth >= self.schedule.max_depth {
+			trace!(target: "wasm", "runtime: call failed due to call depth");
+			return Ok((-1i32).into());
+		}
 
-		// TODO: fix it later
-		/*
-		let call_result = self
-			.ext
-			.call(
-				&gas.into(),
-				match call_type {
-					ActionType::DelegateCall => &self.context.sender,
-					_ => &self.context.address,
-				},
-				match call_type {
-					ActionType::Call | ActionType::StaticCall => &address,
-					_ => &self.context.address,
-				},
-				val,
-				&payload,
-				&address,
-				call_type,
-				false,
-			)
-			.ok()
-			.expect("Trap is false; trap error will not happen; qed");
+		// The value is given back if the callee fails
+		let checkpoint = self.state.checkpoint();
+		if let Some(val) = val {
+			if !self.state.transfer(&self.params.address, &address, &val)? {
+				trace!(target: "wasm", "runtime: call failed due to value transfer");
+				return Ok((-1i32).into());
+			}
+		}
 
-		match call_result {
-			MessageCallResult::Success(gas_left, data) => {
-				let len = cmp::min(result.len(), data.len());
-				(&mut result[..len]).copy_from_slice(&data[..len]);
+		let code = self.state.code(&address)?;
+		if code.is_empty() {
+			trace!(target: "wasm", "runtime: call to account without code");
+			return Ok(0i32.into());
+		}
 
-				// cannot overflow, before making call gas_counter was incremented with gas, and gas_left < gas
-				self.gas_counter = self.gas_counter
-					- gas_left.low_u64() * self.schedule.wasm().opcodes_div as u64
-						/ self.schedule.wasm().opcodes_mul as u64;
+		self.charge(|_| adjusted_gas)?;
 
-				self.memory.set(result_ptr, &result)?;
-				Ok(0i32.into())
-			}
-			MessageCallResult::Reverted(gas_left, data) => {
-				let len = cmp::min(result.len(), data.len());
-				(&mut result[..len]).copy_from_slice(&data[..len]);
+		let params = ActionParams {
+			code_address: address,
+			address,
+			sender: self.params.address,
+			origin: self.params.origin,
+			gas: U256::from(gas),
+			gas_price: self.params.gas_price,
+			value: val.unwrap_or_else(U256::zero),
+			code_hash: Some(self.state.code_hash(&address)?),
+			code,
+			code_version: self.schedule.latest_version,
+			args: payload,
+			action_type: call_type,
+			depth: self.params.depth + 1,
+		};
+		let call_result = frame::execute(
+			self.state,
+			&params,
+			self.schedule,
+			self.usage,
+			self.tracer,
+			None,
+		)?;
+
+		// cannot overflow, before making call gas_counter was incremented with gas, and gas_left < gas
+		self.gas_counter -= call_result.gas_left.low_u64() * self.wasm_costs.opcodes_div as u64
+			/ self.wasm_costs.opcodes_mul as u64;
+
+		if !call_result.outcome.is_success() {
+			self.state.revert_to(checkpoint);
+		}
+		match call_result.outcome {
+			ExecutionOutcome::Success | ExecutionOutcome::Revert(_) => {}
+			_ => return Ok((-1i32).into()),
+		}
+		let len = cmp::min(result.len(), call_result.data.len());
+		result[..len].copy_from_slice(&call_result.data[..len]);
+		self.memory.set(result_ptr, &result)?;
+		self.return_data = call_result.data;
+
+		if call_result.outcome.is_success() {
+			self.logs.extend(call_result.logs);
+			Ok(0i32.into())
+		} else {
+			Ok((-1i32).into())
+		}
+	}
 
-				// cannot overflow, before making call gas_counter was incremented with gas, and gas_left < gas
-				self.gas_counter = self.gas_counter
-					- gas_left.low_u64() * self.schedule.wasm().opcodes_div as u64
-						/ self.schedule.wasm().opcodes_mul as u64;
+	/// Query the size of the data returned by the last nested call
+	///
+	/// Signature: `fn return_data_size() -> i32`
+	fn return_data_size(&mut self) -> RuntimeValue {
+		RuntimeValue::I32(self.return_data.len() as i32)
+	}
 
-				self.memory.set(result_ptr, &result)?;
-				Ok((-1i32).into())
-			}
-			MessageCallResult::Failed => Ok((-1i32).into()),
+	/// Write `len` bytes of the data returned by the last nested call, starting at `offset`,
+	/// to the memory location using the passed pointer
+	///
+	/// Signature: `fn return_data_copy(ptr: *mut u8, offset: u32, len: u32)`
+	fn return_data_copy(&mut self, args: RuntimeArgs) -> Result<(), Error> {
+		let ptr: u32 = args.nth_checked(0)?;
+		let offset: u32 = args.nth_checked(1)?;
+		let len: u32 = args.nth_checked(2)?;
+
+		let memcpy = self.wasm_costs.memcpy as u64;
+		self.overflow_charge(|_| (len as u64).checked_mul(memcpy))?;
+
+		let size = self.return_data.len();
+		let end = offset as usize + len as usize;
+		if end > size {
+			return Err(Error::ReturnDataOutOfBounds { offset, len, size });
 		}
-		*/
-		Err(Error::Panic {
-			msg: "not completed".to_string(),
-		})
+
+		self.memory.set(ptr, &self.return_data[offset as usize..end])?;
+		Ok(())
 	}
 
 	/// Message call
@@ -403,7 +573,8 @@ impl<'a> Runtime<'a> {
 	*/
 
 	fn return_address_ptr(&mut self, ptr: u32, val: Address) -> Result<(), Error> {
-		self.charge(|schedule| schedule.wasm().static_address as u64)?;
+		let static_address = self.wasm_costs.static_address as u64;
+		self.charge(|_| static_address)?;
 		self.memory.set(ptr, val.as_bytes())?;
 		Ok(())
 	}
@@ -411,7 +582,8 @@ impl<'a> Runtime<'a> {
 	fn return_u256_ptr(&mut self, ptr: u32, val: U256) -> Result<(), Error> {
 		let mut ret = H256::zero();
 		val.to_big_endian(ret.as_bytes_mut());
-		self.charge(|schedule| schedule.wasm().static_u256 as u64)?;
+		let static_u256 = self.wasm_costs.static_u256 as u64;
+		self.charge(|_| static_u256)?;
 		self.memory.set(ptr, ret.as_bytes())?;
 		Ok(())
 	}
@@ -439,8 +611,8 @@ impl<'a> Runtime<'a> {
 		self.adjusted_charge(|schedule| schedule.create_data_gas as u64 * code.len() as u64)?;
 
 		let gas_left: U256 = U256::from(self.gas_left()?)
-			* U256::from(self.schedule.wasm().opcodes_mul)
-			/ U256::from(self.schedule.wasm().opcodes_div);
+			* U256::from(self.wasm_costs.opcodes_mul)
+			/ U256::from(self.wasm_costs.opcodes_div);
 
 
 		match self
@@ -458,8 +630,8 @@ impl<'a> Runtime<'a> {
 				self.gas_counter = self.gas_limit -
 					// this cannot overflow, since initial gas is in [0..u64::max) range,
 					// and gas_left cannot be bigger
-					gas_left.low_u64() * self.schedule.wasm().opcodes_div as u64
-						/ self.schedule.wasm().opcodes_mul as u64;
+					gas_left.low_u64() * self.wasm_costs.opcodes_div as u64
+						/ self.wasm_costs.opcodes_mul as u64;
 				trace!(target: "wasm", "runtime: create contract success (@{:?})", address);
 				Ok(0i32.into())
 			}
@@ -472,8 +644,8 @@ impl<'a> Runtime<'a> {
 				self.gas_counter = self.gas_limit -
 					// this cannot overflow, since initial gas is in [0..u64::max) range,
 					// and gas_left cannot be bigger
-					gas_left.low_u64() * self.schedule.wasm().opcodes_div as u64
-						/ self.schedule.wasm().opcodes_mul as u64;
+					gas_left.low_u64() * self.wasm_costs.opcodes_div as u64
+						/ self.wasm_costs.opcodes_mul as u64;
 
 				Ok((-1i32).into())
 			}
@@ -534,16 +706,13 @@ impl<'a> Runtime<'a> {
 		Ok(())
 	}
 
-	/// Pass suicide to state runtime
+	/// Deletes the contract at the end of the transaction, sending its balance to the refund address
+	///
+	/// Signature: `fn suicide(refund_address: *const u8)`
 	pub fn suicide(&mut self, args: RuntimeArgs) -> Result<(), Error> {
-		let _refund_address = self.address_at(args.nth_checked(0)?)?;
+		let refund_address = self.address_at(args.nth_checked(0)?)?;
 
-		// TODO: Not completed
-		/*
-		if self
-			.exists(&refund_address)
-			.map_err(|_| Error::SuicideAbort)?
-		{
+		if self.state.exist(&refund_address)? {
 			trace!(target: "wasm", "Suicide: refund to existing address {}", refund_address);
 			self.adjusted_charge(|schedule| schedule.suicide_gas as u64)?;
 		} else {
@@ -551,19 +720,13 @@ impl<'a> Runtime<'a> {
 			self.adjusted_charge(|schedule| schedule.suicide_to_new_account_cost as u64)?;
 		}
 
-		self.ext
-			.suicide(&refund_address)
-			.map_err(|_| Error::SuicideAbort)?;
+		self.state.suicide(&self.params.address, &refund_address)?;
 
 		// We send trap to interpreter so it should abort further execution
-		Err(Error::Suicide.into())
-		*/
-		Err(Error::Panic {
-			msg: "Not completed".to_string(),
-		})
+		Err(Error::Suicide)
 	}
 
-	///	Signature: `fn block_hash(number: i64, dest: *mut u8)`
+	/// Signature: `fn block_hash(number: i64, dest: *mut u8)`
 	pub fn block_hash(&mut self, args: RuntimeArgs) -> Result<(), Error> {
 		self.adjusted_charge(|schedule| schedule.blockhash_gas as u64)?;
 		let hash = self.state.block_hash(args.nth_checked::<u64>(0)?)?;
@@ -572,62 +735,90 @@ impl<'a> Runtime<'a> {
 		Ok(())
 	}
 
-	///	Signature: `fn blocknumber() -> i64`
+	/// Signature: `fn blocknumber() -> i64`
 	pub fn block_number(&mut self) -> Result<RuntimeValue, Error> {
-		Ok(RuntimeValue::from(self.state.block_number()))
+		Ok(RuntimeValue::from(self.state.block().number))
 	}
 
-	///	Signature: `fn block_author(dest: *mut u8)`
+	/// Signature: `fn block_author(dest: *mut u8)`
 	pub fn block_author(&mut self, args: RuntimeArgs) -> Result<(), Error> {
-		let author = self.state.block_author()?;
+		let author = self.state.block().author;
 		self.return_address_ptr(args.nth_checked(0)?, author)
 	}
 
-	///	Signature: `fn difficulty(dest: *mut u8)`
+	/// Signature: `fn difficulty(dest: *mut u8)`
 	pub fn difficulty(&mut self, args: RuntimeArgs) -> Result<(), Error> {
-		let difficulty = self.state.difficulty()?;
+		let difficulty = self.state.block().difficulty;
 		self.return_u256_ptr(args.nth_checked(0)?, difficulty)
 	}
 
-	///	Signature: `fn gaslimit(dest: *mut u8)`
+	/// Signature: `fn gaslimit(dest: *mut u8)`
 	pub fn gaslimit(&mut self, args: RuntimeArgs) -> Result<(), Error> {
-		let gas_limit = self.state.gas_limit()?;
+		let gas_limit = self.state.block().gas_limit;
 		self.return_u256_ptr(args.nth_checked(0)?, gas_limit)
 	}
 
-	///	Signature: `timestamp() -> i64`
+	/// Signature: `fn chain_id() -> i64`
+	pub fn chain_id(&mut self) -> Result<RuntimeValue, Error> {
+		Ok(RuntimeValue::from(self.state.block().chain_id))
+	}
+
+	/// Writes the base fee of the block, zero if the chain has no base fee
+	///
+	/// Signature: `fn basefee(dest: *mut u8)`
+	pub fn basefee(&mut self, args: RuntimeArgs) -> Result<(), Error> {
+		let base_fee = self.state.block().base_fee.unwrap_or_default();
+		self.return_u256_ptr(args.nth_checked(0)?, base_fee)
+	}
+
+	/// Writes the random beacon value of the block,
+	/// the difficulty if the chain provides no random beacon
+	///
+	/// Signature: `fn random_seed(dest: *mut u8)`
+	pub fn random_seed(&mut self, args: RuntimeArgs) -> Result<(), Error> {
+		let block = self.state.block();
+		let seed = match block.prev_randao {
+			Some(prev_randao) => U256::from_big_endian(prev_randao.as_bytes()),
+			None => block.difficulty,
+		};
+		self.return_u256_ptr(args.nth_checked(0)?, seed)
+	}
+
+	/// Signature: `timestamp() -> i64`
 	pub fn timestamp(&mut self) -> Result<RuntimeValue, Error> {
-		let timestamp = self.state.timestamp();
+		let timestamp = self.state.block().timestamp;
 		Ok(RuntimeValue::from(timestamp))
 	}
 
-	///	Signature: `fn gasleft() -> i64`
+	/// Signature: `fn gasleft() -> i64`
 	pub fn gasleft(&mut self) -> Result<RuntimeValue, Error> {
-		Ok(RuntimeValue::from(
-			self.gas_left()? * self.schedule.wasm().opcodes_mul as u64
-				/ self.schedule.wasm().opcodes_div as u64,
-		))
+		let gas_left = self
+			.gas_left()?
+			.checked_mul(self.wasm_costs.opcodes_mul as u64)
+			.map(|gas| gas / self.wasm_costs.opcodes_div as u64)
+			.ok_or(Error::InvalidGasState)?;
+		Ok(RuntimeValue::from(gas_left))
 	}
 
-	///	Signature: `fn address(dest: *mut u8)`
+	/// Signature: `fn address(dest: *mut u8)`
 	pub fn address(&mut self, args: RuntimeArgs) -> Result<(), Error> {
 		let address = self.params.address;
 		self.return_address_ptr(args.nth_checked(0)?, address)
 	}
 
-	///	Signature: `sender(dest: *mut u8)`
+	/// Signature: `sender(dest: *mut u8)`
 	pub fn sender(&mut self, args: RuntimeArgs) -> Result<(), Error> {
 		let sender = self.params.sender;
 		self.return_address_ptr(args.nth_checked(0)?, sender)
 	}
 
-	///	Signature: `origin(dest: *mut u8)`
+	/// Signature: `origin(dest: *mut u8)`
 	pub fn origin(&mut self, args: RuntimeArgs) -> Result<(), Error> {
 		let origin = self.params.origin;
 		self.return_address_ptr(args.nth_checked(0)?, origin)
 	}
 
-	///	Signature: `fn elog(topic_ptr: *const u8, topic_count: u32, data_ptr: *const u8, data_len: u32)`
+	/// Signature: `fn elog(topic_ptr: *const u8, topic_count: u32, data_ptr: *const u8, data_len: u32)`
 	pub fn elog(&mut self, args: RuntimeArgs) -> Result<(), Error> {
 		let topic_ptr: u32 = args.nth_checked(0)?;
 		let topic_count: u32 = args.nth_checked(1)?;
@@ -635,7 +826,7 @@ impl<'a> Runtime<'a> {
 		let data_len: u32 = args.nth_checked(3)?;
 
 		if topic_count > 4 {
-			return Err(Error::Log.into());
+			return Err(Error::Log);
 		}
 
 		self.adjusted_overflow_charge(|schedule| {
@@ -662,11 +853,13 @@ impl<'a> Runtime<'a> {
 
 		let data = self.memory.get(data_ptr, data_len as usize)?;
 
-		self.logs.push(LogEntry {
-			address: self.params.address.clone(),
-			topics: topics,
+		let log = LogEntry {
+			address: self.params.address,
+			topics,
 			data: data.to_vec()
-		});
+		};
+		self.tracer.host_event(&HostEvent::Log(&log));
+		self.logs.push(log);
 
 		Ok(())
 	}
@@ -676,18 +869,30 @@ impl<'a> Runtime<'a> {
 		//self.substate.sstore_clears_refund += value as i128;
 	}
 
-	pub fn init_code(&mut self, address: &Address, code: Vec<u8>) {
-		self.state.init_code(address, code);
+	/// Takes the cached state of the frame, to be given back to the state of the caller
+	pub fn take_state_changes(&mut self) -> StateChanges {
+		self.state.take_changes()
 	}
 
-	pub fn update_state(&mut self) -> Result<(), Error> {
-		self.state.update_state()
+	/// Reverts the changes the frame made since the `checkpoint`
+	pub fn revert_state(&mut self, checkpoint: usize) {
+		self.state.revert_to(checkpoint);
+	}
+
+	pub fn init_code(&mut self, address: &Address, code: Vec<u8>) -> Result<(), Error> {
+		self.state.init_code(address, code)
+	}
+
+	pub fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: Vec<u8>) {
+		self.state.set_prepared_code(code_hash, version, code);
 	}
 }
 
 mod ext_impl {
 
+	use env::func_name;
 	use env::ids::*;
+	use error::Error;
 	use wasmi::{Externals, RuntimeArgs, RuntimeValue, Trap};
 
 	macro_rules! void {
@@ -708,10 +913,16 @@ mod ext_impl {
 			index: usize,
 			args: RuntimeArgs,
 		) -> Result<Option<RuntimeValue>, Trap> {
+			if let Some(name) = func_name(index) {
+				self.tracer.host_function(name);
+			}
+			self.check_limits(index == GAS_FUNC)?;
+
 			match index {
 				STORAGE_WRITE_FUNC => void!(self.storage_write(args)),
 				STORAGE_READ_FUNC => void!(self.storage_read(args)),
 				RET_FUNC => void!(self.ret(args)),
+				REVERT_FUNC => void!(self.revert(args)),
 				GAS_FUNC => void!(self.gas(args)),
 				INPUT_LENGTH_FUNC => cast!(self.input_legnth()),
 				FETCH_INPUT_FUNC => void!(self.fetch_input(args)),
@@ -735,7 +946,15 @@ mod ext_impl {
 				ELOG_FUNC => void!(self.elog(args)),
 				//CREATE2_FUNC => some!(self.create2(args)),
 				GASLEFT_FUNC => some!(self.gasleft()),
-				_ => panic!("env module doesn't provide function at index {}", index),
+				RETURN_DATA_SIZE_FUNC => cast!(self.return_data_size()),
+				RETURN_DATA_COPY_FUNC => void!(self.return_data_copy(args)),
+				CHAIN_ID_FUNC => some!(self.chain_id()),
+				BASEFEE_FUNC => void!(self.basefee(args)),
+				RANDOM_SEED_FUNC => void!(self.random_seed(args)),
+				_ => {
+					trace!(target: "wasm", "env module doesn't provide function at index {}", index);
+					Err(Error::Unknown.into())
+				}
 			}
 		}
 	}
@@ -1,4 +1,6 @@
 
+use error::Error;
+use limits::ExecutionLimits;
 use primitive_types::U256;
 use wasm_cost::WasmCosts;
 
@@ -18,7 +20,11 @@ pub struct Schedule {
 	pub have_extcodehash: bool,
 	/// VM stack limit
 	pub stack_limit: usize,
-	/// Max number of nested calls/creates
+	/// Max number of nested calls/creates, the transaction itself is at depth zero.
+	/// A call deeper than the limit fails and `ccall` returns -1 to the caller.
+	///
+	/// Every nested call takes up to 30KB of the native stack in debug builds, 8KB in release builds,
+	/// so the execution needs a thread with a larger stack than the default to reach the limit.
 	pub max_depth: usize,
 	/// Gas prices for instructions in all tiers
 	pub tier_step_gas: [usize; 8],
@@ -119,9 +125,10 @@ pub struct Schedule {
 	pub latest_version: U256,
 	/// Wasm extra schedule settings, if wasm activated
 	pub wasm: Option<WasmCosts>,
+	/// Resource limits of the wasm execution
+	pub limits: ExecutionLimits,
 }
 
-#[allow(dead_code)]
 /// Dust accounts cleanup mode.
 #[derive(Debug, PartialEq, Eq)]
 pub enum CleanDustMode {
@@ -139,6 +146,19 @@ impl Schedule {
 		Self::new(false, false, 21000)
 	}
 
+	/// Schedule for executing wasm contracts in durian.
+	///
+	/// Failing to pay the code deposit fails the creation and
+	/// the deployed code can't be larger than 24KB.
+	pub fn new_wasm() -> Schedule {
+		let mut schedule = Self::new(true, false, 53000);
+		schedule.create_data_limit = 24576;
+		schedule.have_return_data = true;
+		schedule.have_chain_id = true;
+		schedule.wasm = Some(WasmCosts::default());
+		schedule
+	}
+
 	fn new(efcd: bool, hdc: bool, tcg: usize) -> Schedule {
 		Schedule {
 			exceptional_failed_code_deposit: efcd,
@@ -197,15 +217,15 @@ impl Schedule {
 			eip1706: false,
 			latest_version: U256::zero(),
 			wasm: None,
+			limits: ExecutionLimits::default(),
 		}
 	}
 
 	/// Returns wasm schedule
 	///
-	/// May panic if there is no wasm schedule
-	pub fn wasm(&self) -> &WasmCosts {
-		// *** Prefer PANIC here instead of silently breaking consensus! ***
-		self.wasm.as_ref().expect("Wasm schedule expected to exist while checking wasm contract. Misconfigured client?")
+	/// Fails if there is no wasm schedule. Misconfigured client?
+	pub fn wasm(&self) -> Result<&WasmCosts, Error> {
+		self.wasm.as_ref().ok_or(Error::MissingWasmCosts)
 	}
 }
 
@@ -1,80 +1,186 @@
 use error::Error;
-use log::debug;
 use primitive_types::{H256, U256};
-use provider::Provider;
+use provider::{BlockContext, Provider, StateAccount, StateChangeSet, EMPTY_CODE_HASH};
 use std::collections::HashMap;
+use std::mem;
 use address::Address;
+use utils;
 
 #[derive(Debug, Clone, PartialEq)]
 struct AccountInfo {
     nonce: U256,
     balance: U256,
-    code: Vec<u8>,
+    code_hash: H256,
+    /// Code hash of the account before the transaction
+    original_code_hash: H256,
     storage: HashMap<H256, (H256, bool)>,
+    /// Whether the account exists in the provider
+    exists: bool,
+    /// Whether the account is deleted by `State::suicide`
+    deleted: bool,
 }
 
 impl AccountInfo {
-    pub fn new(nonce: U256, balance: U256, code: Vec<u8>) -> AccountInfo {
+    pub fn new(nonce: U256, balance: U256, code_hash: H256, exists: bool) -> AccountInfo {
         AccountInfo {
             nonce,
             balance,
-            code,
+            code_hash,
+            original_code_hash: code_hash,
             storage: HashMap::new(),
+            exists,
+            deleted: false,
         }
     }
 }
 
+/// Change of the cached state, undone by `State::revert_to`
+enum JournalEntry {
+    /// Nonce, balance, code hash and dirty flag of the account before the change
+    Account(Address, U256, U256, H256, bool),
+    /// Storage value before the change, `None` if it wasn't cached
+    Storage(Address, H256, Option<(H256, bool)>),
+    /// Prepared code before the change
+    PreparedCode(H256, U256, Option<Vec<u8>>),
+    /// Account deleted by the change
+    Deleted(Address),
+}
+
+/// Cached state of the frames, moved to a nested frame and back, see `State::nested`
+#[derive(Default)]
+pub struct StateChanges {
+    accounts: HashMap<Address, (AccountInfo, bool)>,
+    codes: HashMap<H256, Vec<u8>>,
+    prepared_codes: HashMap<(H256, U256), Vec<u8>>,
+    journal: Vec<JournalEntry>,
+}
+
 pub struct State<'a> {
     provider: &'a mut dyn Provider,
     accounts: HashMap<Address, (AccountInfo, bool)>,
+    /// Code by its hash, fetched once for all the contracts with the same code
+    codes: HashMap<H256, Vec<u8>>,
+    prepared_codes: HashMap<(H256, U256), Vec<u8>>,
+    /// Changes made since the start of the transaction, in order
+    journal: Vec<JournalEntry>,
+    block: BlockContext,
 }
 
 impl<'a> State<'a> {
-    pub fn new(provider: &'a mut dyn Provider) -> Self {
-        State {
+    pub fn new(provider: &'a mut dyn Provider) -> Result<Self, Error> {
+        let block = provider
+            .block_context()
+            .map_err(Error::provider_failure)?;
+        Ok(State {
             provider: provider,
             accounts: HashMap::new(),
+            codes: HashMap::new(),
+            prepared_codes: HashMap::new(),
+            journal: Vec::new(),
+            block,
+        })
+    }
+
+    /// State for a nested frame. The cached state is moved to the nested state and should be
+    /// moved back by passing `take_changes` to `commit`, after the changes of a failed frame
+    /// are reverted with `revert_to`. If the nested frame fails with `Err`, the transaction
+    /// is aborted and the state is dropped.
+    pub fn nested<'b>(&'b mut self) -> State<'b> {
+        let changes = self.take_changes();
+        State {
+            provider: &mut *self.provider,
+            accounts: changes.accounts,
+            codes: changes.codes,
+            prepared_codes: changes.prepared_codes,
+            journal: changes.journal,
+            block: self.block.clone(),
         }
     }
 
-    #[allow(dead_code)]
-    pub fn nonce(&mut self, address: &Address) -> Result<U256, Error> {
-        let acc = self.account(address)?;
-        Ok(acc.nonce)
+    pub fn take_changes(&mut self) -> StateChanges {
+        StateChanges {
+            accounts: mem::take(&mut self.accounts),
+            codes: mem::take(&mut self.codes),
+            prepared_codes: mem::take(&mut self.prepared_codes),
+            journal: mem::take(&mut self.journal),
+        }
     }
 
-    pub fn balance(&mut self, address: &Address) -> Result<U256, Error> {
-        let acc = self.account(address)?;
-        Ok(acc.balance)
+    /// Takes the changes of the nested state back
+    pub fn commit(&mut self, changes: StateChanges) {
+        self.accounts = changes.accounts;
+        self.codes = changes.codes;
+        self.prepared_codes = changes.prepared_codes;
+        self.journal = changes.journal;
     }
 
-    #[allow(dead_code)]
-    pub fn exist(&self, address: &Address) -> bool {
-        self.provider.exist(address)
+    /// Point the changes can be reverted to
+    pub fn checkpoint(&self) -> usize {
+        self.journal.len()
     }
 
-    pub fn timestamp(&self) -> u64 {
-        self.provider.block_number()
+    /// Reverts the changes made since the `checkpoint`
+    pub fn revert_to(&mut self, checkpoint: usize) {
+        while self.journal.len() > checkpoint {
+            match self.journal.pop() {
+                Some(JournalEntry::Account(address, nonce, balance, code_hash, dirty)) => {
+                    if let Some(acc) = self.accounts.get_mut(&address) {
+                        acc.0.nonce = nonce;
+                        acc.0.balance = balance;
+                        acc.0.code_hash = code_hash;
+                        acc.1 = dirty;
+                    }
+                }
+                Some(JournalEntry::Storage(address, key, value)) => {
+                    if let Some(acc) = self.accounts.get_mut(&address) {
+                        match value {
+                            Some(value) => acc.0.storage.insert(key, value),
+                            None => acc.0.storage.remove(&key),
+                        };
+                    }
+                }
+                Some(JournalEntry::PreparedCode(code_hash, version, code)) => {
+                    match code {
+                        Some(code) => self.prepared_codes.insert((code_hash, version), code),
+                        None => self.prepared_codes.remove(&(code_hash, version)),
+                    };
+                }
+                Some(JournalEntry::Deleted(address)) => {
+                    if let Some(acc) = self.accounts.get_mut(&address) {
+                        acc.0.deleted = false;
+                    }
+                }
+                None => {}
+            }
+        }
     }
 
-    pub fn block_number(&self) -> u64 {
-        self.provider.block_number()
+    #[allow(dead_code)]
+    pub fn nonce(&mut self, address: &Address) -> Result<U256, Error> {
+        let acc = self.account(address)?;
+        Ok(acc.nonce)
     }
 
-    pub fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
-        self.provider.block_hash(block_no)
+    pub fn balance(&mut self, address: &Address) -> Result<U256, Error> {
+        let acc = self.account(address)?;
+        Ok(acc.balance)
     }
 
-    pub fn block_author(&self) -> Result<Address, Error> {
-        self.provider.block_author()
+    /// Whether the account exists in the provider
+    pub fn exist(&mut self, address: &Address) -> Result<bool, Error> {
+        let acc = self.account(address)?;
+        Ok(acc.exists)
     }
 
-    pub fn difficulty(&self) -> Result<U256, Error> {
-        self.provider.difficulty()
+    /// Context of the block, fetched when the state was created
+    pub fn block(&self) -> &BlockContext {
+        &self.block
     }
 
-    pub fn gas_limit(&self) -> Result<U256, Error> {
-        self.provider.gas_limit()
+    pub fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        self.provider
+            .block_hash(block_no)
+            .map_err(Error::provider_failure)
     }
 
     pub fn storage_at(&mut self, address: &Address, key: &H256) -> Result<H256, Error> {
@@ -93,48 +199,244 @@ impl<'a> State<'a> {
         }
     }
 
-    pub fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) {
-        let acc = self.account_mut(address).unwrap();
-        acc.0.storage.insert(*key, (*value, true));
+    pub fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        let acc = self.account_mut(address)?;
+        let previous = acc.0.storage.insert(*key, (*value, true));
+        self.journal.push(JournalEntry::Storage(*address, *key, previous));
+        Ok(())
+    }
+
+    /// Moves `value` from the balance of `from` to the balance of `to`.
+    /// Returns false, without changing the balances, if `from` doesn't have the value
+    /// or the balance of `to` would overflow.
+    pub fn transfer(&mut self, from: &Address, to: &Address, value: &U256) -> Result<bool, Error> {
+        let from_balance = self.balance(from)?;
+        if from_balance < *value {
+            return Ok(false);
+        }
+        if value.is_zero() || from == to {
+            return Ok(true);
+        }
+        let to_balance = match self.balance(to)?.checked_add(*value) {
+            Some(balance) => balance,
+            None => return Ok(false),
+        };
+
+        let acc = self.change_account(from)?;
+        acc.0.balance = from_balance - *value;
+        acc.1 = true;
+        let acc = self.change_account(to)?;
+        acc.0.balance = to_balance;
+        acc.1 = true;
+        Ok(true)
+    }
+
+    /// Deletes the account at `address` at the end of the transaction
+    /// and moves its balance to `refund_address`
+    pub fn suicide(&mut self, address: &Address, refund_address: &Address) -> Result<(), Error> {
+        let balance = self.balance(address)?;
+        if !self.transfer(address, refund_address, &balance)? {
+            return Err(Error::SuicideAbort);
+        }
+
+        let acc = self.account_mut(address)?;
+        if !acc.0.deleted {
+            acc.0.deleted = true;
+            self.journal.push(JournalEntry::Deleted(*address));
+        }
+        Ok(())
+    }
+
+    /// The account to change, its current state is journaled
+    fn change_account(&mut self, address: &Address) -> Result<&mut (AccountInfo, bool), Error> {
+        self.fetch_account(address)?;
+
+        let acc = self
+            .accounts
+            .get_mut(address)
+            .ok_or(Error::InvalidAddress { address: *address })?;
+        self.journal.push(JournalEntry::Account(
+            *address,
+            acc.0.nonce,
+            acc.0.balance,
+            acc.0.code_hash,
+            acc.1,
+        ));
+        Ok(acc)
     }
 
     fn account_mut(&mut self, address: &Address) -> Result<&mut (AccountInfo, bool), Error> {
         self.fetch_account(address)?;
 
-        return Ok(self.accounts.get_mut(address).unwrap());
+        self.accounts
+            .get_mut(address)
+            .ok_or(Error::InvalidAddress { address: *address })
     }
 
     fn account(&mut self, address: &Address) -> Result<&AccountInfo, Error> {
         self.fetch_account(address)?;
 
-        return Ok(&self.accounts.get(address).unwrap().0);
+        self.accounts
+            .get(address)
+            .map(|acc| &acc.0)
+            .ok_or(Error::InvalidAddress { address: *address })
+    }
+
+    pub fn code_hash(&mut self, address: &Address) -> Result<H256, Error> {
+        let acc = self.account(address)?;
+        Ok(acc.code_hash)
+    }
+
+    pub fn code(&mut self, address: &Address) -> Result<Vec<u8>, Error> {
+        let code_hash = self.code_hash(address)?;
+        self.code_by_hash(&code_hash)
     }
 
-    pub fn init_code(&mut self, address: &Address, code: Vec<u8>) {
-        let mut acc = self.account_mut(address).unwrap();
-        acc.0.code = code;
+    pub fn init_code(&mut self, address: &Address, code: Vec<u8>) -> Result<(), Error> {
+        let code_hash = utils::keccak(&code);
+        let acc = self.change_account(address)?;
+        acc.0.code_hash = code_hash;
         acc.1 = true;
+        self.codes.insert(code_hash, code);
+        Ok(())
     }
 
-    pub fn update_state(&mut self) -> Result<(), Error> {
+    fn code_by_hash(&mut self, code_hash: &H256) -> Result<Vec<u8>, Error> {
+        if *code_hash == EMPTY_CODE_HASH {
+            return Ok(vec![]);
+        }
+        if let Some(code) = self.codes.get(code_hash) {
+            return Ok(code.clone());
+        }
+
+        let code = self
+            .provider
+            .code_by_hash(code_hash)
+            .map_err(Error::provider_failure)?
+            .ok_or_else(|| Error::ProviderFailure {
+                msg: format!("Missing code for the code hash {:?}", code_hash),
+            })?;
+        self.codes.insert(*code_hash, code.clone());
+        Ok(code)
+    }
+
+    pub fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: Vec<u8>) {
+        let previous = self.prepared_codes.insert((*code_hash, *version), code);
+        self.journal.push(JournalEntry::PreparedCode(*code_hash, *version, previous));
+    }
+
+    pub fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        match self.prepared_codes.get(&(*code_hash, *version)) {
+            Some(code) => Ok(Some(code.clone())),
+            None => self
+                .provider
+                .prepared_code(code_hash, version)
+                .map_err(Error::provider_failure),
+        }
+    }
+
+    /// Fetches the accounts at `addresses` and the storage at `keys` that aren't cached yet,
+    /// with one call of `Provider::accounts` and one of `Provider::storages`
+    pub fn prefetch(&mut self, addresses: &[Address], keys: &[(Address, H256)]) -> Result<(), Error> {
+        let mut addresses: Vec<Address> = addresses
+            .iter()
+            .chain(keys.iter().map(|(address, _)| address))
+            .filter(|address| !self.accounts.contains_key(address))
+            .cloned()
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        if !addresses.is_empty() {
+            let accounts = self
+                .provider
+                .accounts(&addresses)
+                .map_err(Error::provider_failure)?;
+            for (address, acc) in addresses.iter().zip(accounts) {
+                self.insert_account(address, acc);
+            }
+        }
+
+        let mut keys: Vec<(Address, H256)> = keys
+            .iter()
+            .filter(|(address, key)| !self.accounts[address].0.storage.contains_key(key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        if !keys.is_empty() {
+            let values = self
+                .provider
+                .storages(&keys)
+                .map_err(Error::provider_failure)?;
+            for ((address, key), value) in keys.iter().zip(values) {
+                let acc = self.account_mut(address)?;
+                acc.0.storage.insert(*key, (value.unwrap_or_default(), false));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Changes of the cached accounts, to be applied to the provider
+    fn changes(&self) -> StateChangeSet {
+        let mut changes = StateChangeSet::default();
         for (addr, acc) in &self.accounts {
+            if acc.0.deleted {
+                if acc.0.exists {
+                    changes.deleted.push(*addr);
+                }
+                continue;
+            }
+
             if acc.1 {
-                if !self.provider.exist(addr) {
-                    self.provider.create_contract(addr, &acc.0.code)?;
+                if !acc.0.exists {
+                    // the code set by `init_code`, none for the empty code
+                    let code = self.codes.get(&acc.0.code_hash).cloned().unwrap_or_default();
+                    changes.created.push((*addr, code));
+                    // value sent to the new account
+                    if !acc.0.balance.is_zero() || !acc.0.nonce.is_zero() {
+                        changes.updated.push((*addr, acc.0.balance, acc.0.nonce));
+                    }
                 } else {
-                    self.provider
-                        .update_account(addr, &acc.0.balance, &acc.0.nonce)?;
+                    if acc.0.code_hash != acc.0.original_code_hash {
+                        let code = self.codes.get(&acc.0.code_hash).cloned().unwrap_or_default();
+                        changes.created.push((*addr, code));
+                    }
+                    changes.updated.push((*addr, acc.0.balance, acc.0.nonce));
                 }
             }
 
             for (key, val) in &acc.0.storage {
                 if val.1 {
-                    self.provider.set_storage(addr, key, &val.0)?;
+                    changes.storage.push((*addr, *key, val.0));
                 }
             }
         }
 
-        Ok(())
+        for ((code_hash, version), code) in &self.prepared_codes {
+            changes
+                .prepared_codes
+                .push((*code_hash, *version, code.clone()));
+        }
+
+        changes.created.sort();
+        changes.updated.sort();
+        changes.storage.sort();
+        changes.prepared_codes.sort();
+        changes.deleted.sort();
+        changes
+    }
+
+    /// Applies the changes to the provider in one `Provider::apply_changes` call
+    pub fn update_state(&mut self) -> Result<(), Error> {
+        let changes = self.changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.provider
+            .apply_changes(changes)
+            .map_err(Error::provider_failure)
     }
 
     fn fetch_account(&mut self, address: &Address) -> Result<(), Error> {
@@ -142,15 +444,20 @@ impl<'a> State<'a> {
             return Ok(());
         }
 
-        if let Ok(acc) = self.provider.account(address) {
-            let acc = AccountInfo::new(acc.balance, acc.nonce, acc.code);
-            self.accounts.insert(*address, (acc, false));
-            Ok(())
-        } else {
-            let acc = AccountInfo::new(U256::zero(), U256::from(1), vec![]);
-            self.accounts.insert(*address, (acc, false));
-            Ok(())
-        }
+        let acc = self
+            .provider
+            .account(address)
+            .map_err(Error::provider_failure)?;
+        self.insert_account(address, acc);
+        Ok(())
+    }
+
+    fn insert_account(&mut self, address: &Address, acc: Option<StateAccount>) {
+        let acc = match acc {
+            Some(acc) => AccountInfo::new(acc.nonce, acc.balance, acc.code_hash, true),
+            None => AccountInfo::new(U256::zero(), U256::zero(), EMPTY_CODE_HASH, false),
+        };
+        self.accounts.insert(*address, (acc, false));
     }
 
     fn fetch_storage(&mut self, address: &Address, key: &H256) -> Result<(), Error> {
@@ -159,13 +466,311 @@ impl<'a> State<'a> {
             return Ok(());
         }
 
-        if let Ok(value) = self.provider.storage_at(address, key) {
-            let acc = self.account_mut(address)?;
-            acc.0.storage.insert(*key, (value, false));
+        let value = self
+            .provider
+            .storage_at(address, key)
+            .map_err(Error::provider_failure)?;
+        let acc = self.account_mut(address)?;
+        acc.0.storage.insert(*key, (value.unwrap_or_default(), false));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use address::Address;
+    use error::Error;
+    use primitive_types::{H256, U256};
+    use proptest::prelude::*;
+    use provider::{Provider, StateAccount};
+    use std::collections::BTreeMap;
+    use utils;
+
+    /// Reference provider keeping the accounts and the storage in memory
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct ReferenceProvider {
+        accounts: BTreeMap<Address, (U256, U256, Vec<u8>)>,
+        storage: BTreeMap<(Address, H256), H256>,
+    }
+
+    impl Provider for ReferenceProvider {
+        fn exist(&self, address: &Address) -> Result<bool, Error> {
+            Ok(self.accounts.contains_key(address))
+        }
+
+        fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+            Ok(self
+                .accounts
+                .get(address)
+                .map(|(nonce, balance, code)| StateAccount {
+                    nonce: *nonce,
+                    balance: *balance,
+                    code_hash: utils::keccak(code),
+                }))
+        }
+
+        fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+            let acc = self
+                .accounts
+                .get_mut(address)
+                .ok_or(Error::InvalidAddress { address: *address })?;
+            acc.0 = *nonce;
+            acc.1 = *bal;
             Ok(())
-        } else {
-            debug!("Not storage at {:?}", key);
+        }
+
+        fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+            self.accounts
+                .insert(*address, (U256::zero(), U256::zero(), code.clone()));
+            Ok(())
+        }
+
+        fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+            Ok(self.storage.get(&(*address, *key)).cloned())
+        }
+
+        fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+            self.storage.insert((*address, *key), *value);
             Ok(())
         }
+
+        fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+            Ok(self
+                .accounts
+                .values()
+                .map(|(_, _, code)| code)
+                .find(|code| utils::keccak(code) == *code_hash)
+                .cloned())
+        }
+
+        fn timestamp(&self) -> u64 {
+            0
+        }
+
+        fn block_number(&self) -> u64 {
+            0
+        }
+
+        fn block_hash(&self, _block_no: u64) -> Result<H256, Error> {
+            Ok(H256::zero())
+        }
+
+        fn block_author(&self) -> Result<Address, Error> {
+            Ok(Address::zero())
+        }
+
+        fn difficulty(&self) -> Result<U256, Error> {
+            Ok(U256::zero())
+        }
+
+        fn gas_limit(&self) -> Result<U256, Error> {
+            Ok(U256::zero())
+        }
+
+        fn chain_id(&self) -> u64 {
+            1
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        SetStorage(Address, H256, H256),
+        InitCode(Address, Vec<u8>),
+    }
+
+    impl Op {
+        fn apply(&self, state: &mut State) {
+            match self {
+                Op::SetStorage(address, key, value) => state.set_storage(address, key, value).unwrap(),
+                Op::InitCode(address, code) => state.init_code(address, code.clone()).unwrap(),
+            }
+        }
+    }
+
+    // A few addresses and keys, so the operations hit the same entries
+    fn address() -> impl Strategy<Value = Address> {
+        (0u8..6).prop_map(Address::repeat_byte)
+    }
+
+    fn key() -> impl Strategy<Value = H256> {
+        (0u8..4).prop_map(H256::repeat_byte)
+    }
+
+    fn value() -> impl Strategy<Value = H256> {
+        any::<u64>().prop_map(H256::from_low_u64_be)
+    }
+
+    fn provider() -> impl Strategy<Value = ReferenceProvider> {
+        let account = (any::<u64>(), any::<u64>(), prop::collection::vec(any::<u8>(), 0..8))
+            .prop_map(|(nonce, balance, code)| (U256::from(nonce), U256::from(balance), code));
+        (
+            prop::collection::btree_map(address(), account, 0..4),
+            prop::collection::btree_map((address(), key()), value(), 0..8),
+        )
+            .prop_map(|(accounts, storage)| ReferenceProvider { accounts, storage })
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (address(), key(), value()).prop_map(|(address, key, value)| Op::SetStorage(address, key, value)),
+            (address(), prop::collection::vec(any::<u8>(), 0..8)).prop_map(|(address, code)| Op::InitCode(address, code)),
+        ]
+    }
+
+    fn ops() -> impl Strategy<Value = Vec<Op>> {
+        prop::collection::vec(op(), 0..16)
+    }
+
+    /// Checks that the state reads the same accounts and storage as the `expected` provider
+    fn assert_reads(state: &mut State, expected: &ReferenceProvider) -> Result<(), TestCaseError> {
+        for byte in 0..6 {
+            let address = Address::repeat_byte(byte);
+            let (nonce, balance, code) = expected
+                .accounts
+                .get(&address)
+                .cloned()
+                .unwrap_or_default();
+            prop_assert_eq!(state.nonce(&address).unwrap(), nonce);
+            prop_assert_eq!(state.balance(&address).unwrap(), balance);
+            prop_assert_eq!(state.code_hash(&address).unwrap(), utils::keccak(&code));
+            prop_assert_eq!(state.code(&address).unwrap(), code);
+
+            for byte in 0..4 {
+                let key = H256::repeat_byte(byte);
+                prop_assert_eq!(
+                    state.storage_at(&address, &key).unwrap(),
+                    expected.storage_at(&address, &key).unwrap().unwrap_or_default()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The accounts and the storage the state should read after applying `ops` on `provider`
+    fn applied(provider: &ReferenceProvider, ops: &[Op]) -> ReferenceProvider {
+        let mut expected = provider.clone();
+        for op in ops {
+            match op {
+                Op::SetStorage(address, key, value) => {
+                    expected.storage.insert((*address, *key), *value);
+                }
+                Op::InitCode(address, code) => {
+                    let acc = expected.accounts.entry(*address).or_default();
+                    acc.2 = code.clone();
+                }
+            }
+        }
+        expected
+    }
+
+    proptest! {
+        #[test]
+        fn loads_accounts_and_storage(mut provider in provider()) {
+            let expected = provider.clone();
+            let mut state = State::new(&mut provider).unwrap();
+
+            assert_reads(&mut state, &expected)?;
+        }
+
+        #[test]
+        fn reads_missing_accounts_as_empty(mut provider in provider(), address in address(), key in key()) {
+            provider.accounts.remove(&address);
+            let expected = provider.clone();
+            let mut state = State::new(&mut provider).unwrap();
+
+            prop_assert_eq!(state.nonce(&address).unwrap(), U256::zero());
+            prop_assert_eq!(state.balance(&address).unwrap(), U256::zero());
+            prop_assert!(state.code(&address).unwrap().is_empty());
+            prop_assert!(!state.exist(&address).unwrap());
+            prop_assert_eq!(
+                state.storage_at(&address, &key).unwrap(),
+                expected.storage_at(&address, &key).unwrap().unwrap_or_default()
+            );
+        }
+
+        #[test]
+        fn prefetches_accounts_and_storage(
+            mut provider in provider(),
+            addresses in prop::collection::vec(address(), 0..4),
+            keys in prop::collection::vec((address(), key()), 0..8),
+        ) {
+            let expected = provider.clone();
+            let mut state = State::new(&mut provider).unwrap();
+            state.prefetch(&addresses, &keys).unwrap();
+
+            assert_reads(&mut state, &expected)?;
+        }
+
+        #[test]
+        fn reads_modified_state(mut provider in provider(), ops in ops()) {
+            let original = provider.clone();
+            let expected = applied(&provider, &ops);
+            {
+                let mut state = State::new(&mut provider).unwrap();
+                for op in &ops {
+                    op.apply(&mut state);
+                }
+                assert_reads(&mut state, &expected)?;
+            }
+
+            // nothing is written before flushing
+            prop_assert_eq!(provider, original);
+        }
+
+        #[test]
+        fn flushes_modified_state(mut provider in provider(), ops in ops()) {
+            let original = provider.clone();
+            let expected = applied(&provider, &ops);
+            {
+                let mut state = State::new(&mut provider).unwrap();
+                // loading the accounts doesn't make them dirty
+                assert_reads(&mut state, &original)?;
+                for op in &ops {
+                    op.apply(&mut state);
+                }
+                state.update_state().unwrap();
+            }
+
+            prop_assert_eq!(provider, expected);
+        }
+
+        #[test]
+        fn commits_or_reverts_nested_state(mut provider in provider(), ops in ops(), commit in any::<bool>()) {
+            let expected = if commit { applied(&provider, &ops) } else { provider.clone() };
+            let mut state = State::new(&mut provider).unwrap();
+            {
+                let mut nested = state.nested();
+                let checkpoint = nested.checkpoint();
+                for op in &ops {
+                    op.apply(&mut nested);
+                }
+                if !commit {
+                    nested.revert_to(checkpoint);
+                }
+                let changes = nested.take_changes();
+                state.commit(changes);
+            }
+
+            assert_reads(&mut state, &expected)?;
+        }
+
+        #[test]
+        fn reverts_to_checkpoint(mut provider in provider(), ops in ops(), reverted in ops()) {
+            let expected = applied(&provider, &ops);
+            let mut state = State::new(&mut provider).unwrap();
+            for op in &ops {
+                op.apply(&mut state);
+            }
+            let checkpoint = state.checkpoint();
+            for op in &reverted {
+                op.apply(&mut state);
+                state.set_prepared_code(&H256::zero(), &U256::zero(), vec![1]);
+            }
+            state.revert_to(checkpoint);
+
+            assert_reads(&mut state, &expected)?;
+            prop_assert_eq!(state.prepared_code(&H256::zero(), &U256::zero()).unwrap(), None);
+        }
     }
 }
@@ -0,0 +1,461 @@
+//! Commitment to the state: the Ethereum-compatible state root.
+//!
+//! The accounts are kept in a Merkle Patricia trie by the hash of their address, and the
+//! storage of each account in its own trie by the hash of the key. The root of the storage
+//! trie is kept in the account. The tries are updated from the state diff of each execution.
+
+use address::Address;
+use error::Error;
+use primitive_types::{H256, U256};
+use provider::{StateChangeSet, EMPTY_CODE_HASH};
+use rlp::{Rlp, RlpStream};
+use trie::{self, TrieNodes, EMPTY_ROOT};
+use utils::keccak;
+
+/// Account as committed in the state trie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrieAccount {
+    pub nonce: U256,
+    pub balance: U256,
+    /// Root of the storage trie of the account
+    pub storage_root: H256,
+    pub code_hash: H256,
+}
+
+impl Default for TrieAccount {
+    fn default() -> Self {
+        TrieAccount {
+            nonce: U256::zero(),
+            balance: U256::zero(),
+            storage_root: EMPTY_ROOT,
+            code_hash: EMPTY_CODE_HASH,
+        }
+    }
+}
+
+fn invalid_account<T: ToString>(err: T) -> Error {
+    Error::InvalidTrieNode {
+        msg: format!("invalid account: {}", err.to_string()),
+    }
+}
+
+/// Big-endian bytes of the value without the leading zeros, as the integers are encoded in RLP
+fn trimmed(bytes: &[u8]) -> &[u8] {
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    &bytes[zeros..]
+}
+
+fn u256_bytes(value: &U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn decode_u256(rlp: &Rlp) -> Result<U256, Error> {
+    let bytes = rlp.data().map_err(invalid_account)?;
+    if bytes.len() > 32 {
+        return Err(invalid_account("integer longer than 32 bytes"));
+    }
+    Ok(U256::from_big_endian(bytes))
+}
+
+fn decode_h256(rlp: &Rlp) -> Result<H256, Error> {
+    let bytes = rlp.data().map_err(invalid_account)?;
+    if bytes.len() != 32 {
+        return Err(invalid_account(format!("hash of {} bytes", bytes.len())));
+    }
+    Ok(H256::from_slice(bytes))
+}
+
+impl TrieAccount {
+    /// RLP encoding of the account, the value of its leaf in the state trie
+    pub fn rlp_bytes(&self) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(4);
+        stream
+            .append(&trimmed(&u256_bytes(&self.nonce)))
+            .append(&trimmed(&u256_bytes(&self.balance)))
+            .append(&self.storage_root.as_bytes())
+            .append(&self.code_hash.as_bytes());
+        stream.out()
+    }
+
+    pub fn from_rlp(bytes: &[u8]) -> Result<Self, Error> {
+        let rlp = Rlp::new(bytes);
+        if rlp.item_count().map_err(invalid_account)? != 4 {
+            return Err(invalid_account("expected 4 items"));
+        }
+        Ok(TrieAccount {
+            nonce: decode_u256(&rlp.at(0).map_err(invalid_account)?)?,
+            balance: decode_u256(&rlp.at(1).map_err(invalid_account)?)?,
+            storage_root: decode_h256(&rlp.at(2).map_err(invalid_account)?)?,
+            code_hash: decode_h256(&rlp.at(3).map_err(invalid_account)?)?,
+        })
+    }
+}
+
+/// RLP encoding of the storage value, the value of its leaf in the storage trie
+pub fn storage_rlp_bytes(value: &H256) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+    stream.append(&trimmed(value.as_bytes()));
+    stream.out()
+}
+
+pub fn storage_from_rlp(bytes: &[u8]) -> Result<H256, Error> {
+    let data = Rlp::new(bytes).data().map_err(invalid_account)?;
+    if data.len() > 32 {
+        return Err(invalid_account("storage value longer than 32 bytes"));
+    }
+    let mut value = H256::zero();
+    value.as_bytes_mut()[32 - data.len()..].copy_from_slice(data);
+    Ok(value)
+}
+
+/// Proof of an account and its storage against a state root, see `StateTrie::get_proof`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountProof {
+    pub address: Address,
+    /// The account, `None` if the proof shows the account doesn't exist
+    pub account: Option<TrieAccount>,
+    /// Nodes of the state trie on the path of the account, starting with the root
+    pub proof: Vec<Vec<u8>>,
+    pub storage: Vec<StorageProof>,
+}
+
+/// Proof of a storage value against the storage root of the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageProof {
+    pub key: H256,
+    /// The value, zero if the key isn't set
+    pub value: H256,
+    /// Nodes of the storage trie on the path of the key, starting with the root
+    pub proof: Vec<Vec<u8>>,
+}
+
+/// Verifies the proof of the account and its storage against the state root
+pub fn verify_proof(state_root: &H256, proof: &AccountProof) -> Result<(), Error> {
+    let account = match trie::verify_proof(state_root, keccak(proof.address).as_bytes(), &proof.proof)? {
+        Some(bytes) => Some(TrieAccount::from_rlp(&bytes)?),
+        None => None,
+    };
+    if account != proof.account {
+        return Err(Error::InvalidProof {
+            msg: format!("account {} doesn't match the proof", proof.address),
+        });
+    }
+
+    let storage_root = account.map_or(EMPTY_ROOT, |account| account.storage_root);
+    for storage in &proof.storage {
+        let value = match trie::verify_proof(&storage_root, keccak(storage.key).as_bytes(), &storage.proof)? {
+            Some(bytes) => storage_from_rlp(&bytes)?,
+            None => H256::zero(),
+        };
+        if value != storage.value {
+            return Err(Error::InvalidProof {
+                msg: format!("storage {} doesn't match the proof", storage.key),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Tries of the accounts and their storage, with the state root
+#[derive(Debug, Clone)]
+pub struct StateTrie {
+    nodes: TrieNodes,
+    root: H256,
+}
+
+impl Default for StateTrie {
+    fn default() -> Self {
+        StateTrie::new()
+    }
+}
+
+impl StateTrie {
+    /// Empty state
+    pub fn new() -> Self {
+        StateTrie {
+            nodes: TrieNodes::new(),
+            root: EMPTY_ROOT,
+        }
+    }
+
+    /// State at the `root`, with the nodes of the tries
+    pub fn from_nodes(nodes: TrieNodes, root: H256) -> Self {
+        StateTrie { nodes, root }
+    }
+
+    /// State root, the root of the accounts trie
+    pub fn root(&self) -> H256 {
+        self.root
+    }
+
+    /// Nodes of the tries by their hash, including the nodes of the previous roots
+    pub fn nodes(&self) -> &TrieNodes {
+        &self.nodes
+    }
+
+    pub fn account(&self, address: &Address) -> Result<Option<TrieAccount>, Error> {
+        match trie::get(&self.nodes, &self.root, keccak(address).as_bytes())? {
+            Some(bytes) => Ok(Some(TrieAccount::from_rlp(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn storage(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        let storage_root = match self.account(address)? {
+            Some(account) => account.storage_root,
+            None => return Ok(None),
+        };
+        match trie::get(&self.nodes, &storage_root, keccak(key).as_bytes())? {
+            Some(bytes) => Ok(Some(storage_from_rlp(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the proof of the account and its storage at the `storage_keys`
+    pub fn get_proof(&self, address: &Address, storage_keys: &[H256]) -> Result<AccountProof, Error> {
+        self.get_proof_at(&self.root, address, storage_keys)
+    }
+
+    /// Returns the proof of the account and its storage in the state of an older root
+    pub fn get_proof_at(&self, state_root: &H256, address: &Address, storage_keys: &[H256]) -> Result<AccountProof, Error> {
+        let key = keccak(address);
+        let account = match trie::get(&self.nodes, state_root, key.as_bytes())? {
+            Some(bytes) => Some(TrieAccount::from_rlp(&bytes)?),
+            None => None,
+        };
+        let storage_root = account.as_ref().map_or(EMPTY_ROOT, |account| account.storage_root);
+
+        let mut storage = Vec::with_capacity(storage_keys.len());
+        for storage_key in storage_keys {
+            let key = keccak(storage_key);
+            let value = match trie::get(&self.nodes, &storage_root, key.as_bytes())? {
+                Some(bytes) => storage_from_rlp(&bytes)?,
+                None => H256::zero(),
+            };
+            storage.push(StorageProof {
+                key: *storage_key,
+                value,
+                proof: trie::prove(&self.nodes, &storage_root, key.as_bytes())?,
+            });
+        }
+
+        Ok(AccountProof {
+            address: *address,
+            account,
+            proof: trie::prove(&self.nodes, state_root, key.as_bytes())?,
+            storage,
+        })
+    }
+
+    /// Inserts the account, replacing the account at `address` and its storage
+    pub fn insert_account(&mut self, address: &Address, account: &TrieAccount) -> Result<H256, Error> {
+        self.root = trie::insert(&mut self.nodes, &self.root, keccak(address).as_bytes(), account.rlp_bytes())?;
+        Ok(self.root)
+    }
+
+    pub fn remove_account(&mut self, address: &Address) -> Result<H256, Error> {
+        self.root = trie::remove(&mut self.nodes, &self.root, keccak(address).as_bytes())?;
+        Ok(self.root)
+    }
+
+    /// Sets the storage of the account, creating the account if it doesn't exist.
+    /// A zero value removes the key.
+    pub fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<H256, Error> {
+        let mut account = self.account(address)?.unwrap_or_default();
+        let value = if value.is_zero() { vec![] } else { storage_rlp_bytes(value) };
+        account.storage_root = trie::insert(&mut self.nodes, &account.storage_root, keccak(key).as_bytes(), value)?;
+        self.insert_account(address, &account)
+    }
+
+    /// Applies the state diff of an execution and returns the new state root.
+    /// If one of the changes can't be applied, e.g. updating an account that doesn't exist,
+    /// the state root isn't changed.
+    pub fn apply_changes(&mut self, changes: &StateChangeSet) -> Result<H256, Error> {
+        let root = self.root;
+        let result = self.apply(changes);
+        if result.is_err() {
+            self.root = root;
+        }
+        result
+    }
+
+    fn apply(&mut self, changes: &StateChangeSet) -> Result<H256, Error> {
+        for (address, code) in &changes.created {
+            // the balance sent to the address before the contract was created is kept
+            let mut account = self.account(address)?.unwrap_or_default();
+            account.code_hash = keccak(code);
+            self.insert_account(address, &account)?;
+        }
+        for (address, balance, nonce) in &changes.updated {
+            let mut account = self
+                .account(address)?
+                .ok_or(Error::InvalidAddress { address: *address })?;
+            account.balance = *balance;
+            account.nonce = *nonce;
+            self.insert_account(address, &account)?;
+        }
+        for (address, key, value) in &changes.storage {
+            self.set_storage(address, key, value)?;
+        }
+        for address in &changes.deleted {
+            self.remove_account(address)?;
+        }
+        Ok(self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_state_root() {
+        let mut state = StateTrie::new();
+        assert_eq!(state.root(), EMPTY_ROOT);
+
+        // genesis of the blockchain tests of the Ethereum test suite:
+        // a single account with only a balance
+        let address = "a94f5374fce5edbc8e2a8697c15331677e6ebf0b".parse::<Address>().unwrap();
+        let account = TrieAccount {
+            balance: U256::from(10_000_000_000u64),
+            ..Default::default()
+        };
+        let root = state.insert_account(&address, &account).unwrap();
+        assert_eq!(
+            format!("{:x}", root),
+            "cafd881ab193703b83816c49ff6c2bf6ba6f464a1be560c42106128c8dbc35e7"
+        );
+        assert_eq!(state.account(&address).unwrap(), Some(account));
+    }
+
+    #[test]
+    fn computes_state_root_with_storage() {
+        // custom genesis of the go-ethereum genesis tests, the account 0x01 with
+        // a balance of 1 and the value 0x01 at the key 0x01 (left-aligned)
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        let address = Address::from_slice(&one[..20]);
+        let mut state = StateTrie::new();
+        state
+            .insert_account(&address, &TrieAccount {
+                balance: U256::one(),
+                ..Default::default()
+            })
+            .unwrap();
+        let root = state.set_storage(&address, &H256::from(one), &H256::from(one)).unwrap();
+        assert_eq!(
+            format!("{:x}", root),
+            "8257aee1cdaa2c42ae5dfad636710ee73437466b7504770feb19d63e2be3c913"
+        );
+
+        // the values are RLP strings without the leading zeros
+        assert_eq!(storage_rlp_bytes(&H256::from_low_u64_be(1)), vec![0x01]);
+        assert_eq!(storage_rlp_bytes(&H256::from_low_u64_be(0x80)), vec![0x81, 0x80]);
+        assert_eq!(storage_rlp_bytes(&H256::from(one))[0], 0xa0);
+    }
+
+    #[test]
+    fn applies_changes() {
+        let mut state = StateTrie::new();
+        let sender = Address::repeat_byte(0x11);
+        let contract = Address::repeat_byte(0x22);
+        state.insert_account(&sender, &TrieAccount::default()).unwrap();
+        let empty = state.root();
+
+        let changes = StateChangeSet {
+            created: vec![(contract, vec![0, 0x61, 0x73, 0x6d])],
+            updated: vec![(sender, U256::from(5), U256::one())],
+            storage: vec![(contract, H256::repeat_byte(1), H256::from_low_u64_be(2))],
+            ..Default::default()
+        };
+        let root = state.apply_changes(&changes).unwrap();
+        assert_ne!(root, empty);
+        assert_eq!(state.storage(&contract, &H256::repeat_byte(1)).unwrap(), Some(H256::from_low_u64_be(2)));
+        assert_eq!(state.account(&sender).unwrap().unwrap().balance, U256::from(5));
+
+        // the storage is part of the root
+        let changes = StateChangeSet {
+            storage: vec![(contract, H256::repeat_byte(1), H256::zero())],
+            ..Default::default()
+        };
+        let cleared = state.apply_changes(&changes).unwrap();
+        assert_ne!(cleared, root);
+        assert_eq!(state.account(&contract).unwrap().unwrap().storage_root, EMPTY_ROOT);
+
+        let changes = StateChangeSet {
+            deleted: vec![contract],
+            ..Default::default()
+        };
+        state.apply_changes(&changes).unwrap();
+        let mut expected = StateTrie::new();
+        expected
+            .insert_account(&sender, &TrieAccount {
+                nonce: U256::one(),
+                balance: U256::from(5),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(state.root(), expected.root());
+    }
+
+    #[test]
+    fn proves_accounts_and_storage() {
+        let mut state = StateTrie::new();
+        let contract = Address::repeat_byte(0x22);
+        for byte in 0..20u8 {
+            let account = TrieAccount {
+                balance: U256::from(byte),
+                ..Default::default()
+            };
+            state.insert_account(&Address::repeat_byte(byte), &account).unwrap();
+            state.set_storage(&contract, &H256::repeat_byte(byte), &H256::from_low_u64_be(byte as u64 + 1)).unwrap();
+        }
+        let old_root = state.root();
+        state.set_storage(&contract, &H256::repeat_byte(1), &H256::from_low_u64_be(100)).unwrap();
+
+        let keys = [H256::repeat_byte(1), H256::repeat_byte(0xff)];
+        let proof = state.get_proof(&contract, &keys).unwrap();
+        assert_eq!(proof.account, state.account(&contract).unwrap());
+        assert_eq!(proof.storage[0].value, H256::from_low_u64_be(100));
+        assert_eq!(proof.storage[1].value, H256::zero());
+        verify_proof(&state.root(), &proof).unwrap();
+
+        // the proof of the older root doesn't prove the newer state
+        let old_proof = state.get_proof_at(&old_root, &contract, &keys).unwrap();
+        assert_eq!(old_proof.storage[0].value, H256::from_low_u64_be(2));
+        verify_proof(&old_root, &old_proof).unwrap();
+        match verify_proof(&state.root(), &old_proof) {
+            Err(Error::InvalidProof { .. }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut forged = proof.clone();
+        forged.storage[1].value = H256::from_low_u64_be(1);
+        match verify_proof(&state.root(), &forged) {
+            Err(Error::InvalidProof { msg }) => assert_eq!(msg, format!("storage {} doesn't match the proof", keys[1])),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // an account that doesn't exist has no storage
+        let proof = state.get_proof(&Address::repeat_byte(0x33), &keys).unwrap();
+        assert_eq!(proof.account, None);
+        verify_proof(&state.root(), &proof).unwrap();
+    }
+
+    #[test]
+    fn keeps_root_on_failure() {
+        let mut state = StateTrie::new();
+        let changes = StateChangeSet {
+            created: vec![(Address::repeat_byte(0x22), vec![0, 0x61, 0x73, 0x6d])],
+            updated: vec![(Address::repeat_byte(0x33), U256::one(), U256::one())],
+            ..Default::default()
+        };
+        match state.apply_changes(&changes) {
+            Err(Error::InvalidAddress { address }) => assert_eq!(address, Address::repeat_byte(0x33)),
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(state.root(), EMPTY_ROOT);
+    }
+}
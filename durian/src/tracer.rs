@@ -0,0 +1,241 @@
+use address::Address;
+use execute::ResultData;
+use log_entry::LogEntry;
+use outcome::ExecutionOutcome;
+use primitive_types::{H256, U256};
+use std::fmt::Write;
+
+/// Kind of the execution frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+	Call,
+	Create,
+}
+
+/// Execution frame, entered for the transaction and for every nested call
+#[derive(Debug)]
+pub struct Frame<'a> {
+	pub kind: FrameKind,
+	/// Depth of the frame, zero for the transaction itself
+	pub depth: usize,
+	pub sender: Address,
+	/// Address of the called or created contract
+	pub address: Address,
+	pub value: U256,
+	pub gas: U256,
+	/// Constructor code, empty for calls
+	pub code: &'a [u8],
+	/// Call arguments
+	pub input: &'a [u8],
+}
+
+/// Event emitted by a host function
+#[derive(Debug)]
+pub enum HostEvent<'a> {
+	StorageRead {
+		address: Address,
+		key: H256,
+		value: H256,
+	},
+	StorageWrite {
+		address: Address,
+		key: H256,
+		value: H256,
+	},
+	Log(&'a LogEntry),
+	Balance {
+		address: Address,
+		balance: U256,
+	},
+}
+
+/// Receives structured events of the execution.
+///
+/// All methods have empty default implementations, so tracers implement only
+/// the events they are interested in.
+pub trait Tracer {
+	/// A frame is entered, before its code is loaded
+	fn enter_frame(&mut self, _frame: &Frame) {}
+
+	/// The last entered frame is exited.
+	/// `output` is the returned (or reverted) data, for created contracts it is the deployed code.
+	fn exit_frame(&mut self, _outcome: &ExecutionOutcome, _gas_used: &U256, _output: &[u8]) {}
+
+	/// A host function, imported by the contract from `env`, is invoked
+	fn host_function(&mut self, _name: &'static str) {}
+
+	/// A host function read or changed the state
+	fn host_event(&mut self, _event: &HostEvent) {}
+
+	/// Gas is charged, `amount` and `gas_left` are in wasm gas units
+	/// (gas * `opcodes_div` / `opcodes_mul`)
+	fn gas_charge(&mut self, _amount: u64, _gas_left: u64) {}
+
+	/// The transaction is executed
+	fn outcome(&mut self, _result: &ResultData) {}
+}
+
+/// Tracer that ignores all the events
+pub struct NoopTracer;
+
+impl Tracer for NoopTracer {}
+
+#[derive(Debug, Clone)]
+struct CallFrame {
+	kind: FrameKind,
+	sender: Address,
+	address: Address,
+	value: U256,
+	gas: U256,
+	code: Vec<u8>,
+	input: Vec<u8>,
+	gas_used: U256,
+	output: Vec<u8>,
+	error: Option<String>,
+	calls: Vec<CallFrame>,
+}
+
+/// Builds the call tree of the transaction, which can be written as JSON
+/// in the format of Parity's `trace_transaction`.
+#[derive(Debug, Default)]
+pub struct CallTracer {
+	stack: Vec<CallFrame>,
+	root: Option<CallFrame>,
+}
+
+impl CallTracer {
+	pub fn new() -> Self {
+		CallTracer::default()
+	}
+
+	/// Writes the traces as a JSON array, a trace per frame in the depth-first order.
+	/// `traceAddress` of a trace is the path of the frame from the root frame.
+	pub fn to_json(&self) -> String {
+		let mut traces = Vec::new();
+		if let Some(ref root) = self.root {
+			write_traces(root, &mut vec![], &mut traces);
+		}
+
+		format!("[{}]", traces.join(","))
+	}
+}
+
+impl Tracer for CallTracer {
+	fn enter_frame(&mut self, frame: &Frame) {
+		self.stack.push(CallFrame {
+			kind: frame.kind,
+			sender: frame.sender,
+			address: frame.address,
+			value: frame.value,
+			gas: frame.gas,
+			code: frame.code.to_vec(),
+			input: frame.input.to_vec(),
+			gas_used: U256::zero(),
+			output: vec![],
+			error: None,
+			calls: vec![],
+		});
+	}
+
+	fn exit_frame(&mut self, outcome: &ExecutionOutcome, gas_used: &U256, output: &[u8]) {
+		let mut frame = match self.stack.pop() {
+			Some(frame) => frame,
+			None => return,
+		};
+		frame.gas_used = *gas_used;
+		frame.output = output.to_vec();
+		if !outcome.is_success() {
+			frame.error = Some(format!("{}", outcome));
+		}
+
+		match self.stack.last_mut() {
+			Some(parent) => parent.calls.push(frame),
+			None => self.root = Some(frame),
+		}
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	let mut s = String::with_capacity(2 + bytes.len() * 2);
+	s.push_str("0x");
+	for byte in bytes {
+		let _ = write!(s, "{:02x}", byte);
+	}
+	s
+}
+
+fn json_string(value: &str) -> String {
+	let mut s = String::with_capacity(value.len() + 2);
+	s.push('"');
+	for c in value.chars() {
+		match c {
+			'"' => s.push_str("\\\""),
+			'\\' => s.push_str("\\\\"),
+			'\n' => s.push_str("\\n"),
+			c if (c as u32) < 0x20 => {
+				let _ = write!(s, "\\u{:04x}", c as u32);
+			}
+			c => s.push(c),
+		}
+	}
+	s.push('"');
+	s
+}
+
+fn write_traces(frame: &CallFrame, trace_address: &mut Vec<usize>, traces: &mut Vec<String>) {
+	let (kind, action, result) = match frame.kind {
+		FrameKind::Call => (
+			"call",
+			format!(
+				r#"{{"callType":"call","from":"{:#x}","gas":"{:#x}","input":"{}","to":"{:#x}","value":"{:#x}"}}"#,
+				frame.sender,
+				frame.gas,
+				hex(&frame.input),
+				frame.address,
+				frame.value
+			),
+			format!(
+				r#"{{"gasUsed":"{:#x}","output":"{}"}}"#,
+				frame.gas_used,
+				hex(&frame.output)
+			),
+		),
+		FrameKind::Create => (
+			"create",
+			format!(
+				r#"{{"from":"{:#x}","gas":"{:#x}","init":"{}","input":"{}","value":"{:#x}"}}"#,
+				frame.sender,
+				frame.gas,
+				hex(&frame.code),
+				hex(&frame.input),
+				frame.value
+			),
+			format!(
+				r#"{{"address":"{:#x}","code":"{}","gasUsed":"{:#x}"}}"#,
+				frame.address,
+				hex(&frame.output),
+				frame.gas_used
+			),
+		),
+	};
+	let outcome = match frame.error {
+		Some(ref error) => format!(r#""error":{}"#, json_string(error)),
+		None => format!(r#""result":{}"#, result),
+	};
+	let path: Vec<String> = trace_address.iter().map(|i| i.to_string()).collect();
+
+	traces.push(format!(
+		r#"{{"action":{},{},"subtraces":{},"traceAddress":[{}],"type":"{}"}}"#,
+		action,
+		outcome,
+		frame.calls.len(),
+		path.join(","),
+		kind
+	));
+
+	for (index, call) in frame.calls.iter().enumerate() {
+		trace_address.push(index);
+		write_traces(call, trace_address, traces);
+		trace_address.pop();
+	}
+}
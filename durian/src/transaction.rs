@@ -21,6 +21,9 @@ pub struct Transaction {
     pub gas_price: U256,
     pub action: Action,
     pub args: Bytes,
+    /// Accounts and storage keys the transaction is expected to access,
+    /// fetched in one batch before the execution
+    pub access_list: Vec<(Address, Vec<H256>)>,
 }
 
 impl Transaction {
@@ -43,6 +46,7 @@ impl Transaction {
             gas,
             gas_price,
             args,
+            access_list: vec![],
         }
     }
 
@@ -62,6 +66,7 @@ impl Transaction {
             gas,
             gas_price,
             args,
+            access_list: vec![],
         }
     }
 
@@ -80,6 +85,12 @@ impl Transaction {
             gas,
             gas_price,
             args,
+            access_list: vec![],
         }
     }
+
+    pub fn with_access_list(mut self, access_list: Vec<(Address, Vec<H256>)>) -> Self {
+        self.access_list = access_list;
+        self
+    }
 }
@@ -0,0 +1,153 @@
+//! Records the location of the instruction a contract traps at.
+//!
+//! In the debug and the profiling modes, before every instruction that may trap, the
+//! instrumented code stores the location of the instruction in a global. The normal execution
+//! only stores the index of the function being executed, at the start of every function and
+//! after the calls of the contract functions. The index of the global is kept in a custom
+//! section, so the location can be read once the execution trapped. The stores aren't
+//! metered, so the gas used doesn't change.
+
+use debugger::Location;
+use error::Error;
+use outcome::TrapLocation;
+use parity_wasm::builder;
+use parity_wasm::elements::{self, Instruction};
+use parser;
+use wasmi;
+
+/// Custom section holding the index of the global, as little endian `u32`
+const SECTION_NAME: &str = "durian.trap_site";
+
+/// Value of the global until an instruction that may trap is executed
+const NO_SITE: i64 = -1;
+
+/// Offset stored by the normal execution, which only records the function
+const NO_OFFSET: u32 = u32::MAX;
+
+fn may_trap(instruction: &Instruction) -> bool {
+	use parity_wasm::elements::Instruction::*;
+
+	matches!(
+		*instruction,
+		Unreachable
+			| Call(_) | CallIndirect(..)
+			| I32Load(..) | I64Load(..) | I32Load8S(..) | I32Load8U(..) | I32Load16S(..)
+			| I32Load16U(..) | I64Load8S(..) | I64Load8U(..) | I64Load16S(..) | I64Load16U(..)
+			| I64Load32S(..) | I64Load32U(..)
+			| I32Store(..) | I64Store(..) | I32Store8(..) | I32Store16(..) | I64Store8(..)
+			| I64Store16(..) | I64Store32(..)
+			| I32DivS | I32DivU | I32RemS | I32RemU | I64DivS | I64DivU | I64RemS | I64RemU
+	)
+}
+
+fn encode(location: Location) -> i64 {
+	((location.function as i64) << 32) | location.offset as i64
+}
+
+fn decode(site: i64) -> Option<TrapLocation> {
+	if site < 0 {
+		return None;
+	}
+
+	let offset = site as u32;
+	Some(TrapLocation {
+		function: (site >> 32) as u32,
+		offset: if offset == NO_OFFSET { None } else { Some(offset) },
+		function_name: None,
+	})
+}
+
+/// Adds the global the locations are stored in, returns the module and the index of the global
+pub fn add_global(module: elements::Module) -> (elements::Module, u32) {
+	let imported = module.import_count(elements::ImportCountType::Global);
+	let defined = module.global_section().map_or(0, |gs| gs.entries().len());
+	let global = (imported + defined) as u32;
+
+	let module = builder::from_module(module)
+		.with_global(elements::GlobalEntry::new(
+			elements::GlobalType::new(elements::ValueType::I64, true),
+			elements::InitExpr::new(vec![Instruction::I64Const(NO_SITE), Instruction::End]),
+		))
+		.build();
+
+	(module, global)
+}
+
+/// Stores the location in the global if `original_instruction` may trap
+pub fn record(global: u32, location: Location, original_instruction: &Instruction, code: &mut Vec<Instruction>) {
+	if may_trap(original_instruction) {
+		code.push(Instruction::I64Const(encode(location)));
+		code.push(Instruction::SetGlobal(global));
+	}
+}
+
+/// Adds the global to the module instrumented from `original` and stores the index
+/// of the function being executed in it, for the normal execution
+pub fn record_functions(original: &elements::Module, module: elements::Module) -> Result<(elements::Module, u32), Error> {
+	let imported = original.import_count(elements::ImportCountType::Function) as u32;
+	let (mut module, global) = add_global(module);
+	parser::rewrite_contract_code(original, &mut module, |_, location, original_instruction, instruction, code| {
+		let site = encode(Location {
+			function: location.function,
+			offset: NO_OFFSET,
+		});
+		if location.offset == 0 {
+			code.push(Instruction::I64Const(site));
+			code.push(Instruction::SetGlobal(global));
+		}
+		code.push(instruction);
+		// the callee changed the global, the host functions don't
+		let returned = match *original_instruction {
+			Instruction::Call(function) => function >= imported,
+			Instruction::CallIndirect(..) => true,
+			_ => false,
+		};
+		if returned {
+			code.push(Instruction::I64Const(site));
+			code.push(Instruction::SetGlobal(global));
+		}
+		Ok(())
+	})?;
+
+	Ok((module, global))
+}
+
+/// Stores the index of the global in the module.
+/// The stack limiter drops the custom sections, so it is stored once the module is instrumented.
+pub fn set_global_index(module: &mut elements::Module, global: u32) {
+	module.set_custom_section(SECTION_NAME, global.to_le_bytes().to_vec());
+}
+
+/// Index of the global the locations are stored in, if the module records the trap sites
+pub fn global_index(module: &elements::Module) -> Option<u32> {
+	module
+		.custom_sections()
+		.find(|section| section.name() == SECTION_NAME)
+		.and_then(|section| {
+			let payload = section.payload();
+			if payload.len() != 4 {
+				return None;
+			}
+			let mut bytes = [0u8; 4];
+			bytes.copy_from_slice(payload);
+			Some(u32::from_le_bytes(bytes))
+		})
+}
+
+/// Location of the last instruction that may trap executed by the instance
+pub fn last_site(instance: &wasmi::ModuleRef, global: u32) -> Option<TrapLocation> {
+	let globals = instance.globals();
+	match globals.get(global as usize)?.get() {
+		wasmi::RuntimeValue::I64(site) => decode(site),
+		_ => None,
+	}
+}
+
+/// Resolves the function name from the `name` section of the original contract `code`
+pub fn resolve(code: &[u8], site: TrapLocation) -> TrapLocation {
+	let function_name = elements::Module::from_bytes(code)
+		.ok()
+		.and_then(|module| parser::function_names(&module).remove(&site.function));
+
+	TrapLocation { function_name, ..site }
+}
@@ -0,0 +1,485 @@
+//! Merkle Patricia trie, as specified in the Ethereum yellow paper.
+//!
+//! The nodes are kept in `TrieNodes` by their hash and never removed, so the trie of any
+//! root computed before can still be read. Updating the trie returns the new root.
+//! A child is referred to by its hash, or inlined in its parent if its RLP encoding
+//! is shorter than 32 bytes.
+
+use error::Error;
+use primitive_types::H256;
+use rlp::{Rlp, RlpStream};
+use std::collections::HashMap;
+use utils::keccak;
+
+/// Nodes of the tries by their hash
+pub type TrieNodes = HashMap<H256, Vec<u8>>;
+
+/// Root of the empty trie, the hash of the RLP encoding of an empty string
+pub const EMPTY_ROOT: H256 = H256([
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8,
+    0x6e, 0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63,
+    0xb4, 0x21,
+]);
+
+/// RLP encoding of an empty string, the reference to an empty child
+const EMPTY_REF: &[u8] = &[0x80];
+
+enum Node {
+    Empty,
+    /// Rest of the key in nibbles, and the value
+    Leaf(Vec<u8>, Vec<u8>),
+    /// Shared part of the keys in nibbles, and the reference to the child
+    Extension(Vec<u8>, Vec<u8>),
+    /// References to the children by the next nibble, and the value of the key ending here
+    Branch(Vec<Vec<u8>>, Option<Vec<u8>>),
+}
+
+fn invalid_node<T: ToString>(err: T) -> Error {
+    Error::InvalidTrieNode {
+        msg: err.to_string(),
+    }
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|byte| vec![byte >> 4, byte & 0x0f]).collect()
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(a, b)| a == b).count()
+}
+
+/// Hex-prefix encoding of the nibbles, flagging a leaf and an odd number of nibbles
+fn encode_path(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 0x20 } else { 0x00 };
+    let mut bytes = Vec::with_capacity(path.len() / 2 + 1);
+    let rest = if path.len() % 2 == 1 {
+        bytes.push(flag | 0x10 | path[0]);
+        &path[1..]
+    } else {
+        bytes.push(flag);
+        path
+    };
+    for pair in rest.chunks(2) {
+        bytes.push(pair[0] << 4 | pair[1]);
+    }
+    bytes
+}
+
+fn decode_path(bytes: &[u8]) -> Result<(Vec<u8>, bool), Error> {
+    if bytes.is_empty() {
+        return Err(invalid_node("empty path"));
+    }
+    let leaf = bytes[0] & 0x20 != 0;
+    let mut path = nibbles(bytes);
+    if bytes[0] & 0x10 != 0 {
+        path.remove(0);
+    } else {
+        path.drain(..2);
+    }
+    Ok((path, leaf))
+}
+
+fn encode(node: &Node) -> Vec<u8> {
+    match node {
+        Node::Empty => EMPTY_REF.to_vec(),
+        Node::Leaf(path, value) => {
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&encode_path(path, true)).append(value);
+            stream.out()
+        }
+        Node::Extension(path, child) => {
+            let mut stream = RlpStream::new_list(2);
+            stream.append(&encode_path(path, false)).append_raw(child, 1);
+            stream.out()
+        }
+        Node::Branch(children, value) => {
+            let mut stream = RlpStream::new_list(17);
+            for child in children {
+                stream.append_raw(child, 1);
+            }
+            match value {
+                Some(value) => stream.append(value),
+                None => stream.append_empty_data(),
+            };
+            stream.out()
+        }
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Node, Error> {
+    let rlp = Rlp::new(bytes);
+    if rlp.is_data() && rlp.is_empty() {
+        return Ok(Node::Empty);
+    }
+    match rlp.item_count().map_err(invalid_node)? {
+        2 => {
+            let (path, leaf) = decode_path(rlp.at(0).and_then(|path| path.data()).map_err(invalid_node)?)?;
+            let second = rlp.at(1).map_err(invalid_node)?;
+            if leaf {
+                Ok(Node::Leaf(path, second.data().map_err(invalid_node)?.to_vec()))
+            } else {
+                Ok(Node::Extension(path, second.as_raw().to_vec()))
+            }
+        }
+        17 => {
+            let mut children = Vec::with_capacity(16);
+            for index in 0..16 {
+                children.push(rlp.at(index).map_err(invalid_node)?.as_raw().to_vec());
+            }
+            let value = rlp.at(16).and_then(|value| value.data()).map_err(invalid_node)?;
+            let value = if value.is_empty() { None } else { Some(value.to_vec()) };
+            Ok(Node::Branch(children, value))
+        }
+        count => Err(invalid_node(format!("node with {} items", count))),
+    }
+}
+
+fn load_hash(nodes: &TrieNodes, hash: &H256) -> Result<Node, Error> {
+    match nodes.get(hash) {
+        Some(bytes) => decode(bytes),
+        None => Err(Error::MissingTrieNode { hash: *hash }),
+    }
+}
+
+/// Hash of the child the reference refers to, `None` if the child is inlined
+fn reference_hash(reference: &[u8]) -> Result<Option<H256>, Error> {
+    let rlp = Rlp::new(reference);
+    if rlp.is_data() && rlp.size() == 32 {
+        return Ok(Some(H256::from_slice(rlp.data().map_err(invalid_node)?)));
+    }
+    Ok(None)
+}
+
+/// Loads the child the reference refers to
+fn load(nodes: &TrieNodes, reference: &[u8]) -> Result<Node, Error> {
+    match reference_hash(reference)? {
+        Some(hash) => load_hash(nodes, &hash),
+        None => decode(reference),
+    }
+}
+
+/// Stores the node and returns the reference to it
+fn store(nodes: &mut TrieNodes, node: &Node) -> Vec<u8> {
+    let bytes = encode(node);
+    if bytes.len() < 32 {
+        return bytes;
+    }
+    let hash = keccak(&bytes);
+    nodes.insert(hash, bytes);
+    let mut stream = RlpStream::new();
+    stream.append(&hash.as_bytes());
+    stream.out()
+}
+
+fn load_root(nodes: &TrieNodes, root: &H256) -> Result<Node, Error> {
+    if *root == EMPTY_ROOT {
+        return Ok(Node::Empty);
+    }
+    load_hash(nodes, root)
+}
+
+/// Stores the root node, hashed even if its encoding is short
+fn store_root(nodes: &mut TrieNodes, node: &Node) -> H256 {
+    let bytes = encode(node);
+    let hash = keccak(&bytes);
+    if hash != EMPTY_ROOT {
+        nodes.insert(hash, bytes);
+    }
+    hash
+}
+
+/// Looks the path up below the node, adding the hashed nodes it goes through to the proof
+fn get_node(nodes: &TrieNodes, node: Node, path: &[u8], proof: &mut Vec<Vec<u8>>) -> Result<Option<Vec<u8>>, Error> {
+    let (child, rest) = match node {
+        Node::Empty => return Ok(None),
+        Node::Leaf(key, value) => return Ok(if key == path { Some(value) } else { None }),
+        Node::Extension(key, child) => {
+            if !path.starts_with(&key) {
+                return Ok(None);
+            }
+            (child, &path[key.len()..])
+        }
+        Node::Branch(mut children, value) => match path.split_first() {
+            None => return Ok(value),
+            Some((index, rest)) => (children.swap_remove(*index as usize), rest),
+        },
+    };
+    let child = match reference_hash(&child)? {
+        Some(hash) => {
+            let bytes = nodes.get(&hash).ok_or(Error::MissingTrieNode { hash })?;
+            proof.push(bytes.clone());
+            decode(bytes)?
+        }
+        None => decode(&child)?,
+    };
+    get_node(nodes, child, rest, proof)
+}
+
+fn insert_node(nodes: &mut TrieNodes, node: Node, path: &[u8], value: Vec<u8>) -> Result<Node, Error> {
+    match node {
+        Node::Empty => Ok(Node::Leaf(path.to_vec(), value)),
+        Node::Leaf(key, old) => {
+            let common = common_prefix(&key, path);
+            if common == key.len() && common == path.len() {
+                return Ok(Node::Leaf(key, value));
+            }
+            let mut children = vec![EMPTY_REF.to_vec(); 16];
+            let mut branch_value = None;
+            if common == key.len() {
+                branch_value = Some(old);
+            } else {
+                children[key[common] as usize] = store(nodes, &Node::Leaf(key[common + 1..].to_vec(), old));
+            }
+            if common == path.len() {
+                branch_value = Some(value);
+            } else {
+                children[path[common] as usize] = store(nodes, &Node::Leaf(path[common + 1..].to_vec(), value));
+            }
+            Ok(extend(nodes, &path[..common], Node::Branch(children, branch_value)))
+        }
+        Node::Extension(key, child) => {
+            let common = common_prefix(&key, path);
+            if common == key.len() {
+                let child = insert_node(nodes, load(nodes, &child)?, &path[common..], value)?;
+                return Ok(Node::Extension(key, store(nodes, &child)));
+            }
+            let mut children = vec![EMPTY_REF.to_vec(); 16];
+            let mut branch_value = None;
+            children[key[common] as usize] = if common + 1 == key.len() {
+                child
+            } else {
+                store(nodes, &Node::Extension(key[common + 1..].to_vec(), child))
+            };
+            if common == path.len() {
+                branch_value = Some(value);
+            } else {
+                children[path[common] as usize] = store(nodes, &Node::Leaf(path[common + 1..].to_vec(), value));
+            }
+            Ok(extend(nodes, &path[..common], Node::Branch(children, branch_value)))
+        }
+        Node::Branch(mut children, branch_value) => match path.split_first() {
+            None => Ok(Node::Branch(children, Some(value))),
+            Some((index, rest)) => {
+                let index = *index as usize;
+                let child = insert_node(nodes, load(nodes, &children[index])?, rest, value)?;
+                children[index] = store(nodes, &child);
+                Ok(Node::Branch(children, branch_value))
+            }
+        },
+    }
+}
+
+/// Puts the branch under an extension with the shared part of the keys, if any
+fn extend(nodes: &mut TrieNodes, path: &[u8], branch: Node) -> Node {
+    if path.is_empty() {
+        branch
+    } else {
+        Node::Extension(path.to_vec(), store(nodes, &branch))
+    }
+}
+
+fn remove_node(nodes: &mut TrieNodes, node: Node, path: &[u8]) -> Result<Node, Error> {
+    match node {
+        Node::Empty => Ok(Node::Empty),
+        Node::Leaf(key, value) => Ok(if key == path { Node::Empty } else { Node::Leaf(key, value) }),
+        Node::Extension(key, child) => {
+            if !path.starts_with(&key) {
+                return Ok(Node::Extension(key, child));
+            }
+            let child = remove_node(nodes, load(nodes, &child)?, &path[key.len()..])?;
+            Ok(prepend(nodes, key, child))
+        }
+        Node::Branch(mut children, mut value) => {
+            match path.split_first() {
+                None => value = None,
+                Some((index, rest)) => {
+                    let index = *index as usize;
+                    let child = remove_node(nodes, load(nodes, &children[index])?, rest)?;
+                    children[index] = store(nodes, &child);
+                }
+            }
+            collapse(nodes, children, value)
+        }
+    }
+}
+
+/// Merges the path into the node below it, after one of its keys was removed
+fn prepend(nodes: &mut TrieNodes, mut path: Vec<u8>, node: Node) -> Node {
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf(key, value) => {
+            path.extend(key);
+            Node::Leaf(path, value)
+        }
+        Node::Extension(key, child) => {
+            path.extend(key);
+            Node::Extension(path, child)
+        }
+        branch => Node::Extension(path, store(nodes, &branch)),
+    }
+}
+
+/// Replaces the branch left with a single child or only a value
+fn collapse(nodes: &mut TrieNodes, children: Vec<Vec<u8>>, value: Option<Vec<u8>>) -> Result<Node, Error> {
+    let mut used = children
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| child.as_slice() != EMPTY_REF)
+        .map(|(index, _)| index);
+    match (used.next(), used.next(), value) {
+        (None, _, None) => Ok(Node::Empty),
+        (None, _, Some(value)) => Ok(Node::Leaf(vec![], value)),
+        (Some(index), None, None) => {
+            let child = load(nodes, &children[index])?;
+            Ok(prepend(nodes, vec![index as u8], child))
+        }
+        (_, _, value) => Ok(Node::Branch(children, value)),
+    }
+}
+
+/// Returns the value of the key in the trie of the `root`
+pub fn get(nodes: &TrieNodes, root: &H256, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    get_node(nodes, load_root(nodes, root)?, &nibbles(key), &mut vec![])
+}
+
+/// Returns the nodes on the path of the key in the trie of the `root`, starting with the root.
+/// They prove the value of the key, or that the trie doesn't have the key.
+pub fn prove(nodes: &TrieNodes, root: &H256, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut proof = vec![];
+    if *root != EMPTY_ROOT {
+        let bytes = nodes.get(root).ok_or(Error::MissingTrieNode { hash: *root })?;
+        proof.push(bytes.clone());
+    }
+    get_node(nodes, load_root(nodes, root)?, &nibbles(key), &mut proof)?;
+    Ok(proof)
+}
+
+/// Verifies the proof returned by `prove` against the `root` and returns the value of the key
+pub fn verify_proof(root: &H256, key: &[u8], proof: &[Vec<u8>]) -> Result<Option<Vec<u8>>, Error> {
+    let nodes: TrieNodes = proof.iter().map(|node| (keccak(node), node.clone())).collect();
+    get(&nodes, root, key).map_err(|err| match err {
+        Error::MissingTrieNode { hash } => Error::InvalidProof {
+            msg: format!("missing node {}", hash),
+        },
+        err => err,
+    })
+}
+
+/// Sets the value of the key in the trie of the `root` and returns the new root.
+/// An empty value removes the key.
+pub fn insert(nodes: &mut TrieNodes, root: &H256, key: &[u8], value: Vec<u8>) -> Result<H256, Error> {
+    if value.is_empty() {
+        return remove(nodes, root, key);
+    }
+    let node = insert_node(nodes, load_root(nodes, root)?, &nibbles(key), value)?;
+    Ok(store_root(nodes, &node))
+}
+
+/// Removes the key from the trie of the `root` and returns the new root
+pub fn remove(nodes: &mut TrieNodes, root: &H256, key: &[u8]) -> Result<H256, Error> {
+    let node = remove_node(nodes, load_root(nodes, root)?, &nibbles(key))?;
+    Ok(store_root(nodes, &node))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeMap;
+
+    fn root_of(pairs: &[(&str, &str)]) -> H256 {
+        let mut nodes = TrieNodes::new();
+        let mut root = EMPTY_ROOT;
+        for (key, value) in pairs {
+            root = insert(&mut nodes, &root, key.as_bytes(), value.as_bytes().to_vec()).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn computes_ethereum_roots() {
+        assert_eq!(root_of(&[]), EMPTY_ROOT);
+        assert_eq!(keccak(EMPTY_REF), EMPTY_ROOT);
+        // from the trie tests of the Ethereum test suite
+        assert_eq!(
+            format!("{:x}", root_of(&[("do", "verb"), ("horse", "stallion"), ("doge", "coin"), ("dog", "puppy")])),
+            "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+        );
+        assert_eq!(
+            format!("{:x}", root_of(&[("doe", "reindeer"), ("dog", "puppy"), ("dogglesworth", "cat")])),
+            "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+        );
+    }
+
+    #[test]
+    fn keeps_older_roots() {
+        let mut nodes = TrieNodes::new();
+        let first = insert(&mut nodes, &EMPTY_ROOT, b"dog", b"puppy".to_vec()).unwrap();
+        let second = insert(&mut nodes, &first, b"dog", b"hound".to_vec()).unwrap();
+        assert_eq!(get(&nodes, &first, b"dog").unwrap(), Some(b"puppy".to_vec()));
+        assert_eq!(get(&nodes, &second, b"dog").unwrap(), Some(b"hound".to_vec()));
+
+        match get(&TrieNodes::new(), &second, b"dog") {
+            Err(Error::MissingTrieNode { hash }) => assert_eq!(hash, second),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn proves_values() {
+        let mut nodes = TrieNodes::new();
+        let mut root = EMPTY_ROOT;
+        for index in 0..100u32 {
+            let key = keccak(index.to_be_bytes());
+            root = insert(&mut nodes, &root, key.as_bytes(), index.to_be_bytes().to_vec()).unwrap();
+        }
+
+        let key = keccak(7u32.to_be_bytes());
+        let proof = prove(&nodes, &root, key.as_bytes()).unwrap();
+        assert!(proof.len() > 1);
+        assert_eq!(verify_proof(&root, key.as_bytes(), &proof).unwrap(), Some(7u32.to_be_bytes().to_vec()));
+
+        // the proof of a missing key shows it's not in the trie
+        let missing = keccak(100u32.to_be_bytes());
+        let proof = prove(&nodes, &root, missing.as_bytes()).unwrap();
+        assert_eq!(verify_proof(&root, missing.as_bytes(), &proof).unwrap(), None);
+
+        // the proof doesn't prove a key it doesn't lead to
+        match verify_proof(&root, key.as_bytes(), &proof) {
+            Err(Error::InvalidProof { .. }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        match verify_proof(&H256::repeat_byte(1), missing.as_bytes(), &proof) {
+            Err(Error::InvalidProof { .. }) => {}
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert_eq!(prove(&nodes, &EMPTY_ROOT, key.as_bytes()).unwrap(), Vec::<Vec<u8>>::new());
+    }
+
+    proptest! {
+        #[test]
+        fn root_depends_only_on_content(
+            entries in prop::collection::vec((prop::collection::vec(0u8..4, 0..4), prop::collection::vec(any::<u8>(), 0..40)), 0..40),
+        ) {
+            // the keys and values are inserted and removed one by one, the expected trie is built at once
+            let mut nodes = TrieNodes::new();
+            let mut root = EMPTY_ROOT;
+            let mut expected = BTreeMap::new();
+            for (key, value) in entries {
+                root = insert(&mut nodes, &root, &key, value.clone()).unwrap();
+                if value.is_empty() {
+                    expected.remove(&key);
+                } else {
+                    expected.insert(key, value);
+                }
+            }
+
+            let mut expected_root = EMPTY_ROOT;
+            for (key, value) in &expected {
+                expected_root = insert(&mut nodes, &expected_root, key, value.clone()).unwrap();
+                prop_assert_eq!(get(&nodes, &root, key).unwrap(), Some(value.clone()));
+            }
+            prop_assert_eq!(root, expected_root);
+        }
+    }
+}
@@ -49,4 +49,6 @@ pub struct ActionParams {
     pub args: Bytes,
     /// Type of action (e.g. CALL, DELEGATECALL, CREATE, etc.)
     pub action_type: ActionType,
+    /// Depth of the call, zero for the transaction itself
+    pub depth: usize,
 }
@@ -0,0 +1,344 @@
+//! Stateless execution from a witness.
+//!
+//! `record` executes a transaction against a provider and collects the state it touches into
+//! a `Witness`: the nodes of the state tries that prove the accounts and the storage it reads
+//! and writes, the code of the executed contracts and the hashes of the blocks it reads.
+//! `execute` executes the transaction again from the witness, without a provider, and returns
+//! the state root after the execution. The block context and the block hashes are given by the
+//! verifier, e.g. from its chain, and a value the witness doesn't prove can't be read, so the
+//! result can be checked against the state roots, e.g. on another machine or in a fraud proof.
+
+use address::Address;
+use error::Error;
+use execute::{self, ResultData};
+use primitive_types::{H256, U256};
+use provider::{self, BlockContext, Provider, StateAccount, StateChangeSet};
+use serde::{Deserialize, Serialize};
+use state_trie::StateTrie;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use transaction::Transaction;
+use trie::TrieNodes;
+use utils::keccak;
+
+/// State a transaction touches, proven against the state root before the transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Witness {
+    /// State root before the transaction
+    pub state_root: H256,
+    /// Hashes of the blocks the transaction reads, checked against the hashes the verifier knows
+    pub block_hashes: BTreeMap<u64, H256>,
+    /// Nodes of the state tries, to read the touched accounts and storage and to update them
+    pub nodes: Vec<Vec<u8>>,
+    /// Code of the executed contracts
+    pub codes: Vec<Vec<u8>>,
+}
+
+/// Provider recording the state the execution touches
+struct Recorder<'a, P: Provider> {
+    provider: &'a mut P,
+    accounts: RefCell<BTreeSet<Address>>,
+    storage: RefCell<BTreeSet<(Address, H256)>>,
+    codes: RefCell<BTreeMap<H256, Vec<u8>>>,
+    block_hashes: RefCell<BTreeMap<u64, H256>>,
+    changes: StateChangeSet,
+}
+
+impl<'a, P: Provider> Recorder<'a, P> {
+    fn new(provider: &'a mut P) -> Self {
+        Recorder {
+            provider,
+            accounts: RefCell::new(BTreeSet::new()),
+            storage: RefCell::new(BTreeSet::new()),
+            codes: RefCell::new(BTreeMap::new()),
+            block_hashes: RefCell::new(BTreeMap::new()),
+            changes: StateChangeSet::default(),
+        }
+    }
+
+    /// Builds the witness from the recorded state and the tries of the state before the execution
+    fn witness(self, state: &StateTrie) -> Result<Witness, Error> {
+        let mut accounts = self.accounts.into_inner();
+        let mut storage = self.storage.into_inner();
+        accounts.extend(self.changes.created.iter().map(|(address, _)| *address));
+        accounts.extend(self.changes.updated.iter().map(|(address, _, _)| *address));
+        accounts.extend(self.changes.deleted.iter().cloned());
+        storage.extend(self.changes.storage.iter().map(|(address, key, _)| (*address, *key)));
+
+        let mut nodes = TrieNodes::new();
+        for address in &accounts {
+            let keys: Vec<H256> = storage
+                .iter()
+                .filter(|(storage_address, _)| storage_address == address)
+                .map(|(_, key)| *key)
+                .collect();
+            let proof = state.get_proof(address, &keys)?;
+            let proofs = proof.storage.into_iter().flat_map(|storage| storage.proof);
+            for node in proof.proof.into_iter().chain(proofs) {
+                nodes.insert(keccak(&node), node);
+            }
+        }
+
+        // removing a key can merge the branch above it with a node off its path
+        loop {
+            let mut updated = StateTrie::from_nodes(nodes.clone(), state.root());
+            match updated.apply_changes(&self.changes) {
+                Ok(_) => break,
+                Err(Error::MissingTrieNode { hash }) => {
+                    let node = state.nodes().get(&hash).ok_or(Error::MissingTrieNode { hash })?;
+                    nodes.insert(hash, node.clone());
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut nodes: Vec<(H256, Vec<u8>)> = nodes.into_iter().collect();
+        nodes.sort();
+        Ok(Witness {
+            state_root: state.root(),
+            block_hashes: self.block_hashes.into_inner(),
+            nodes: nodes.into_iter().map(|(_, node)| node).collect(),
+            codes: self.codes.into_inner().into_values().collect(),
+        })
+    }
+}
+
+impl<'a, P: Provider> Provider for Recorder<'a, P> {
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        self.accounts.borrow_mut().insert(*address);
+        self.provider.exist(address)
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        self.accounts.borrow_mut().insert(*address);
+        self.provider.account(address)
+    }
+
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.provider.update_account(address, bal, nonce)
+    }
+
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+        self.provider.create_contract(address, code)
+    }
+
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        self.storage.borrow_mut().insert((*address, *key));
+        self.provider.storage_at(address, key)
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.provider.set_storage(address, key, value)
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        let code = self.provider.code_by_hash(code_hash)?;
+        if let Some(code) = &code {
+            self.codes.borrow_mut().insert(*code_hash, code.clone());
+        }
+        Ok(code)
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.provider.timestamp()
+    }
+
+    fn block_number(&self) -> u64 {
+        self.provider.block_number()
+    }
+
+    fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        let hash = self.provider.block_hash(block_no)?;
+        self.block_hashes.borrow_mut().insert(block_no, hash);
+        Ok(hash)
+    }
+
+    fn block_author(&self) -> Result<Address, Error> {
+        self.provider.block_author()
+    }
+
+    fn difficulty(&self) -> Result<U256, Error> {
+        self.provider.difficulty()
+    }
+
+    fn gas_limit(&self) -> Result<U256, Error> {
+        self.provider.gas_limit()
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.provider.chain_id()
+    }
+
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        self.provider.base_fee()
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        self.provider.prev_randao()
+    }
+
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        self.provider.block_context()
+    }
+
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        self.changes = changes.clone();
+        self.provider.apply_changes(changes)
+    }
+
+    fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        self.provider.prepared_code(code_hash, version)
+    }
+
+    fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+        self.provider.set_prepared_code(code_hash, version, code)
+    }
+}
+
+/// Provider reading the state from a witness and updating its tries
+struct WitnessProvider {
+    state: StateTrie,
+    block: BlockContext,
+    block_hashes: BTreeMap<u64, H256>,
+    codes: BTreeMap<H256, Vec<u8>>,
+}
+
+impl Provider for WitnessProvider {
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        Ok(self.state.account(address)?.is_some())
+    }
+
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        Ok(self.state.account(address)?.map(|account| StateAccount {
+            nonce: account.nonce,
+            balance: account.balance,
+            code_hash: account.code_hash,
+        }))
+    }
+
+    fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            updated: vec![(*address, *bal, *nonce)],
+            ..Default::default()
+        })
+    }
+
+    fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            created: vec![(*address, code.clone())],
+            ..Default::default()
+        })
+    }
+
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        self.state.storage(address, key)
+    }
+
+    fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            storage: vec![(*address, *key, *value)],
+            ..Default::default()
+        })
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        if *code_hash == provider::EMPTY_CODE_HASH {
+            return Ok(Some(vec![]));
+        }
+        Ok(self.codes.get(code_hash).cloned())
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.block.timestamp
+    }
+
+    fn block_number(&self) -> u64 {
+        self.block.number
+    }
+
+    fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+        self.block_hashes.get(&block_no).cloned().ok_or(Error::InvalidWitness {
+            msg: format!("missing the hash of the block {}", block_no),
+        })
+    }
+
+    fn block_author(&self) -> Result<Address, Error> {
+        Ok(self.block.author)
+    }
+
+    fn difficulty(&self) -> Result<U256, Error> {
+        Ok(self.block.difficulty)
+    }
+
+    fn gas_limit(&self) -> Result<U256, Error> {
+        Ok(self.block.gas_limit)
+    }
+
+    fn chain_id(&self) -> u64 {
+        self.block.chain_id
+    }
+
+    fn base_fee(&self) -> Result<Option<U256>, Error> {
+        Ok(self.block.base_fee)
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(self.block.prev_randao)
+    }
+
+    fn block_context(&self) -> Result<BlockContext, Error> {
+        Ok(self.block.clone())
+    }
+
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        self.state.apply_changes(&changes)?;
+        Ok(())
+    }
+}
+
+/// Executes the transaction against the provider, like `execute::execute`, and records the
+/// witness of the state it touches. `state` has the tries of the state of the provider before
+/// the transaction, e.g. `MemoryProvider::state_trie`.
+pub fn record<P: Provider>(provider: &mut P, state: &StateTrie, transaction: &Transaction) -> Result<(ResultData, Witness), Error> {
+    let mut recorder = Recorder::new(provider);
+    let result = execute::execute(&mut recorder, transaction)?;
+    let witness = recorder.witness(state)?;
+    Ok((result, witness))
+}
+
+/// Executes the transaction from the witness in the `block` and returns the result with the state
+/// root after the execution. The witness must be of the `state_root` before the transaction and
+/// its block hashes must be in `block_hashes`, the hashes of the blocks the verifier knows.
+/// The execution fails with `Error::ProviderFailure` if the witness misses state it reads.
+pub fn execute(
+    witness: &Witness,
+    state_root: &H256,
+    block: &BlockContext,
+    block_hashes: &BTreeMap<u64, H256>,
+    transaction: &Transaction,
+) -> Result<(ResultData, H256), Error> {
+    if witness.state_root != *state_root {
+        return Err(Error::InvalidWitness {
+            msg: format!("witness of the state root {}, expected {}", witness.state_root, state_root),
+        });
+    }
+    for (number, hash) in &witness.block_hashes {
+        if block_hashes.get(number) != Some(hash) {
+            return Err(Error::InvalidWitness {
+                msg: format!("hash {} of the block {} is not known", hash, number),
+            });
+        }
+    }
+
+    // the nodes and the codes are keyed by their hash, so the witness can't forge them
+    let nodes: TrieNodes = witness.nodes.iter().map(|node| (keccak(node), node.clone())).collect();
+    let codes = witness.codes.iter().map(|code| (provider::code_hash(code), code.clone())).collect();
+    let mut provider = WitnessProvider {
+        state: StateTrie::from_nodes(nodes, witness.state_root),
+        block: block.clone(),
+        block_hashes: witness.block_hashes.clone(),
+        codes,
+    };
+    let result = execute::execute(&mut provider, transaction)?;
+    Ok((result, provider.state.root()))
+}
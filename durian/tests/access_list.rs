@@ -0,0 +1,93 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{self, call_tx};
+use common::{RecordingProvider, TestProvider};
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute;
+use durian::provider::Provider;
+use durian::transaction::Transaction;
+use primitive_types::H256;
+
+/// Methods reading the accounts and the storage
+const READS: &[&str] = &["account", "accounts", "storage_at", "storages"];
+
+/// Returns the storage at the keys 1 and 2
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(i32.store8 (i32.const 31) (i32.const 1))
+		(call $storage_read (i32.const 0) (i32.const 64))
+		(i32.store8 (i32.const 31) (i32.const 2))
+		(call $storage_read (i32.const 0) (i32.const 96))
+		(call $ret (i32.const 64) (i32.const 64)))
+)
+"#;
+
+
+fn key(byte: u64) -> H256 {
+	H256::from_low_u64_be(byte)
+}
+
+fn deploy(provider: &mut RecordingProvider) -> Address {
+	let contract = fixtures::deploy(provider, CONTRACT, H256::zero());
+	provider.state.set_storage(&contract, &key(1), &H256::repeat_byte(1)).unwrap();
+	provider.state.set_storage(&contract, &key(2), &H256::repeat_byte(2)).unwrap();
+	provider.clear();
+	contract
+}
+
+fn call(provider: &mut RecordingProvider, tx: &Transaction) {
+	let result = execute::execute(provider, tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+
+	let mut expected = H256::repeat_byte(1).as_bytes().to_vec();
+	expected.extend(H256::repeat_byte(2).as_bytes());
+	assert_eq!(result.data, expected);
+}
+
+#[test]
+fn prefetches_access_list() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = deploy(&mut provider);
+
+	let tx = call_tx(contract, vec![])
+		.with_access_list(vec![(contract, vec![key(1), key(2)])]);
+	call(&mut provider, &tx);
+
+	// the called account and the access list are fetched in one batch
+	assert_eq!(provider.calls_of(READS), vec!["accounts", "storages"]);
+}
+
+#[test]
+fn fetches_storage_missing_from_access_list() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = deploy(&mut provider);
+
+	let tx = call_tx(contract, vec![])
+		.with_access_list(vec![(contract, vec![key(1)])]);
+	call(&mut provider, &tx);
+
+	assert_eq!(provider.calls_of(READS), vec!["accounts", "storages", "storage_at"]);
+}
+
+#[test]
+fn rejects_missing_account_from_batch() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+
+	let missing = Address::repeat_byte(0x22);
+	let tx = call_tx(missing, vec![]);
+	match execute::execute(&mut provider, &tx) {
+		Err(Error::InvalidAddress { address }) => assert_eq!(address, missing),
+		result => panic!("unexpected result {:?}", result),
+	}
+	assert_eq!(provider.calls_of(READS), vec!["accounts"]);
+}
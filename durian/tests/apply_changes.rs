@@ -0,0 +1,82 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{self, call_tx, deploy, sender};
+use common::{RecordingProvider, TestProvider};
+use durian::error::Error;
+use durian::execute;
+use durian::provider::{Provider, StateChangeSet};
+use primitive_types::{H256, U256};
+
+/// Writes the byte of the input to the storage at the keys 1 and 2
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 63))
+		(i32.store8 (i32.const 31) (i32.const 2))
+		(call $storage_write (i32.const 0) (i32.const 32))
+		(i32.store8 (i32.const 31) (i32.const 1))
+		(call $storage_write (i32.const 0) (i32.const 32)))
+)
+"#;
+
+fn key(byte: u64) -> H256 {
+	H256::from_low_u64_be(byte)
+}
+
+fn value(byte: u8) -> H256 {
+	H256::from_low_u64_be(byte as u64)
+}
+
+#[test]
+fn applies_changes_at_once() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+	assert_eq!(provider.changes.len(), 1);
+	assert_eq!(provider.changes[0].created.len(), 1);
+	assert_eq!(provider.changes[0].created[0].0, contract);
+
+	let result = execute::execute(&mut provider, &call_tx(contract, vec![7])).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+
+	// the storage is sorted by the key
+	assert_eq!(
+		provider.changes[1],
+		StateChangeSet {
+			storage: vec![(contract, key(1), value(7)), (contract, key(2), value(7))],
+			..Default::default()
+		}
+	);
+	assert_eq!(provider.state.storage_at(&contract, &key(2)).unwrap(), Some(value(7)));
+
+	// the changes are applied at once
+	assert!(provider.calls_of(&["update_account", "create_contract", "set_storage"]).is_empty());
+}
+
+#[test]
+fn fails_when_applying_changes_fails() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+	let state = provider.state.clone();
+
+	provider.failing_changes = true;
+	match execute::execute(&mut provider, &call_tx(contract, vec![7])) {
+		Err(Error::ProviderFailure { msg }) => assert_eq!(msg, "Error: storage is full"),
+		result => panic!("unexpected result {:?}", result),
+	}
+	assert_eq!(provider.state, state);
+}
+
+#[test]
+fn applies_changes_by_default() {
+	let mut provider = TestProvider::new();
+	provider.add_account(sender(), U256::zero());
+	fixtures::applies_changes_atomically(&mut provider);
+}
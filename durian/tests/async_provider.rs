@@ -0,0 +1,207 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, create_tx};
+use common::{RecordingProvider, TestProvider};
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute::{self, ResultData};
+use durian::limits::Limit;
+use durian::provider::{AsyncProvider, BlockContext, Provider, ProviderFuture, StateAccount};
+use durian::schedule::Schedule;
+use primitive_types::{H256, U256};
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Increments the first byte of the storage at the key given as the first 32 bytes of the input
+/// and returns it. If the input has an address after the key, calls it with the key.
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $storage_read (i32.const 0) (i32.const 64))
+		(i32.store8 (i32.const 64) (i32.add (i32.load8_u (i32.const 64)) (i32.const 1)))
+		(call $storage_write (i32.const 0) (i32.const 64))
+		(if (i32.gt_u (call $input_length) (i32.const 32))
+			(then (drop (call $ccall (i64.const 100000) (i32.const 32) (i32.const 96)
+				(i32.const 0) (i32.const 32) (i32.const 128) (i32.const 0)))))
+		(call $ret (i32.const 64) (i32.const 1)))
+)
+"#;
+
+/// Async provider over `RecordingProvider` whose futures are ready on the second poll
+struct AsyncTestProvider {
+	inner: RecordingProvider,
+}
+
+/// Future that is pending once
+struct Delayed<T> {
+	output: Option<Result<T, Error>>,
+	pending: bool,
+}
+
+impl<T: Unpin> Future for Delayed<T> {
+	type Output = Result<T, Error>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+		let this = self.get_mut();
+		if this.pending {
+			this.pending = false;
+			cx.waker().wake_by_ref();
+			return Poll::Pending;
+		}
+		Poll::Ready(this.output.take().expect("polled after completion"))
+	}
+}
+
+fn delayed<T: Unpin + 'static>(output: Result<T, Error>) -> ProviderFuture<T> {
+	Box::pin(Delayed {
+		output: Some(output),
+		pending: true,
+	})
+}
+
+impl AsyncProvider for AsyncTestProvider {
+	fn exist(&self, address: &Address) -> ProviderFuture<bool> {
+		delayed(self.inner.exist(address))
+	}
+
+	fn account(&self, address: &Address) -> ProviderFuture<Option<StateAccount>> {
+		delayed(self.inner.account(address))
+	}
+
+	fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> ProviderFuture<()> {
+		delayed(self.inner.update_account(address, bal, nonce))
+	}
+
+	fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> ProviderFuture<()> {
+		delayed(self.inner.create_contract(address, code))
+	}
+
+	fn storage_at(&self, address: &Address, key: &H256) -> ProviderFuture<Option<H256>> {
+		delayed(self.inner.storage_at(address, key))
+	}
+
+	fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> ProviderFuture<()> {
+		delayed(self.inner.set_storage(address, key, value))
+	}
+
+	fn code_by_hash(&self, code_hash: &H256) -> ProviderFuture<Option<Vec<u8>>> {
+		delayed(self.inner.code_by_hash(code_hash))
+	}
+
+	fn block_hash(&self, block_no: u64) -> ProviderFuture<H256> {
+		delayed(self.inner.block_hash(block_no))
+	}
+
+	fn block_context(&self) -> ProviderFuture<BlockContext> {
+		delayed(self.inner.block_context())
+	}
+}
+
+fn noop_waker() -> Waker {
+	fn clone(_: *const ()) -> RawWaker {
+		RawWaker::new(ptr::null(), &VTABLE)
+	}
+	fn noop(_: *const ()) {}
+	static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+	unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+/// Polls the future until it's ready
+fn block_on<F: Future>(future: F) -> F::Output {
+	let waker = noop_waker();
+	let mut cx = Context::from_waker(&waker);
+	let mut future = Box::pin(future);
+	loop {
+		if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+			return output;
+		}
+	}
+}
+
+fn deploy(provider: &mut AsyncTestProvider) -> Address {
+	let tx = create_tx(wat::parse_str(CONTRACT).unwrap(), H256::zero());
+	let result = block_on(execute::execute_async(provider, &tx)).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	result.contract
+}
+
+fn with_max_fetch_rounds(max_fetch_rounds: usize) -> Schedule {
+	let mut schedule = Schedule::new_wasm();
+	schedule.limits.max_fetch_rounds = max_fetch_rounds;
+	schedule
+}
+
+#[test]
+fn executes_like_execute() {
+	let mut provider = AsyncTestProvider {
+		inner: RecordingProvider::new(TestProvider::new()),
+	};
+	let contract = deploy(&mut provider);
+	let mut expected = provider.inner.state.clone();
+
+	let key = H256::repeat_byte(7).as_bytes().to_vec();
+	let mut nested = key.clone();
+	nested.extend(contract.as_bytes());
+	for input in &[key.clone(), nested, key] {
+		let tx = call_tx(contract, input.clone());
+		let result: ResultData = block_on(execute::execute_async(&mut provider, &tx)).unwrap();
+		assert_eq!(result, execute::execute(&mut expected, &tx).unwrap());
+		assert_eq!(provider.inner.state, expected);
+	}
+
+	let value = provider.inner.state.storage_at(&contract, &H256::repeat_byte(7)).unwrap().unwrap();
+	assert_eq!(value.as_bytes()[0], 4);
+}
+
+#[test]
+fn fails_when_fetching_state_fails() {
+	let mut provider = AsyncTestProvider {
+		inner: RecordingProvider::new(TestProvider::new()),
+	};
+	let contract = deploy(&mut provider);
+	let state = provider.inner.state.clone();
+
+	provider.inner.failing = Some(contract);
+	let tx = call_tx(contract, H256::repeat_byte(7).as_bytes().to_vec());
+	match block_on(execute::execute_async(&mut provider, &tx)) {
+		Err(Error::ProviderFailure { msg }) => assert_eq!(msg, "Error: connection lost"),
+		result => panic!("unexpected result {:?}", result),
+	}
+	assert_eq!(provider.inner.state, state);
+}
+
+#[test]
+fn stops_after_max_fetch_rounds() {
+	let mut provider = AsyncTestProvider {
+		inner: RecordingProvider::new(TestProvider::new()),
+	};
+	let contract = deploy(&mut provider);
+	let state = provider.inner.state.clone();
+
+	// the code and then the storage are missed
+	let tx = call_tx(contract, H256::repeat_byte(7).as_bytes().to_vec());
+	match block_on(execute::execute_async_with_schedule(&mut provider, &tx, with_max_fetch_rounds(1))) {
+		Err(Error::LimitExceeded { limit }) => assert_eq!(limit, Limit::FetchRounds),
+		result => panic!("unexpected result {:?}", result),
+	}
+	assert_eq!(provider.inner.state, state);
+
+	let result = block_on(execute::execute_async_with_schedule(&mut provider, &tx, with_max_fetch_rounds(2))).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+}
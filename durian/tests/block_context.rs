@@ -0,0 +1,132 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, deploy};
+use common::{RecordingProvider, TestProvider};
+use durian::address::Address;
+use durian::execute::{self, ResultData};
+use durian::provider::Provider;
+use primitive_types::{H256, U256};
+
+/// Returns the chain ID as little endian `u64`, the base fee and the random seed
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "chain_id" (func $chain_id (result i64)))
+	(import "env" "basefee" (func $basefee (param i32)))
+	(import "env" "random_seed" (func $random_seed (param i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(i64.store (i32.const 0) (call $chain_id))
+		(call $basefee (i32.const 8))
+		(call $random_seed (i32.const 40))
+		(call $ret (i32.const 0) (i32.const 72)))
+)
+"#;
+
+/// Reads the block context twice and returns the timestamp and the number as little endian `u64`,
+/// the author, the difficulty, the gas limit and the hash of the previous block
+const CONTEXT_CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "timestamp" (func $timestamp (result i64)))
+	(import "env" "blocknumber" (func $blocknumber (result i64)))
+	(import "env" "coinbase" (func $coinbase (param i32)))
+	(import "env" "difficulty" (func $difficulty (param i32)))
+	(import "env" "gaslimit" (func $gaslimit (param i32)))
+	(import "env" "block_hash" (func $block_hash (param i64 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func $read
+		(i64.store (i32.const 0) (call $timestamp))
+		(i64.store (i32.const 8) (call $blocknumber))
+		(call $coinbase (i32.const 16))
+		(call $difficulty (i32.const 36))
+		(call $gaslimit (i32.const 68))
+		(call $block_hash (i64.sub (call $blocknumber) (i64.const 1)) (i32.const 100)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $read)
+		(call $read)
+		(call $ret (i32.const 0) (i32.const 132)))
+)
+"#;
+
+fn deploy_and_call(provider: &mut dyn Provider, code: &str) -> ResultData {
+	let contract = deploy(provider, code, H256::zero());
+	let result = execute::execute(provider, &call_tx(contract, vec![])).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	result
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+	let mut bytes = [0u8; 8];
+	bytes.copy_from_slice(&data[offset..offset + 8]);
+	u64::from_le_bytes(bytes)
+}
+
+/// Returns the chain ID, the base fee and the random seed the contract reads
+fn block_context(provider: &mut TestProvider) -> (u64, U256, H256) {
+	let result = deploy_and_call(provider, CONTRACT);
+	(
+		u64_at(&result.data, 0),
+		U256::from_big_endian(&result.data[8..40]),
+		H256::from_slice(&result.data[40..72]),
+	)
+}
+
+#[test]
+fn reads_block_context_fetched_once() {
+	// a distinct value for every block context field
+	let mut state = TestProvider::new();
+	state.timestamp = 1_600_000_000;
+	state.block_number = 42;
+	state.block_hashes.insert(41, H256::from_low_u64_be(41));
+	state.author = Address::repeat_byte(0xaa);
+	state.difficulty = U256::from(131_072);
+	state.gas_limit = U256::from(8_000_000);
+	state.chain_id = 3;
+	let mut provider = RecordingProvider::new(state);
+	let result = deploy_and_call(&mut provider, CONTEXT_CONTRACT);
+
+	let data = &result.data;
+	assert_eq!(u64_at(data, 0), 1_600_000_000);
+	assert_eq!(u64_at(data, 8), 42);
+	assert_eq!(Address::from_slice(&data[16..36]), Address::repeat_byte(0xaa));
+	assert_eq!(U256::from_big_endian(&data[36..68]), U256::from(131_072));
+	assert_eq!(U256::from_big_endian(&data[68..100]), U256::from(8_000_000));
+	assert_eq!(H256::from_slice(&data[100..132]), H256::from_low_u64_be(41));
+
+	// the context is fetched once per execution, by the deployment and by the call,
+	// the block hashes are fetched on every read
+	let calls = provider.calls.borrow();
+	let count = |method: &str| calls.iter().filter(|call| **call == method).count();
+	for method in &["timestamp", "block_number", "block_author", "difficulty", "gas_limit", "chain_id"] {
+		assert_eq!(count(method), 2, "{}", method);
+	}
+	assert_eq!(count("block_hash"), 2);
+}
+
+#[test]
+fn reads_block_context() {
+	let mut provider = TestProvider::new();
+	provider.chain_id = 5;
+	provider.base_fee = Some(U256::from(7_000_000_000u64));
+	provider.prev_randao = Some(H256::repeat_byte(0xab));
+
+	assert_eq!(
+		block_context(&mut provider),
+		(5, U256::from(7_000_000_000u64), H256::repeat_byte(0xab))
+	);
+}
+
+#[test]
+fn defaults_without_base_fee_and_random_beacon() {
+	let mut provider = TestProvider::new();
+
+	// the random seed is the difficulty, zero in the test provider
+	assert_eq!(block_context(&mut provider), (1, U256::zero(), H256::zero()));
+}
@@ -0,0 +1,229 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, deploy, deploy_with_schedule};
+use common::TestProvider;
+use durian::address::Address;
+use durian::execute;
+use durian::outcome::{ExecutionOutcome, TrapKind};
+use durian::schedule::Schedule;
+use durian::tracer::NoopTracer;
+use primitive_types::{H256, U256};
+
+/// Calls the contract at the address given as the input, passing its own address.
+/// Returns the number of the nested calls that succeeded below it, as little endian `u32`.
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "address" (func $address (param i32)))
+	(import "env" "gasleft" (func $gasleft (result i64)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(local $gas i64)
+		(call $fetch_input (i32.const 0))
+		(call $address (i32.const 20))
+		(local.set $gas (call $gasleft))
+		(if (i32.eqz (call $ccall
+				(i64.sub (local.get $gas) (i64.add (i64.div_u (local.get $gas) (i64.const 64)) (i64.const 1000)))
+				(i32.const 0) (i32.const 64) (i32.const 20) (i32.const 20) (i32.const 96) (i32.const 4)))
+			(then (i32.store (i32.const 96) (i32.add (i32.load (i32.const 96)) (i32.const 1))))
+			(else (i32.store (i32.const 96) (i32.const 0))))
+		(call $ret (i32.const 96) (i32.const 4)))
+)
+"#;
+
+/// Returns the input
+const ECHO: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $ret (i32.const 0) (call $input_length)))
+)
+"#;
+
+/// Calls the contract at the address given in the first 20 bytes of the input with the rest
+/// of the input as a 4 bytes result buffer, then returns `EXTRA` bytes more than
+/// the return data buffer holds.
+const RETURN_DATA: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "env" "return_data_size" (func $return_data_size (result i32)))
+	(import "env" "return_data_copy" (func $return_data_copy (param i32 i32 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(local $len i32)
+		(call $fetch_input (i32.const 0))
+		(drop (call $ccall (i64.const 100000) (i32.const 0) (i32.const 0x1000)
+			(i32.const 20) (i32.sub (call $input_length) (i32.const 20)) (i32.const 0x2000) (i32.const 4)))
+		(local.set $len (i32.add (call $return_data_size) (i32.const EXTRA)))
+		(call $return_data_copy (i32.const 0x3000) (i32.const 0) (local.get $len))
+		(call $ret (i32.const 0x3000) (local.get $len)))
+)
+"#;
+
+/// Calls the contract at the address given in the first 20 bytes of the input, sending it
+/// the value given in the next 32 bytes. Returns the result of `ccall`, as little endian `i32`.
+const SEND: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(i32.store (i32.const 64)
+			(call $ccall (i64.const 100000) (i32.const 0) (i32.const 20) (i32.const 0) (i32.const 0) (i32.const 0) (i32.const 0)))
+		(call $ret (i32.const 64) (i32.const 4)))
+)
+"#;
+
+/// Reverts every call
+const REVERT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "revert" (func $revert (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $revert (i32.const 0) (i32.const 0)))
+)
+"#;
+
+fn with_max_depth(max_depth: usize) -> Schedule {
+	let mut schedule = Schedule::new_wasm();
+	schedule.wasm.as_mut().unwrap().have_gasleft = true;
+	schedule.max_depth = max_depth;
+	schedule
+}
+
+/// Calls `contract` with `callee` as the input, returns the number of the nested calls that succeeded
+fn count_calls(provider: &mut TestProvider, schedule: &Schedule, contract: Address, callee: Address) -> u32 {
+	let mut tx = call_tx(contract, callee.as_bytes().to_vec());
+	tx.gas = U256::from(100_000_000);
+	let result = execute::execute_with_schedule(provider, &tx, schedule, &mut NoopTracer).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+
+	let mut count = [0u8; 4];
+	count.copy_from_slice(&result.data);
+	u32::from_le_bytes(count)
+}
+
+#[test]
+fn stops_deep_recursion_at_max_depth() {
+	let mut provider = TestProvider::new();
+	let schedule = with_max_depth(64);
+	let contract = deploy_with_schedule(&mut provider, &schedule, CONTRACT, H256::zero());
+
+	// the call at depth 65 fails and the frames at depth 0 to 64 return
+	assert_eq!(count_calls(&mut provider, &schedule, contract, contract), 64);
+	assert_eq!(count_calls(&mut provider, &with_max_depth(3), contract, contract), 3);
+	assert_eq!(count_calls(&mut provider, &with_max_depth(0), contract, contract), 0);
+}
+
+#[test]
+fn stops_mutual_recursion_at_max_depth() {
+	let mut provider = TestProvider::new();
+	let schedule = with_max_depth(25);
+	let ping = deploy_with_schedule(&mut provider, &schedule, CONTRACT, H256::repeat_byte(1));
+	let pong = deploy_with_schedule(&mut provider, &schedule, CONTRACT, H256::repeat_byte(2));
+	assert_ne!(ping, pong);
+
+	assert_eq!(count_calls(&mut provider, &schedule, ping, pong), 25);
+	assert_eq!(count_calls(&mut provider, &schedule, pong, ping), 25);
+}
+
+#[test]
+fn copies_return_data_longer_than_result_buffer() {
+	let mut provider = TestProvider::new();
+	let echo = deploy(&mut provider, ECHO, H256::zero());
+	let caller = deploy(&mut provider, &RETURN_DATA.replace("EXTRA", "0"), H256::zero());
+
+	let message: Vec<u8> = (0..100).collect();
+	let mut input = echo.as_bytes().to_vec();
+	input.extend(&message);
+	let tx = call_tx(caller, input);
+	let result = execute::execute(&mut provider, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(result.data, message);
+
+	// no return data from an account without code
+	let mut input = Address::repeat_byte(0x22).as_bytes().to_vec();
+	input.extend(&message);
+	let tx = call_tx(caller, input);
+	let result = execute::execute(&mut provider, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert!(result.data.is_empty());
+}
+
+#[test]
+fn traps_on_return_data_out_of_bounds() {
+	let mut provider = TestProvider::new();
+	let echo = deploy(&mut provider, ECHO, H256::zero());
+	let caller = deploy(&mut provider, &RETURN_DATA.replace("EXTRA", "1"), H256::zero());
+
+	let mut input = echo.as_bytes().to_vec();
+	input.extend(&[1, 2, 3]);
+	let tx = call_tx(caller, input);
+	match execute::execute(&mut provider, &tx).unwrap().outcome {
+		ExecutionOutcome::Trap(TrapKind::Host(msg), _) => {
+			assert_eq!(msg, "Return data out of bounds: 4 bytes at 0 of 3")
+		}
+		outcome => panic!("unexpected outcome {:?}", outcome),
+	}
+}
+
+/// Sends `value` from `contract` to `callee`, returns the result of `ccall`
+fn send(provider: &mut TestProvider, contract: Address, callee: Address, value: u64) -> i32 {
+	let mut input = callee.as_bytes().to_vec();
+	input.extend_from_slice(H256::from_low_u64_be(value).as_bytes());
+	let tx = call_tx(contract, input);
+	let result = execute::execute(provider, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+
+	let mut ret = [0u8; 4];
+	ret.copy_from_slice(&result.data);
+	i32::from_le_bytes(ret)
+}
+
+fn balance(provider: &TestProvider, address: &Address) -> U256 {
+	provider.accounts.get(address).map(|acc| acc.0).unwrap_or_default()
+}
+
+#[test]
+fn transfers_value_to_callee() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, SEND, H256::zero());
+	let reverting = deploy(&mut provider, REVERT, H256::zero());
+	provider.accounts.get_mut(&contract).unwrap().0 = U256::from(100);
+
+	// to a new account without code
+	let receiver = Address::repeat_byte(0x33);
+	assert_eq!(send(&mut provider, contract, receiver, 30), 0);
+	assert_eq!(balance(&provider, &contract), U256::from(70));
+	assert_eq!(balance(&provider, &receiver), U256::from(30));
+
+	// the value is given back when the callee fails
+	assert_eq!(send(&mut provider, contract, reverting, 30), -1);
+	assert_eq!(balance(&provider, &contract), U256::from(70));
+	assert_eq!(balance(&provider, &reverting), U256::zero());
+
+	// more than the balance
+	assert_eq!(send(&mut provider, contract, receiver, 71), -1);
+	assert_eq!(balance(&provider, &contract), U256::from(70));
+	assert_eq!(balance(&provider, &receiver), U256::from(30));
+}
@@ -0,0 +1,57 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, deploy, sender};
+use common::{RecordingProvider, TestProvider};
+use durian::execute;
+use durian::provider::{self, Provider};
+use primitive_types::{H256, U256};
+
+/// Calls the contract at the address given as the input, if any
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(if (i32.gt_u (call $input_length) (i32.const 0))
+			(then (drop (call $ccall (i64.const 100000) (i32.const 0) (i32.const 32)
+				(i32.const 0) (i32.const 0) (i32.const 64) (i32.const 0))))))
+)
+"#;
+
+#[test]
+fn fetches_code_once_per_hash() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let first = deploy(&mut provider, CONTRACT, H256::repeat_byte(1));
+	let second = deploy(&mut provider, CONTRACT, H256::repeat_byte(2));
+	assert_ne!(first, second);
+
+	// the accounts share the code stored for their code hash
+	let code_hash = provider.account(&first).unwrap().unwrap().code_hash;
+	assert_eq!(provider.account(&second).unwrap().unwrap().code_hash, code_hash);
+	assert_eq!(provider.state.codes.len(), 1);
+	assert_eq!(provider::code_hash(&provider.state.codes[&code_hash]), code_hash);
+	assert!(provider.fetched_codes.borrow().is_empty());
+
+	let tx = call_tx(first, second.as_bytes().to_vec());
+	let result = execute::execute(&mut provider, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(*provider.fetched_codes.borrow(), vec![code_hash]);
+}
+
+#[test]
+fn reads_accounts_without_code() {
+	let mut provider = TestProvider::new();
+	provider.add_account(sender(), U256::from(100));
+
+	let account = provider.account(&sender()).unwrap().unwrap();
+	assert_eq!(account.code_hash, provider::EMPTY_CODE_HASH);
+	assert_eq!(provider::code_hash(&[]), provider::EMPTY_CODE_HASH);
+}
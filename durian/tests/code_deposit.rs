@@ -0,0 +1,96 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::sender;
+use common::TestProvider;
+use durian::execute::{self, ResultData};
+use durian::outcome::ExecutionOutcome;
+use durian::provider::{Provider, EMPTY_CODE_HASH};
+use durian::schedule::Schedule;
+use durian::tracer::NoopTracer;
+use durian::transaction::Transaction;
+use primitive_types::{H256, U256};
+
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(func (export "deploy"))
+	(func (export "call"))
+)
+"#;
+
+fn create(provider: &mut TestProvider, schedule: &Schedule, gas: U256) -> ResultData {
+	let code = wat::parse_str(CONTRACT).unwrap();
+	let tx = Transaction::make_create(sender(), U256::zero(), gas, U256::zero(), code, vec![], H256::zero());
+	execute::execute_with_schedule(provider, &tx, schedule, &mut NoopTracer).unwrap()
+}
+
+/// Size of the stored code and the gas used to create the contract without the code deposit
+fn free_create() -> (usize, U256) {
+	let mut schedule = Schedule::new_wasm();
+	schedule.create_data_gas = 0;
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, &schedule, U256::from(1_000_000));
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	let code_hash = provider.account(&result.contract).unwrap().unwrap().code_hash;
+	(provider.codes[&code_hash].len(), result.gas_used)
+}
+
+#[test]
+fn charges_code_deposit() {
+	let (size, gas_used) = free_create();
+	let schedule = Schedule::new_wasm();
+	let result = create(&mut TestProvider::new(), &schedule, U256::from(1_000_000));
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	// the deposit is charged in wasm gas, converting it back rounds down
+	let deposit = (result.gas_used - gas_used).as_usize();
+	let expected = schedule.create_data_gas * size;
+	assert!(deposit <= expected && deposit + 1 >= expected, "deposit {} of {}", deposit, expected);
+}
+
+#[test]
+fn limits_code_size() {
+	let (size, _) = free_create();
+	let mut schedule = Schedule::new_wasm();
+	// only the stored code is limited, not the constructor
+	assert!(wat::parse_str(CONTRACT).unwrap().len() > size);
+	schedule.create_data_limit = size;
+	assert!(create(&mut TestProvider::new(), &schedule, U256::from(1_000_000)).outcome.is_success());
+
+	schedule.create_data_limit = size - 1;
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, &schedule, U256::from(1_000_000));
+	assert_eq!(result.outcome, ExecutionOutcome::CodeSizeLimit { size, limit: size - 1 });
+	assert_eq!(format!("{}", result.outcome), format!("Code size {} exceeds the limit {}", size, size - 1));
+	assert_eq!(result.gas_left, U256::zero());
+	assert!(!provider.exist(&result.contract).unwrap());
+}
+
+#[test]
+fn fails_without_gas_for_deposit() {
+	let (_, gas_used) = free_create();
+	let schedule = Schedule::new_wasm();
+	assert!(schedule.exceptional_failed_code_deposit);
+
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, &schedule, gas_used + U256::from(10));
+	assert_eq!(result.outcome, ExecutionOutcome::OutOfGas);
+	assert_eq!(result.gas_left, U256::zero());
+	assert!(!provider.exist(&result.contract).unwrap());
+}
+
+#[test]
+fn creates_empty_contract_without_gas_for_deposit() {
+	let (_, gas_used) = free_create();
+	let mut schedule = Schedule::new_wasm();
+	schedule.exceptional_failed_code_deposit = false;
+
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, &schedule, gas_used + U256::from(10));
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	let account = provider.account(&result.contract).unwrap().unwrap();
+	assert_eq!(account.code_hash, EMPTY_CODE_HASH);
+}
@@ -0,0 +1,148 @@
+//! Accounts, contracts and checks shared by the tests of the providers
+
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute;
+use durian::provider::{BlockContext, Provider, StateChangeSet};
+use durian::schedule::Schedule;
+use durian::tracer::NoopTracer;
+use durian::transaction::Transaction;
+use primitive_types::{H256, U256};
+use wat;
+
+/// Gas given to the transactions
+pub const GAS: u64 = 1_000_000;
+
+/// Increments the first byte of the storage at the key given as the input and returns the block number
+pub const COUNTER: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "blocknumber" (func $blocknumber (result i64)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $storage_read (i32.const 0) (i32.const 32))
+		(i32.store8 (i32.const 32) (i32.add (i32.load8_u (i32.const 32)) (i32.const 1)))
+		(call $storage_write (i32.const 0) (i32.const 32))
+		(i64.store (i32.const 64) (call $blocknumber))
+		(call $ret (i32.const 64) (i32.const 8)))
+)
+"#;
+
+/// Deletes itself, sending its balance to the address given as the input
+pub const SUICIDE: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "suicide" (func $suicide (param i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $suicide (i32.const 0)))
+)
+"#;
+
+pub fn sender() -> Address {
+	Address::repeat_byte(0x11)
+}
+
+/// Address of the `COUNTER` contract
+pub fn contract() -> Address {
+	Address::repeat_byte(0x22)
+}
+
+pub fn block(number: u64) -> BlockContext {
+	BlockContext {
+		number,
+		timestamp: 1_600_000_000 + number,
+		author: Address::zero(),
+		difficulty: U256::zero(),
+		gas_limit: U256::from(10_000_000),
+		chain_id: 1,
+		base_fee: None,
+		prev_randao: None,
+	}
+}
+
+/// Transaction of `sender` creating the contract `code`, `salt` tells apart the contracts with the same code
+pub fn create_tx(code: Vec<u8>, salt: H256) -> Transaction {
+	Transaction::make_create(sender(), U256::zero(), U256::from(GAS), U256::zero(), code, vec![], salt)
+}
+
+/// Transaction of `sender` calling `contract` with `input`
+pub fn call_tx(contract: Address, input: Vec<u8>) -> Transaction {
+	Transaction::make_call(sender(), contract, U256::zero(), U256::from(GAS), U256::zero(), input)
+}
+
+/// Deploys the contract in the text format, returns its address
+pub fn deploy(provider: &mut dyn Provider, code: &str, salt: H256) -> Address {
+	deploy_with_schedule(provider, &Schedule::new_wasm(), code, salt)
+}
+
+pub fn deploy_with_schedule(provider: &mut dyn Provider, schedule: &Schedule, code: &str, salt: H256) -> Address {
+	let tx = create_tx(wat::parse_str(code).unwrap(), salt);
+	let result = execute::execute_with_schedule(provider, &tx, schedule, &mut NoopTracer).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	result.contract
+}
+
+/// Increments the counter at the key 7 and returns the block number
+pub fn call(provider: &mut dyn Provider) -> u64 {
+	let tx = call_tx(contract(), H256::repeat_byte(7).as_bytes().to_vec());
+	let result = execute::execute(provider, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+
+	let mut number = [0u8; 8];
+	number.copy_from_slice(&result.data);
+	u64::from_le_bytes(number)
+}
+
+/// Counter at the key 7
+pub fn counter(provider: &dyn Provider) -> u8 {
+	let value = provider.storage_at(&contract(), &H256::repeat_byte(7)).unwrap();
+	value.unwrap_or_default().as_bytes()[0]
+}
+
+/// Checks that the provider applies the change sets as a whole, `sender` should exist
+pub fn applies_changes_atomically(provider: &mut dyn Provider) {
+	let created = Address::repeat_byte(0x33);
+	let missing = Address::repeat_byte(0x44);
+	let code = vec![0, 0x61, 0x73, 0x6d];
+
+	let changes = StateChangeSet {
+		created: vec![(created, code.clone())],
+		updated: vec![(missing, U256::one(), U256::one())],
+		storage: vec![(sender(), H256::repeat_byte(1), H256::repeat_byte(2))],
+		..Default::default()
+	};
+	match provider.apply_changes(changes) {
+		Err(Error::InvalidAddress { address }) => assert_eq!(address, missing),
+		result => panic!("unexpected result {:?}", result),
+	}
+	assert!(!provider.exist(&created).unwrap());
+	assert_eq!(provider.storage_at(&sender(), &H256::repeat_byte(1)).unwrap(), None);
+
+	// the created account is updated by the same change set
+	let changes = StateChangeSet {
+		created: vec![(created, code.clone())],
+		updated: vec![(created, U256::from(5), U256::one())],
+		..Default::default()
+	};
+	provider.apply_changes(changes).unwrap();
+	let account = provider.account(&created).unwrap().unwrap();
+	assert_eq!(account.balance, U256::from(5));
+	assert_eq!(provider.code_by_hash(&account.code_hash).unwrap(), Some(code));
+
+	let changes = StateChangeSet {
+		storage: vec![(sender(), H256::repeat_byte(1), H256::repeat_byte(2))],
+		deleted: vec![created],
+		..Default::default()
+	};
+	provider.apply_changes(changes).unwrap();
+	assert_eq!(provider.storage_at(&sender(), &H256::repeat_byte(1)).unwrap(), Some(H256::repeat_byte(2)));
+	assert!(!provider.exist(&created).unwrap());
+}
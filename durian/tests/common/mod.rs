@@ -0,0 +1,331 @@
+#![allow(dead_code)]
+
+pub mod fixtures;
+
+use durian::address::Address;
+use durian::error::Error;
+use durian::provider::{self, Provider, StateAccount, StateChangeSet, EMPTY_CODE_HASH};
+use primitive_types::{H256, U256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// In-memory provider for the tests
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TestProvider {
+	pub accounts: HashMap<Address, (U256, U256, H256)>,
+	pub codes: HashMap<H256, Vec<u8>>,
+	pub storage: HashMap<(Address, H256), H256>,
+	pub prepared_codes: HashMap<(H256, U256), Vec<u8>>,
+	pub timestamp: u64,
+	pub block_number: u64,
+	/// Hashes of the blocks, zero if not set
+	pub block_hashes: HashMap<u64, H256>,
+	pub author: Address,
+	pub difficulty: U256,
+	pub gas_limit: U256,
+	pub chain_id: u64,
+	pub base_fee: Option<U256>,
+	pub prev_randao: Option<H256>,
+}
+
+impl TestProvider {
+	pub fn new() -> Self {
+		TestProvider {
+			timestamp: 1_500_000_000,
+			block_number: 1,
+			gas_limit: U256::from(10_000_000),
+			chain_id: 1,
+			..Default::default()
+		}
+	}
+
+	pub fn add_account(&mut self, address: Address, balance: U256) {
+		self.accounts
+			.insert(address, (balance, U256::zero(), EMPTY_CODE_HASH));
+	}
+}
+
+impl Provider for TestProvider {
+	fn exist(&self, address: &Address) -> Result<bool, Error> {
+		Ok(self.accounts.contains_key(address))
+	}
+
+	fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+		Ok(self
+			.accounts
+			.get(address)
+			.map(|(balance, nonce, code_hash)| StateAccount {
+				balance: *balance,
+				nonce: *nonce,
+				code_hash: *code_hash,
+			}))
+	}
+
+	fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+		let acc = self
+			.accounts
+			.get_mut(address)
+			.ok_or(Error::InvalidAddress { address: *address })?;
+		acc.0 = *bal;
+		acc.1 = *nonce;
+		Ok(())
+	}
+
+	fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+		let code_hash = provider::code_hash(code);
+		self.accounts
+			.insert(*address, (U256::zero(), U256::zero(), code_hash));
+		self.codes.insert(code_hash, code.clone());
+		Ok(())
+	}
+
+	fn delete_account(&mut self, address: &Address) -> Result<(), Error> {
+		self.accounts.remove(address);
+		self.storage.retain(|(owner, _), _| owner != address);
+		Ok(())
+	}
+
+	fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+		Ok(self.storage.get(&(*address, *key)).cloned())
+	}
+
+	fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+		self.storage.insert((*address, *key), *value);
+		Ok(())
+	}
+
+	fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self.codes.get(code_hash).cloned())
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.timestamp
+	}
+
+	fn block_number(&self) -> u64 {
+		self.block_number
+	}
+
+	fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+		Ok(self.block_hashes.get(&block_no).cloned().unwrap_or_default())
+	}
+
+	fn block_author(&self) -> Result<Address, Error> {
+		Ok(self.author)
+	}
+
+	fn difficulty(&self) -> Result<U256, Error> {
+		Ok(self.difficulty)
+	}
+
+	fn gas_limit(&self) -> Result<U256, Error> {
+		Ok(self.gas_limit)
+	}
+
+	fn chain_id(&self) -> u64 {
+		self.chain_id
+	}
+
+	fn base_fee(&self) -> Result<Option<U256>, Error> {
+		Ok(self.base_fee)
+	}
+
+	fn prev_randao(&self) -> Result<Option<H256>, Error> {
+		Ok(self.prev_randao)
+	}
+
+	fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self.prepared_codes.get(&(*code_hash, *version)).cloned())
+	}
+
+	fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+		self.prepared_codes
+			.insert((*code_hash, *version), code.clone());
+		Ok(())
+	}
+}
+
+/// Provider over `TestProvider` recording the calls of its methods.
+/// Reading the state of the `failing` address fails, as applying the changes does
+/// if `failing_changes` is set.
+pub struct RecordingProvider {
+	pub state: TestProvider,
+	/// Names of the called methods, in order
+	pub calls: RefCell<Vec<&'static str>>,
+	/// Code hashes the code was fetched of
+	pub fetched_codes: RefCell<Vec<H256>>,
+	/// Change sets given to `apply_changes`
+	pub changes: Vec<StateChangeSet>,
+	pub failing: Option<Address>,
+	pub failing_changes: bool,
+}
+
+impl RecordingProvider {
+	pub fn new(state: TestProvider) -> Self {
+		RecordingProvider {
+			state,
+			calls: RefCell::new(vec![]),
+			fetched_codes: RefCell::new(vec![]),
+			changes: vec![],
+			failing: None,
+			failing_changes: false,
+		}
+	}
+
+	/// Calls of the `methods`, in order
+	pub fn calls_of(&self, methods: &[&str]) -> Vec<&'static str> {
+		self.calls
+			.borrow()
+			.iter()
+			.filter(|call| methods.contains(call))
+			.cloned()
+			.collect()
+	}
+
+	pub fn clear(&self) {
+		self.calls.borrow_mut().clear();
+		self.fetched_codes.borrow_mut().clear();
+	}
+
+	fn record(&self, method: &'static str) {
+		self.calls.borrow_mut().push(method);
+	}
+
+	fn check(&self, address: &Address) -> Result<(), Error> {
+		if self.failing == Some(*address) {
+			return Err(Error::Other {
+				msg: "connection lost".to_string(),
+			});
+		}
+		Ok(())
+	}
+}
+
+impl Provider for RecordingProvider {
+	fn exist(&self, address: &Address) -> Result<bool, Error> {
+		self.record("exist");
+		self.check(address)?;
+		self.state.exist(address)
+	}
+
+	fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+		self.record("account");
+		self.check(address)?;
+		self.state.account(address)
+	}
+
+	fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+		self.record("update_account");
+		self.state.update_account(address, bal, nonce)
+	}
+
+	fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
+		self.record("create_contract");
+		self.state.create_contract(address, code)
+	}
+
+	fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+		self.record("storage_at");
+		self.check(address)?;
+		self.state.storage_at(address, key)
+	}
+
+	fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+		self.record("set_storage");
+		self.state.set_storage(address, key, value)
+	}
+
+	fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+		self.record("code_by_hash");
+		self.fetched_codes.borrow_mut().push(*code_hash);
+		self.state.code_by_hash(code_hash)
+	}
+
+	fn timestamp(&self) -> u64 {
+		self.record("timestamp");
+		self.state.timestamp()
+	}
+
+	fn block_number(&self) -> u64 {
+		self.record("block_number");
+		self.state.block_number()
+	}
+
+	fn block_hash(&self, block_no: u64) -> Result<H256, Error> {
+		self.record("block_hash");
+		self.state.block_hash(block_no)
+	}
+
+	fn block_author(&self) -> Result<Address, Error> {
+		self.record("block_author");
+		self.state.block_author()
+	}
+
+	fn difficulty(&self) -> Result<U256, Error> {
+		self.record("difficulty");
+		self.state.difficulty()
+	}
+
+	fn gas_limit(&self) -> Result<U256, Error> {
+		self.record("gas_limit");
+		self.state.gas_limit()
+	}
+
+	fn chain_id(&self) -> u64 {
+		self.record("chain_id");
+		self.state.chain_id()
+	}
+
+	fn base_fee(&self) -> Result<Option<U256>, Error> {
+		self.record("base_fee");
+		self.state.base_fee()
+	}
+
+	fn prev_randao(&self) -> Result<Option<H256>, Error> {
+		self.record("prev_randao");
+		self.state.prev_randao()
+	}
+
+	fn accounts(&self, addresses: &[Address]) -> Result<Vec<Option<StateAccount>>, Error> {
+		self.record("accounts");
+		for address in addresses {
+			self.check(address)?;
+		}
+		self.state.accounts(addresses)
+	}
+
+	fn storages(&self, keys: &[(Address, H256)]) -> Result<Vec<Option<H256>>, Error> {
+		self.record("storages");
+		for (address, _) in keys {
+			self.check(address)?;
+		}
+		self.state.storages(keys)
+	}
+
+	fn delete_account(&mut self, address: &Address) -> Result<(), Error> {
+		self.record("delete_account");
+		self.check(address)?;
+		self.state.delete_account(address)
+	}
+
+	fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+		self.record("apply_changes");
+		if self.failing_changes {
+			return Err(Error::Other {
+				msg: "storage is full".to_string(),
+			});
+		}
+		self.changes.push(changes.clone());
+		self.state.apply_changes(changes)
+	}
+
+	fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+		self.record("prepared_code");
+		self.state.prepared_code(code_hash, version)
+	}
+
+	fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+		self.record("set_prepared_code");
+		self.state.set_prepared_code(code_hash, version, code)
+	}
+}
@@ -0,0 +1,168 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, deploy};
+use common::TestProvider;
+use durian::debugger::{Breakpoint, DebugState, Debugger, Location, Resume, Value};
+use durian::execute;
+use durian::outcome::{ExecutionOutcome, TrapKind, TrapLocation};
+use primitive_types::H256;
+
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(func $square (param $x i32) (result i32)
+		(local $y i64)
+		(local.set $y (i64.const 7))
+		(i32.mul (local.get $x) (local.get $x)))
+	(func $divide (param $x i32) (result i32)
+		(i32.div_u (i32.const 100) (local.get $x)))
+	(func (export "deploy"))
+	(func $call (export "call")
+		(i32.store (i32.const 8) (call $square (i32.const 3)))
+		(drop (call $divide (call $input_length))))
+)
+"#;
+
+/// Records where the execution stopped
+struct TestDebugger {
+	breakpoints: Vec<Breakpoint>,
+	resume: Resume,
+	stops: Vec<(Location, Vec<Value>, Vec<Location>)>,
+	memory: Vec<u8>,
+	names: Vec<String>,
+	instructions: Vec<String>,
+	finished: Option<(ExecutionOutcome, Option<Location>, Vec<Location>)>,
+}
+
+impl TestDebugger {
+	fn new(breakpoints: Vec<Breakpoint>, resume: Resume) -> Self {
+		TestDebugger {
+			breakpoints,
+			resume,
+			stops: vec![],
+			memory: vec![],
+			names: vec![],
+			instructions: vec![],
+			finished: None,
+		}
+	}
+}
+
+impl Debugger for TestDebugger {
+	fn breakpoints(&self) -> &[Breakpoint] {
+		&self.breakpoints
+	}
+
+	fn stop(&mut self, state: &DebugState) -> Resume {
+		self.stops.push((
+			*state.location,
+			state.locals.to_vec(),
+			state.call_stack.to_vec(),
+		));
+		self.memory = state.memory[..16].to_vec();
+		self.names.push(
+			state
+				.function_name(state.location.function)
+				.unwrap_or_default()
+				.to_string(),
+		);
+		self.instructions
+			.push(state.instruction(state.location).unwrap_or_default().to_string());
+		self.resume
+	}
+
+	fn finish(&mut self, outcome: &ExecutionOutcome, state: Option<&DebugState>) {
+		self.finished = Some((
+			outcome.clone(),
+			state.map(|state| *state.location),
+			state.map(|state| state.call_stack.to_vec()).unwrap_or_default(),
+		));
+	}
+}
+
+fn location(function: u32, offset: u32) -> Location {
+	Location { function, offset }
+}
+
+#[test]
+fn stops_at_breakpoints() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+	let tx = call_tx(contract, vec![1]);
+
+	let mut debugger = TestDebugger::new(
+		vec![Breakpoint::Function(1), Breakpoint::Instruction(location(4, 4))],
+		Resume::Continue,
+	);
+	let debugged = execute::execute_with_debugger(&mut provider.clone(), &tx, &mut debugger).unwrap();
+	let result = execute::execute(&mut provider, &tx).unwrap();
+
+	assert_eq!(debugged, result);
+	assert_eq!(
+		debugger.stops,
+		vec![
+			(
+				location(1, 0),
+				vec![Value::I32(3), Value::I64(0)],
+				vec![location(4, 2), location(1, 0)],
+			),
+			(location(4, 4), vec![], vec![location(4, 4)]),
+		]
+	);
+	assert_eq!(debugger.names, vec!["square", "call"]);
+	assert_eq!(debugger.instructions, vec!["i64.const 7", "call 0"]);
+	assert_eq!(debugger.memory[8..12], [9, 0, 0, 0]);
+}
+
+#[test]
+fn steps() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	let mut debugger = TestDebugger::new(vec![Breakpoint::Function(1)], Resume::Step);
+	execute::execute_with_debugger(&mut provider, &call_tx(contract, vec![1]), &mut debugger).unwrap();
+
+	let locations: Vec<Location> = debugger.stops.iter().map(|stop| stop.0).collect();
+	assert_eq!(&locations[..4], &[location(1, 0), location(1, 1), location(1, 2), location(1, 3)]);
+	assert_eq!(debugger.stops[2].1, vec![Value::I32(3), Value::I64(7)]);
+	// back in the caller, after the call returned
+	assert_eq!(locations[6], location(4, 3));
+	assert_eq!(debugger.stops[6].2, vec![location(4, 3)]);
+	assert_eq!(
+		debugger.finished,
+		Some((ExecutionOutcome::Success, Some(location(4, 7)), vec![location(4, 7)]))
+	);
+}
+
+#[test]
+fn reports_trap_location() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	let mut debugger = TestDebugger::new(vec![], Resume::Continue);
+	let result = execute::execute_with_debugger(&mut provider, &call_tx(contract, vec![]), &mut debugger).unwrap();
+
+	let trap = ExecutionOutcome::Trap(
+		TrapKind::DivisionByZero,
+		Some(TrapLocation {
+			function: 2,
+			offset: Some(2),
+			function_name: Some("divide".to_string()),
+		}),
+	);
+	assert_eq!(result.outcome, trap);
+	assert!(debugger.stops.is_empty());
+	assert_eq!(
+		debugger.finished,
+		Some((
+			trap,
+			Some(location(2, 2)),
+			vec![location(4, 5), location(2, 2)],
+		))
+	);
+}
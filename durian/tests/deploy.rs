@@ -0,0 +1,153 @@
+extern crate durian;
+extern crate parity_wasm;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, create_tx};
+use common::TestProvider;
+use durian::address::Address;
+use durian::execute::{self, ResultData};
+use durian::outcome::ExecutionOutcome;
+use durian::provider::Provider;
+use primitive_types::H256;
+
+/// Counts the calls of `deploy` at the storage key 0, `call` returns the count
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy")
+		(call $storage_read (i32.const 0) (i32.const 32))
+		(i32.store8 (i32.const 63) (i32.add (i32.load8_u (i32.const 63)) (i32.const 1)))
+		(call $storage_write (i32.const 0) (i32.const 32)))
+	(func (export "call")
+		(call $storage_read (i32.const 0) (i32.const 32))
+		(call $ret (i32.const 63) (i32.const 1)))
+)
+"#;
+
+/// Runtime code of the pwasm constructors, returns 7
+const RUNTIME: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "call")
+		(i32.store8 (i32.const 0) (i32.const 7))
+		(call $ret (i32.const 0) (i32.const 1)))
+)
+"#;
+
+/// Constructor following the pwasm convention: `call` returns the runtime code
+fn pwasm_constructor(runtime: &[u8]) -> String {
+	let data: String = runtime.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+	format!(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "ret" (func $ret (param i32 i32)))
+		(data (i32.const 0) "{}")
+		(func (export "call")
+			(call $ret (i32.const 0) (i32.const {}))))
+	"#,
+		data,
+		runtime.len()
+	)
+}
+
+fn create(provider: &mut TestProvider, code: &str) -> ResultData {
+	let tx = create_tx(wat::parse_str(code).unwrap(), H256::zero());
+	execute::execute(provider, &tx).unwrap()
+}
+
+fn stored_code(provider: &TestProvider, contract: &Address) -> Vec<u8> {
+	let code_hash = provider.account(contract).unwrap().unwrap().code_hash;
+	provider.codes[&code_hash].clone()
+}
+
+fn exports(code: &[u8]) -> Vec<String> {
+	let module = parity_wasm::deserialize_buffer::<parity_wasm::elements::Module>(code).unwrap();
+	module
+		.export_section()
+		.map(|es| es.entries().iter().map(|export| export.field().to_string()).collect())
+		.unwrap_or_default()
+}
+
+#[test]
+fn invokes_deploy_once() {
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, CONTRACT);
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	let contract = result.contract;
+
+	// the module is stored without the constructor
+	assert_eq!(exports(&stored_code(&provider, &contract)), vec!["call"]);
+	for _ in 0..2 {
+		let result = execute::execute(&mut provider, &call_tx(contract, vec![])).unwrap();
+		assert!(result.outcome.is_success(), "{}", result.outcome);
+		assert_eq!(result.data, vec![1]);
+	}
+}
+
+#[test]
+fn rejects_deploy_without_call() {
+	// the constructor is stored as the contract code, which couldn't be called
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, r#"(module (import "env" "memory" (memory 1 16)) (func (export "deploy")))"#);
+	assert_eq!(
+		result.outcome,
+		ExecutionOutcome::InvalidCode("Module doesn't export `call`".to_string())
+	);
+	assert!(!provider.exist(&result.contract).unwrap());
+}
+
+#[test]
+fn deploys_pwasm_constructor() {
+	let mut provider = TestProvider::new();
+	let runtime = wat::parse_str(RUNTIME).unwrap();
+	let result = create(&mut provider, &pwasm_constructor(&runtime));
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(stored_code(&provider, &result.contract), runtime);
+
+	let result = execute::execute(&mut provider, &call_tx(result.contract, vec![])).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(result.data, vec![7]);
+}
+
+#[test]
+fn rejects_empty_pwasm_code() {
+	// a pwasm constructor returning no code used to create a contract without code
+	let mut provider = TestProvider::new();
+	let result = create(&mut provider, &pwasm_constructor(&[]));
+	match result.outcome {
+		ExecutionOutcome::InvalidCode(_) => {}
+		outcome => panic!("unexpected outcome {}", outcome),
+	}
+	assert!(!provider.exist(&result.contract).unwrap());
+}
+
+#[test]
+fn rejects_runtime_code_exporting_deploy() {
+	let mut provider = TestProvider::new();
+	let code = wat::parse_str(CONTRACT).unwrap();
+	let result = create(&mut provider, &pwasm_constructor(&code));
+	assert_eq!(
+		result.outcome,
+		ExecutionOutcome::InvalidCode("Invalid code: contract code should not export `deploy`".to_string())
+	);
+	assert!(!provider.exist(&result.contract).unwrap());
+}
+
+#[test]
+fn rejects_runtime_code_without_call() {
+	let mut provider = TestProvider::new();
+	let code = wat::parse_str(r#"(module (import "env" "memory" (memory 1 16)) (func (export "main")))"#).unwrap();
+	let result = create(&mut provider, &pwasm_constructor(&code));
+	assert_eq!(
+		result.outcome,
+		ExecutionOutcome::InvalidCode("Module doesn't export `call`".to_string())
+	);
+}
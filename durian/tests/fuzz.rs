@@ -0,0 +1,236 @@
+//! Feeds arbitrary modules and arguments into `execute`.
+//! Whatever the input is, the execution should not panic and a failed execution
+//! should not change the state.
+
+extern crate durian;
+extern crate primitive_types;
+extern crate proptest;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{sender, GAS};
+use common::TestProvider;
+use durian::address::Address;
+use durian::execute::{self, ResultData};
+use durian::error::Error;
+use durian::transaction::Transaction;
+use primitive_types::{H256, U256};
+use proptest::prelude::*;
+
+/// Contract that uses its input as pointers and lengths for the host functions
+const INPUT_CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "elog" (func $elog (param i32 i32 i32 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(if (i32.lt_u (call $input_length) (i32.const 24))
+			(then (return)))
+		(call $fetch_input (i32.const 0))
+		(call $storage_write (i32.load (i32.const 0)) (i32.load (i32.const 4)))
+		(call $storage_read (i32.load (i32.const 4)) (i32.load (i32.const 8)))
+		(call $elog (i32.load (i32.const 8)) (i32.and (i32.load (i32.const 12)) (i32.const 3))
+			(i32.load (i32.const 16)) (i32.load (i32.const 20)))
+		(call $ret (i32.load (i32.const 16)) (i32.load (i32.const 20)))
+	)
+)
+"#;
+
+/// Host functions provided by the runtime along with their signatures
+const HOST_FUNCTIONS: &[(&str, &str, &str)] = &[
+	("storage_read", "i32 i32", ""),
+	("storage_write", "i32 i32", ""),
+	("ret", "i32 i32", ""),
+	("revert", "i32 i32", ""),
+	("gas", "i32", ""),
+	("input_length", "", "i32"),
+	("fetch_input", "i32", ""),
+	("panic", "i32 i32", ""),
+	("debug", "i32 i32", ""),
+	("ccall", "i64 i32 i32 i32 i32 i32 i32", "i32"),
+	("dcall", "i64 i32 i32 i32 i32 i32", "i32"),
+	("scall", "i64 i32 i32 i32 i32 i32", "i32"),
+	("value", "i32", ""),
+	("create", "i32 i32 i32 i32", "i32"),
+	("suicide", "i32", ""),
+	("block_hash", "i64 i32", ""),
+	("blocknumber", "", "i64"),
+	("coinbase", "i32", ""),
+	("difficulty", "i32", ""),
+	("gaslimit", "i32", ""),
+	("timestamp", "", "i64"),
+	("address", "i32", ""),
+	("sender", "i32", ""),
+	("origin", "i32", ""),
+	("elog", "i32 i32 i32 i32", ""),
+	("return_data_size", "", "i32"),
+	("return_data_copy", "i32 i32 i32", ""),
+	("chain_id", "", "i64"),
+	("basefee", "i32", ""),
+	("random_seed", "i32", ""),
+];
+
+fn create(code: Vec<u8>, args: Vec<u8>, gas: U256) -> Transaction {
+	Transaction::make_create(sender(), U256::zero(), gas, U256::zero(), code, args, H256::zero())
+}
+
+/// Executes the transaction and checks the invariants that hold for any input
+fn check(provider: &mut TestProvider, transaction: &Transaction) -> Result<ResultData, Error> {
+	let before = provider.clone();
+	let result = execute::execute(provider, transaction);
+
+	match result {
+		Ok(ref result_data) => {
+			assert!(result_data.gas_left <= transaction.gas);
+			assert_eq!(result_data.gas_used + result_data.gas_left, transaction.gas);
+			if !result_data.outcome.is_success() {
+				assert_eq!(*provider, before, "failed execution changed the state");
+			}
+		}
+		Err(_) => assert_eq!(*provider, before, "failed execution changed the state"),
+	}
+
+	result
+}
+
+fn gas() -> impl Strategy<Value = U256> {
+	prop_oneof![
+		Just(U256::from(GAS)),
+		(0..GAS).prop_map(U256::from),
+		any::<u64>().prop_map(U256::from),
+		any::<[u8; 32]>().prop_map(|bytes| U256::from_big_endian(&bytes)),
+	]
+}
+
+/// Pointers and lengths, mostly around the contract memory
+fn arg() -> impl Strategy<Value = i64> {
+	prop_oneof![
+		0..0x10000i64,
+		0xff00..0x10100i64,
+		any::<i32>().prop_map(|x| x as i64),
+		any::<i64>(),
+	]
+}
+
+fn host_call_module(name: &str, params: &str, result: &str, args: &[i64]) -> String {
+	let result_decl = if result.is_empty() {
+		String::new()
+	} else {
+		format!("(result {})", result)
+	};
+	let operands: Vec<String> = params
+		.split_whitespace()
+		.zip(args)
+		.map(|(ty, arg)| match ty {
+			"i32" => format!("(i32.const {})", *arg as i32),
+			_ => format!("(i64.const {})", arg),
+		})
+		.collect();
+	let call = format!("(call $f {})", operands.join(" "));
+	let body = if result.is_empty() {
+		call
+	} else {
+		format!("(drop {})", call)
+	};
+
+	format!(
+		r#"(module
+			(import "env" "memory" (memory 1 16))
+			(import "env" "{}" (func $f (param {}) {}))
+			(func (export "deploy") {})
+			(func (export "call"))
+		)"#,
+		name, params, result_decl, body
+	)
+}
+
+proptest! {
+	#[test]
+	fn arbitrary_code(
+		code in prop::collection::vec(any::<u8>(), 0..512),
+		args in prop::collection::vec(any::<u8>(), 0..64),
+		gas in gas(),
+	) {
+		let mut provider = TestProvider::new();
+		let _ = check(&mut provider, &create(code.clone(), args, gas));
+
+		let mut code_params = code;
+		code_params.extend_from_slice(&[0u8; 32]);
+		let tx = Transaction::make_create_embedded_code(
+			sender(), U256::zero(), gas, U256::zero(), code_params, H256::zero());
+		let _ = check(&mut provider, &tx);
+	}
+
+	#[test]
+	fn arbitrary_sections(
+		sections in prop::collection::vec(any::<u8>(), 0..512),
+		gas in gas(),
+	) {
+		let mut code = b"\0asm\x01\0\0\0".to_vec();
+		code.extend(sections);
+
+		let mut provider = TestProvider::new();
+		let _ = check(&mut provider, &create(code, vec![], gas));
+	}
+
+	#[test]
+	fn mutated_module(
+		mutations in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+		gas in gas(),
+	) {
+		let mut code = wat::parse_str(INPUT_CONTRACT).unwrap();
+		for (index, byte) in mutations {
+			let idx = index.index(code.len());
+			code[idx] = byte;
+		}
+
+		let mut provider = TestProvider::new();
+		let _ = check(&mut provider, &create(code, vec![], gas));
+	}
+
+	#[test]
+	fn host_call_with_arbitrary_arguments(
+		func in prop::sample::select(HOST_FUNCTIONS),
+		args in prop::collection::vec(arg(), 7),
+		gas in gas(),
+	) {
+		let (name, params, result) = func;
+		let code = wat::parse_str(host_call_module(name, params, result, &args)).unwrap();
+
+		let mut provider = TestProvider::new();
+		let _ = check(&mut provider, &create(code, vec![], gas));
+	}
+
+	#[test]
+	fn call_with_arbitrary_input(
+		args in prop::collection::vec(any::<u8>(), 0..64),
+		pointers in prop::collection::vec(arg(), 6),
+		gas in gas(),
+	) {
+		let mut provider = TestProvider::new();
+		let code = wat::parse_str(INPUT_CONTRACT).unwrap();
+		let deployed = check(&mut provider, &create(code, vec![], U256::from(10 * GAS))).unwrap();
+		prop_assert!(deployed.outcome.is_success());
+
+		let mut input: Vec<u8> = pointers
+			.iter()
+			.flat_map(|ptr| (*ptr as u32).to_le_bytes().to_vec())
+			.collect();
+		input.extend(args);
+		let tx = Transaction::make_call(
+			sender(), deployed.contract, U256::zero(), gas, U256::zero(), input);
+		let _ = check(&mut provider, &tx);
+
+		// calling an account without code
+		let tx = Transaction::make_call(
+			sender(), Address::repeat_byte(0x22), U256::zero(), gas, U256::zero(), vec![]);
+		provider.add_account(Address::repeat_byte(0x22), U256::zero());
+		let _ = check(&mut provider, &tx);
+	}
+}
@@ -0,0 +1,98 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::create_tx;
+use common::TestProvider;
+use durian::execute::{self, ResultData};
+use durian::limits::Limit;
+use durian::outcome::ExecutionOutcome;
+use durian::schedule::Schedule;
+use durian::tracer::NoopTracer;
+use primitive_types::{H256, U256};
+use std::time::Duration;
+
+fn create(schedule: &Schedule, code: &str, gas: U256) -> ResultData {
+	let mut provider = TestProvider::new();
+	let mut tx = create_tx(wat::parse_str(code).unwrap(), H256::zero());
+	tx.gas = gas;
+	execute::execute_with_schedule(&mut provider, &tx, schedule, &mut NoopTracer).unwrap()
+}
+
+#[test]
+fn limits_memory_pages() {
+	let code = r#"
+	(module
+		(import "env" "memory" (memory 1 8))
+		(func (export "deploy"))
+		(func (export "call")))
+	"#;
+
+	let mut schedule = Schedule::new_wasm();
+	assert!(create(&schedule, code, U256::from(1_000_000)).outcome.is_success());
+
+	schedule.limits.max_memory_pages = 4;
+	let result = create(&schedule, code, U256::from(1_000_000));
+	assert_eq!(result.outcome, ExecutionOutcome::LimitExceeded(Limit::MemoryPages));
+	assert_eq!(result.gas_left, U256::zero());
+}
+
+#[test]
+fn limits_host_calls() {
+	let code = r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "input_length" (func $input_length (result i32)))
+		(func (export "deploy")
+			(drop (call $input_length))
+			(drop (call $input_length))
+			(drop (call $input_length)))
+		(func (export "call")))
+	"#;
+
+	let mut schedule = Schedule::new_wasm();
+	schedule.limits.max_host_calls = 3;
+	assert!(create(&schedule, code, U256::from(1_000_000)).outcome.is_success());
+
+	schedule.limits.max_host_calls = 2;
+	let result = create(&schedule, code, U256::from(1_000_000));
+	assert_eq!(result.outcome, ExecutionOutcome::LimitExceeded(Limit::HostCalls));
+}
+
+#[test]
+fn limits_return_data() {
+	let code = r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "revert" (func $revert (param i32 i32)))
+		(func (export "deploy")
+			(call $revert (i32.const 0) (i32.const 100)))
+		(func (export "call")))
+	"#;
+
+	let mut schedule = Schedule::new_wasm();
+	schedule.limits.max_return_data = 100;
+	assert_eq!(create(&schedule, code, U256::from(1_000_000)).outcome, ExecutionOutcome::Revert(vec![0; 100]));
+
+	schedule.limits.max_return_data = 99;
+	let result = create(&schedule, code, U256::from(1_000_000));
+	assert_eq!(result.outcome, ExecutionOutcome::LimitExceeded(Limit::ReturnData));
+}
+
+#[test]
+fn stops_at_deadline() {
+	let code = r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(func (export "deploy")
+			(loop $forever (br $forever)))
+		(func (export "call")))
+	"#;
+
+	let mut schedule = Schedule::new_wasm();
+	schedule.limits.deadline = Some(Duration::from_millis(100));
+	let result = create(&schedule, code, U256::from(u64::MAX / 8));
+	assert_eq!(result.outcome, ExecutionOutcome::LimitExceeded(Limit::Deadline));
+}
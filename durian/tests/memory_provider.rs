@@ -0,0 +1,190 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{self, call, call_tx, contract, counter, sender, COUNTER, SUICIDE};
+use durian::address::Address;
+use durian::error::Error;
+use durian::provider::memory::{MemoryAccount, MemoryProvider};
+use durian::provider::Provider;
+use durian::state_trie;
+use durian::witness;
+use primitive_types::{H256, U256};
+use std::collections::BTreeMap;
+
+fn hex(bytes: &[u8]) -> String {
+	let digits: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+	format!("0x{}", digits.concat())
+}
+
+fn genesis() -> String {
+	let code = wat::parse_str(COUNTER).unwrap();
+	let value = format!("05{}", "00".repeat(31));
+	format!(
+		r#"{{
+			"block": {{
+				"number": 42,
+				"timestamp": 1600000000,
+				"author": "0x{author}",
+				"difficulty": "0x0",
+				"gas_limit": "0x989680",
+				"chain_id": 5
+			}},
+			"accounts": {{
+				"0x{sender}": {{ "balance": "0x3e8" }},
+				"0x{contract}": {{
+					"code": "{code}",
+					"storage": {{ "0x{key}": "0x{value}" }}
+				}}
+			}}
+		}}"#,
+		author = "aa".repeat(20),
+		sender = "11".repeat(20),
+		contract = "22".repeat(20),
+		code = hex(&code),
+		key = "07".repeat(32),
+		value = value,
+	)
+}
+
+#[test]
+fn executes_genesis() {
+	let mut provider = MemoryProvider::from_genesis_json(&genesis()).unwrap();
+	assert_eq!(provider.block().chain_id, 5);
+	assert_eq!(provider.block().author, Address::repeat_byte(0xaa));
+	assert_eq!(provider.get(&sender()).unwrap().balance, U256::from(1000));
+	assert_eq!(counter(&provider), 5);
+
+	assert_eq!(call(&mut provider), 42);
+	assert_eq!(counter(&provider), 6);
+
+	let mut block = provider.block().clone();
+	block.number = 43;
+	provider.set_block(block);
+	assert_eq!(call(&mut provider), 43);
+	assert_eq!(counter(&provider), 7);
+}
+
+#[test]
+fn rejects_invalid_genesis() {
+	for json in &[
+		"",
+		r#"{ "accounts": { "0x11": {} } }"#,
+		r#"{ "accounts": { "0x1111111111111111111111111111111111111111": { "code": "0xzz" } } }"#,
+		r#"{ "alloc": {} }"#,
+	] {
+		match MemoryProvider::from_genesis_json(json) {
+			Err(Error::InvalidGenesis { .. }) => {}
+			result => panic!("unexpected result {:?} for {}", result.map(|_| ()), json),
+		}
+	}
+}
+
+#[test]
+fn restores_snapshot() {
+	let mut provider = MemoryProvider::from_genesis_json(&genesis()).unwrap();
+	let snapshot = provider.snapshot();
+	let expected = provider.clone();
+
+	call(&mut provider);
+	call(&mut provider);
+	assert_eq!(counter(&provider), 7);
+	assert_ne!(provider, expected);
+
+	provider.restore(snapshot);
+	assert_eq!(provider, expected);
+	assert_eq!(counter(&provider), 5);
+}
+
+#[test]
+fn applies_changes_atomically() {
+	let mut provider = MemoryProvider::default();
+	provider.insert_account(sender(), MemoryAccount::default());
+	fixtures::applies_changes_atomically(&mut provider);
+}
+
+#[test]
+fn iterates_accounts_by_address() {
+	let mut provider = MemoryProvider::default();
+	for byte in &[9u8, 3, 7, 1] {
+		provider.insert_account(Address::repeat_byte(*byte), MemoryAccount::default());
+	}
+
+	let addresses: Vec<Address> = provider.iter().map(|(address, _)| *address).collect();
+	let expected: Vec<Address> = [1u8, 3, 7, 9].iter().map(|byte| Address::repeat_byte(*byte)).collect();
+	assert_eq!(addresses, expected);
+}
+
+#[test]
+fn proves_state() {
+	let mut provider = MemoryProvider::from_genesis_json(&genesis()).unwrap();
+	let genesis_root = provider.state_trie().unwrap().root();
+	call(&mut provider);
+	let root = provider.state_trie().unwrap().root();
+	assert_ne!(root, genesis_root);
+
+	let proof = provider.get_proof(&contract(), &[H256::repeat_byte(7)]).unwrap();
+	assert_eq!(proof.account.as_ref().unwrap().code_hash, provider.get(&contract()).unwrap().code_hash);
+	assert_eq!(proof.storage[0].value.as_bytes()[0], 6);
+	state_trie::verify_proof(&root, &proof).unwrap();
+	assert!(state_trie::verify_proof(&genesis_root, &proof).is_err());
+}
+
+#[test]
+fn writes_zero_without_creating_account() {
+	let mut provider = MemoryProvider::from_genesis_json(&genesis()).unwrap();
+	let root = provider.state_trie().unwrap().root();
+
+	let missing = Address::repeat_byte(0x55);
+	provider.set_storage(&missing, &H256::repeat_byte(1), &H256::zero()).unwrap();
+	assert!(!provider.exist(&missing).unwrap());
+	assert_eq!(provider.state_trie().unwrap().root(), root);
+
+	// the trie is built again once the state changed
+	provider.set_storage(&missing, &H256::repeat_byte(1), &H256::repeat_byte(1)).unwrap();
+	assert!(provider.exist(&missing).unwrap());
+	assert_ne!(provider.state_trie().unwrap().root(), root);
+	provider.set_storage(&missing, &H256::repeat_byte(1), &H256::zero()).unwrap();
+	assert!(provider.get(&missing).unwrap().storage.is_empty());
+}
+
+#[test]
+fn deletes_account_by_suicide() {
+	let mut provider = MemoryProvider::from_genesis_json(&genesis()).unwrap();
+	let refund = Address::repeat_byte(0x44);
+	let mut expected = provider.clone();
+	expected.insert_account(
+		refund,
+		MemoryAccount {
+			balance: U256::from(100),
+			..Default::default()
+		},
+	);
+
+	let doomed = Address::repeat_byte(0x33);
+	let code_hash = provider.insert_code(wat::parse_str(SUICIDE).unwrap());
+	provider.insert_account(
+		doomed,
+		MemoryAccount {
+			balance: U256::from(100),
+			code_hash,
+			storage: vec![(H256::repeat_byte(1), H256::repeat_byte(1))].into_iter().collect(),
+			..Default::default()
+		},
+	);
+
+	let tx = call_tx(doomed, refund.as_bytes().to_vec());
+	let state = provider.state_trie().unwrap();
+	let (result, witness) = witness::record(&mut provider, &state, &tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert!(provider.get(&doomed).is_none());
+	assert_eq!(provider.get(&refund).unwrap().balance, U256::from(100));
+
+	// the account is removed from the state trie too
+	let root = expected.state_trie().unwrap().root();
+	assert_eq!(provider.state_trie().unwrap().root(), root);
+	let (_, post_root) = witness::execute(&witness, &state.root(), provider.block(), &BTreeMap::new(), &tx).unwrap();
+	assert_eq!(post_root, root);
+}
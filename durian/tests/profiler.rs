@@ -0,0 +1,81 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, create_tx};
+use common::TestProvider;
+use durian::execute;
+use durian::profiler::GasProfile;
+use primitive_types::{H256, U256};
+
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(func $store (param $key i32)
+		(call $storage_write (local.get $key) (i32.const 64)))
+	(func $fib (param $n i32) (result i32)
+		(if (result i32) (i32.lt_u (local.get $n) (i32.const 2))
+			(then (local.get $n))
+			(else (i32.add
+				(call $fib (i32.sub (local.get $n) (i32.const 1)))
+				(call $fib (i32.sub (local.get $n) (i32.const 2)))))))
+	(func (export "deploy")
+		(call $store (i32.const 0)))
+	(func $call (export "call")
+		(drop (call $fib (i32.const 10)))
+		(call $store (i32.const 32)))
+)
+"#;
+
+fn stack(frames: &[&str]) -> Vec<String> {
+	frames.iter().map(|frame| frame.to_string()).collect()
+}
+
+fn assert_total(profile: &GasProfile, gas_used: U256) {
+	// gas is rounded down per stack
+	assert!(profile.total_gas() <= gas_used);
+	assert!(gas_used - profile.total_gas() <= U256::from(profile.stacks.len()));
+}
+
+#[test]
+fn attributes_gas_to_functions() {
+	let mut provider = TestProvider::new();
+	let tx = create_tx(wat::parse_str(CONTRACT).unwrap(), H256::zero());
+
+	let mut profile = GasProfile::new();
+	let created = execute::execute_with_profiler(&mut provider, &tx, &mut profile).unwrap();
+	assert!(created.outcome.is_success());
+	assert_total(&profile, created.gas_used);
+	assert!(profile.stacks.contains_key(&stack(&["func[3]", "store", "env.storage_write"])));
+	assert!(profile.stacks.contains_key(&stack(&["[initial memory]"])));
+	assert!(profile.stacks.contains_key(&stack(&["[code deposit]"])));
+
+	let tx = call_tx(created.contract, vec![]);
+	let mut profile = GasProfile::new();
+	let called = execute::execute_with_profiler(&mut provider.clone(), &tx, &mut profile).unwrap();
+	let result = execute::execute(&mut provider, &tx).unwrap();
+	assert_eq!(called, result);
+	assert_total(&profile, called.gas_used);
+
+	let storage_write = profile.stacks[&stack(&["call", "store", "env.storage_write"])];
+	assert!(storage_write > U256::zero());
+	assert!(profile.stacks.contains_key(&stack(&["call", "fib", "fib", "fib"])));
+	assert!(!profile.stacks.contains_key(&stack(&["[code deposit]"])));
+
+	let functions = profile.functions();
+	assert_eq!(functions[0].name, "env.storage_write");
+	let fib = functions.iter().find(|function| function.name == "fib").unwrap();
+	let call = functions.iter().find(|function| function.name == "call").unwrap();
+	assert!(fib.self_gas > U256::zero());
+	assert_eq!(fib.self_gas, fib.total_gas);
+	assert!(call.total_gas > fib.total_gas + storage_write);
+
+	let mut folded = Vec::new();
+	profile.write_folded(&mut folded).unwrap();
+	let folded = String::from_utf8(folded).unwrap();
+	assert!(folded.contains(&format!("call;store;env.storage_write {}\n", storage_write)));
+	assert_eq!(folded.lines().count(), profile.stacks.len());
+}
@@ -0,0 +1,84 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{self, call_tx};
+use common::{RecordingProvider, TestProvider};
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute;
+use durian::provider::Provider;
+use primitive_types::H256;
+
+/// Increments the first byte of the storage at the key given as the first 32 bytes of the input.
+/// If the input has an address after the key, calls it with the key.
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "ccall" (func $ccall (param i64 i32 i32 i32 i32 i32 i32) (result i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $storage_read (i32.const 0) (i32.const 64))
+		(i32.store8 (i32.const 64) (i32.add (i32.load8_u (i32.const 64)) (i32.const 1)))
+		(call $storage_write (i32.const 0) (i32.const 64))
+		(if (i32.gt_u (call $input_length) (i32.const 32))
+			(then (drop (call $ccall (i64.const 100000) (i32.const 32) (i32.const 96)
+				(i32.const 0) (i32.const 32) (i32.const 128) (i32.const 0))))))
+)
+"#;
+
+
+fn assert_provider_failure(result: Result<execute::ResultData, Error>) {
+	match result {
+		Err(Error::ProviderFailure { msg }) => assert_eq!(msg, "Error: connection lost"),
+		result => panic!("unexpected result {:?}", result),
+	}
+}
+
+#[test]
+fn aborts_on_provider_failure() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = fixtures::deploy(&mut provider, CONTRACT, H256::zero());
+	let state = provider.state.clone();
+
+	provider.failing = Some(contract);
+	let key = H256::repeat_byte(7).as_bytes().to_vec();
+	assert_provider_failure(execute::execute(&mut provider, &call_tx(contract, key)));
+	assert_eq!(provider.state, state);
+}
+
+#[test]
+fn aborts_on_provider_failure_in_nested_call() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let caller = fixtures::deploy(&mut provider, CONTRACT, H256::repeat_byte(1));
+	let callee = fixtures::deploy(&mut provider, CONTRACT, H256::repeat_byte(2));
+	let state = provider.state.clone();
+
+	// the failure isn't a failed call the caller could handle, the whole execution is aborted
+	provider.failing = Some(callee);
+	let mut input = H256::repeat_byte(7).as_bytes().to_vec();
+	input.extend(callee.as_bytes());
+	assert_provider_failure(execute::execute(&mut provider, &call_tx(caller, input)));
+	assert_eq!(provider.state, state);
+}
+
+#[test]
+fn reads_missing_state_as_empty() {
+	let mut provider = RecordingProvider::new(TestProvider::new());
+	let contract = fixtures::deploy(&mut provider, CONTRACT, H256::zero());
+
+	// calls an account that doesn't exist
+	let mut input = H256::repeat_byte(7).as_bytes().to_vec();
+	input.extend(Address::repeat_byte(0x22).as_bytes());
+	let result = execute::execute(&mut provider, &call_tx(contract, input)).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	let value = provider.state.storage_at(&contract, &H256::repeat_byte(7)).unwrap();
+	assert_eq!(value.unwrap().as_bytes()[0], 1);
+}
@@ -0,0 +1,125 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, create_tx, deploy, sender};
+use common::TestProvider;
+use durian::execute;
+use durian::outcome::ExecutionOutcome;
+use durian::tracer::{CallTracer, Frame, HostEvent, Tracer};
+use primitive_types::{H256, U256};
+
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "storage_read" (func $storage_read (param i32 i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "elog" (func $elog (param i32 i32 i32 i32)))
+	(import "env" "revert" (func $revert (param i32 i32)))
+	(data (i32.const 63) "\2a")
+	(func (export "deploy")
+		(call $storage_write (i32.const 0) (i32.const 32)))
+	(func (export "call")
+		(call $storage_read (i32.const 0) (i32.const 64))
+		(call $elog (i32.const 0) (i32.const 1) (i32.const 95) (i32.const 1))
+		(call $revert (i32.const 95) (i32.const 1)))
+)
+"#;
+
+#[derive(Default)]
+struct EventTracer {
+	events: Vec<String>,
+	charged: u64,
+}
+
+impl Tracer for EventTracer {
+	fn enter_frame(&mut self, frame: &Frame) {
+		self.events
+			.push(format!("enter {:?} {}", frame.kind, frame.depth));
+	}
+
+	fn exit_frame(&mut self, outcome: &ExecutionOutcome, _gas_used: &U256, _output: &[u8]) {
+		self.events.push(format!("exit {}", outcome));
+	}
+
+	fn host_function(&mut self, name: &'static str) {
+		if name != "gas" {
+			self.events.push(name.to_string());
+		}
+	}
+
+	fn host_event(&mut self, event: &HostEvent) {
+		match *event {
+			HostEvent::StorageRead { value, .. } => {
+				self.events.push(format!("read {:x}", value.to_low_u64_be()))
+			}
+			HostEvent::StorageWrite { value, .. } => {
+				self.events.push(format!("write {:x}", value.to_low_u64_be()))
+			}
+			HostEvent::Log(log) => self.events.push(format!("log {:?}", log.data)),
+			HostEvent::Balance { .. } => self.events.push("balance".to_string()),
+		}
+	}
+
+	fn gas_charge(&mut self, amount: u64, _gas_left: u64) {
+		self.charged += amount;
+	}
+}
+
+#[test]
+fn host_events() {
+	let mut provider = TestProvider::new();
+	let tx = create_tx(wat::parse_str(CONTRACT).unwrap(), H256::zero());
+	let mut tracer = EventTracer::default();
+	let created = execute::execute_with_tracer(&mut provider, &tx, &mut tracer).unwrap();
+
+	assert_eq!(
+		tracer.events,
+		vec![
+			"enter Create 0",
+			"storage_write",
+			"write 2a",
+			"exit Success",
+		]
+	);
+	assert!(tracer.charged > 0);
+
+	let tx = call_tx(created.contract, vec![]);
+	let mut tracer = EventTracer::default();
+	let called = execute::execute_with_tracer(&mut provider, &tx, &mut tracer).unwrap();
+
+	assert_eq!(called.outcome, ExecutionOutcome::Revert(vec![0x2a]));
+	assert_eq!(
+		tracer.events,
+		vec![
+			"enter Call 0",
+			"storage_read",
+			"read 2a",
+			"elog",
+			"log [42]",
+			"revert",
+			"exit Reverted",
+		]
+	);
+}
+
+#[test]
+fn call_tree_json() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	let tx = call_tx(contract, vec![0xab]);
+	let mut tracer = CallTracer::new();
+	execute::execute_with_tracer(&mut provider, &tx, &mut tracer).unwrap();
+
+	assert_eq!(
+		tracer.to_json(),
+		format!(
+			r#"[{{"action":{{"callType":"call","from":"{:#x}","gas":"0xf4240","input":"0xab","to":"{:#x}","value":"0x0"}},"error":"Reverted","subtraces":0,"traceAddress":[],"type":"call"}}]"#,
+			sender(),
+			contract
+		)
+	);
+}
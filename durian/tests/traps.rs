@@ -0,0 +1,110 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, deploy};
+use common::TestProvider;
+use durian::address::Address;
+use durian::execute;
+use durian::outcome::{ExecutionOutcome, TrapKind, TrapLocation};
+use durian::profiler::GasProfile;
+use primitive_types::H256;
+
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "input_length" (func $input_length (result i32)))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(func $divide (param $x i32) (result i32)
+		(i32.div_u (i32.const 100) (local.get $x)))
+	(func (export "deploy"))
+	(func $call (export "call")
+		(if (i32.eq (call $input_length) (i32.const 2))
+			(then (call $fetch_input (i32.const 0x7fffffff))))
+		(drop (call $divide (call $input_length))))
+)
+"#;
+
+/// Calls the contract in the profiling mode, which records the trap sites
+fn call_profiled(provider: &mut TestProvider, contract: Address, input: Vec<u8>) -> ExecutionOutcome {
+	execute::execute_with_profiler(provider, &call_tx(contract, input), &mut GasProfile::new()).unwrap().outcome
+}
+
+#[test]
+fn locates_trap_by_function_name() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	let outcome = call_profiled(&mut provider, contract, vec![]);
+	assert_eq!(
+		outcome,
+		ExecutionOutcome::Trap(
+			TrapKind::DivisionByZero,
+			Some(TrapLocation {
+				function: 2,
+				offset: Some(2),
+				function_name: Some("divide".to_string()),
+			})
+		)
+	);
+	assert_eq!(
+		format!("{}", outcome),
+		"Trap: division by zero in func[2]+2 <divide>"
+	);
+	assert!(call_profiled(&mut provider, contract, vec![1]).is_success());
+}
+
+#[test]
+fn locates_trap_function_in_normal_execution() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	let tx = call_tx(contract, vec![]);
+	let profiled = execute::execute_with_profiler(&mut provider, &tx, &mut GasProfile::new()).unwrap();
+	let result = execute::execute(&mut provider, &tx).unwrap();
+	assert_eq!(
+		result.outcome,
+		ExecutionOutcome::Trap(
+			TrapKind::DivisionByZero,
+			Some(TrapLocation {
+				function: 2,
+				offset: None,
+				function_name: Some("divide".to_string()),
+			})
+		)
+	);
+	assert_eq!(format!("{}", result.outcome), "Trap: division by zero in func[2] <divide>");
+	assert_eq!(result.gas_used, profiled.gas_used);
+
+	// the function is recorded again once the callee returned
+	let code = r#"(module
+		(import "env" "memory" (memory 1 16))
+		(func $one (result i32) (i32.const 1))
+		(func (export "deploy"))
+		(func $call (export "call") (drop (call $one)) unreachable))"#;
+	let contract = deploy(&mut provider, code, H256::zero());
+	let tx = call_tx(contract, vec![]);
+	match execute::execute(&mut provider, &tx).unwrap().outcome {
+		ExecutionOutcome::Trap(TrapKind::Unreachable, Some(trap)) => {
+			assert_eq!((trap.function, trap.offset), (2, None));
+			assert_eq!(trap.function_name, Some("call".to_string()));
+		}
+		outcome => panic!("unexpected outcome {:?}", outcome),
+	}
+}
+
+#[test]
+fn locates_host_error_at_call() {
+	let mut provider = TestProvider::new();
+	let contract = deploy(&mut provider, CONTRACT, H256::zero());
+
+	match call_profiled(&mut provider, contract, vec![1, 2]) {
+		ExecutionOutcome::Trap(TrapKind::Host(_), Some(trap)) => {
+			assert_eq!((trap.function, trap.offset), (4, Some(5)));
+			assert_eq!(trap.function_name, Some("call".to_string()));
+		}
+		outcome => panic!("unexpected outcome {:?}", outcome),
+	}
+}
@@ -0,0 +1,169 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{call_tx, create_tx};
+use common::TestProvider;
+use durian::error::Error;
+use durian::execute::{self, ResultData};
+use durian::outcome::ExecutionOutcome;
+use durian::provider::{self, Provider};
+use durian::schedule::Schedule;
+use durian::tracer::NoopTracer;
+use primitive_types::{H256, U256};
+
+/// Contract returning the byte `value`
+fn contract(value: u8) -> String {
+	format!(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "ret" (func $ret (param i32 i32)))
+		(func (export "deploy"))
+		(func (export "call")
+			(i32.store8 (i32.const 0) (i32.const {}))
+			(call $ret (i32.const 0) (i32.const 1))))
+	"#,
+		value
+	)
+}
+
+fn create(provider: &mut TestProvider, code: &str, salt: H256) -> ResultData {
+	let tx = create_tx(wat::parse_str(code).unwrap(), salt);
+	execute::execute(provider, &tx).unwrap()
+}
+
+fn invalid_code(code: &str) -> String {
+	let result = create(&mut TestProvider::new(), code, H256::zero());
+	assert_eq!(result.gas_left, U256::zero());
+	match result.outcome {
+		ExecutionOutcome::InvalidCode(msg) => msg,
+		outcome => panic!("unexpected outcome {}", outcome),
+	}
+}
+
+#[test]
+fn rejects_floats() {
+	let msg = invalid_code(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(func (export "deploy")
+			(drop (f32.add (f32.const 1) (f32.const 2))))
+		(func (export "call")))
+	"#,
+	);
+	assert_eq!(msg, "Invalid code: floating point instructions are forbidden");
+}
+
+#[test]
+fn rejects_unknown_imports() {
+	let msg = invalid_code(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "unknown" (func $unknown))
+		(func (export "deploy") (call $unknown))
+		(func (export "call")))
+	"#,
+	);
+	assert!(msg.contains("unknown"), "{}", msg);
+}
+
+#[test]
+fn rejects_unimplemented_imports() {
+	let msg = invalid_code(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "create" (func $create (param i32 i32 i32 i32) (result i32)))
+		(func (export "deploy"))
+		(func (export "call")))
+	"#,
+	);
+	assert_eq!(msg, "Invalid code: function import create is not provided by the runtime");
+}
+
+#[test]
+fn rejects_internal_memory() {
+	let msg = invalid_code(
+		r#"
+	(module
+		(memory 1)
+		(func (export "deploy"))
+		(func (export "call")))
+	"#,
+	);
+	assert_eq!(msg, "WASM Internal error: Malformed wasm module: internal memory");
+}
+
+#[test]
+fn rejects_module_without_entry() {
+	let msg = invalid_code(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(func (export "main")))
+	"#,
+	);
+	assert_eq!(msg, "Module doesn't export `call`");
+}
+
+#[test]
+fn loads_prepared_code() {
+	let mut provider = TestProvider::new();
+	let first = create(&mut provider, &contract(1), H256::repeat_byte(1)).contract;
+	let second = create(&mut provider, &contract(2), H256::repeat_byte(2)).contract;
+
+	// the instrumented code is stored at deployment for the latest version
+	let version = Schedule::new_wasm().latest_version;
+	let first_hash = provider.account(&first).unwrap().unwrap().code_hash;
+	let second_hash = provider.account(&second).unwrap().unwrap().code_hash;
+	assert_eq!(provider.prepared_codes.len(), 2);
+	assert_eq!(provider::code_hash(&provider.codes[&first_hash]), first_hash);
+
+	// the calls execute the stored artifact rather than the contract code
+	let prepared = provider.prepared_codes[&(second_hash, version)].clone();
+	provider.prepared_codes.insert((first_hash, version), prepared);
+	let result = execute::execute(&mut provider, &call_tx(first, vec![])).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(result.data, vec![2]);
+}
+
+#[test]
+fn rejects_unsupported_code_version() {
+	let mut schedule = Schedule::new_wasm();
+	schedule.latest_version = U256::one();
+	let code = wat::parse_str(contract(1)).unwrap();
+	let tx = create_tx(code, H256::zero());
+	match execute::execute_with_schedule(&mut TestProvider::new(), &tx, &schedule, &mut NoopTracer) {
+		Err(Error::UnsupportedCodeVersion { version }) => assert_eq!(version, U256::one()),
+		result => panic!("unexpected result {:?}", result),
+	}
+}
+
+#[test]
+fn validates_prepared_code() {
+	let mut provider = TestProvider::new();
+	let contract = create(&mut provider, &contract(1), H256::zero()).contract;
+	let code_hash = provider.account(&contract).unwrap().unwrap().code_hash;
+	let version = Schedule::new_wasm().latest_version;
+
+	let artifact = wat::parse_str(
+		r#"
+	(module
+		(import "env" "memory" (memory 1 16))
+		(import "env" "unknown" (func $unknown))
+		(func (export "call") (call $unknown)))
+	"#,
+	)
+	.unwrap();
+	provider.prepared_codes.insert((code_hash, version), artifact);
+	let result = execute::execute(&mut provider, &call_tx(contract, vec![])).unwrap();
+	assert_eq!(
+		result.outcome,
+		ExecutionOutcome::InvalidCode("Invalid code: function import unknown is not provided by the runtime".to_string())
+	);
+}
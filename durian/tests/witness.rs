@@ -0,0 +1,183 @@
+extern crate durian;
+extern crate primitive_types;
+extern crate serde_json;
+extern crate wat;
+
+mod common;
+
+use common::fixtures::{block, call_tx, contract, sender};
+use durian::address::Address;
+use durian::error::Error;
+use durian::execute;
+use durian::provider::memory::{MemoryAccount, MemoryProvider};
+use durian::provider;
+use durian::transaction::Transaction;
+use durian::witness::{self, Witness};
+use primitive_types::{H256, U256};
+use std::collections::BTreeMap;
+
+/// Writes the value to the storage at the key given as the input and returns the hash of the previous block
+const CONTRACT: &str = r#"
+(module
+	(import "env" "memory" (memory 1 16))
+	(import "env" "fetch_input" (func $fetch_input (param i32)))
+	(import "env" "storage_write" (func $storage_write (param i32 i32)))
+	(import "env" "block_hash" (func $block_hash (param i64 i32)))
+	(import "env" "ret" (func $ret (param i32 i32)))
+	(func (export "deploy"))
+	(func (export "call")
+		(call $fetch_input (i32.const 0))
+		(call $storage_write (i32.const 0) (i32.const 32))
+		(call $block_hash (i64.const 41) (i32.const 64))
+		(call $ret (i32.const 64) (i32.const 32)))
+)
+"#;
+
+/// Hashes of the blocks the verifier knows
+fn block_hashes() -> BTreeMap<u64, H256> {
+	(0..42).map(|number| (number, H256::repeat_byte(number as u8))).collect()
+}
+
+fn provider() -> MemoryProvider {
+	let mut provider = MemoryProvider::new(block(42));
+	for (number, hash) in block_hashes() {
+		provider.set_block_hash(number, hash);
+	}
+	provider.insert_account(
+		sender(),
+		MemoryAccount {
+			balance: U256::from(1000),
+			..Default::default()
+		},
+	);
+	for byte in 0x30..0x40 {
+		provider.insert_account(Address::repeat_byte(byte), MemoryAccount::default());
+	}
+
+	let code_hash = provider.insert_code(wat::parse_str(CONTRACT).unwrap());
+	let mut storage = BTreeMap::new();
+	for byte in 1..8 {
+		storage.insert(H256::repeat_byte(byte), H256::repeat_byte(byte));
+	}
+	provider.insert_account(
+		contract(),
+		MemoryAccount {
+			code_hash,
+			storage,
+			..Default::default()
+		},
+	);
+	provider
+}
+
+fn transaction(key: H256, value: H256) -> Transaction {
+	let mut input = key.as_bytes().to_vec();
+	input.extend_from_slice(value.as_bytes());
+	call_tx(contract(), input)
+}
+
+fn record(provider: &mut MemoryProvider, tx: &Transaction) -> (execute::ResultData, Witness) {
+	let state = provider.state_trie().unwrap();
+	let (result, witness) = witness::record(provider, &state, tx).unwrap();
+	assert!(result.outcome.is_success(), "{}", result.outcome);
+	assert_eq!(witness.state_root, state.root());
+	(result, witness)
+}
+
+#[test]
+fn executes_from_witness() {
+	let mut provider = provider();
+	let pre_root = provider.state_trie().unwrap().root();
+
+	// writing and removing storage values, removing collapses the branches of the trie
+	for (key, value) in &[(3, 0x33), (9, 9), (1, 0), (9, 0)] {
+		let tx = transaction(H256::repeat_byte(*key), H256::from_low_u64_be(*value));
+		let pre_root_tx = provider.state_trie().unwrap().root();
+		let (result, witness) = record(&mut provider, &tx);
+		assert_eq!(result.data, H256::repeat_byte(41).as_bytes().to_vec());
+
+		// the witness is shipped to another machine
+		let witness: Witness = serde_json::from_str(&serde_json::to_string(&witness).unwrap()).unwrap();
+		let (stateless, post_root) = witness::execute(&witness, &pre_root_tx, &block(42), &block_hashes(), &tx).unwrap();
+		assert_eq!(stateless.data, result.data);
+		assert_eq!(stateless.gas_used, result.gas_used);
+		assert_eq!(post_root, provider.state_trie().unwrap().root());
+	}
+	assert_ne!(provider.state_trie().unwrap().root(), pre_root);
+}
+
+#[test]
+fn proves_only_touched_state() {
+	let mut provider = provider();
+	let nodes = provider.state_trie().unwrap().nodes().len();
+	let (_, witness) = record(&mut provider, &transaction(H256::repeat_byte(1), H256::repeat_byte(2)));
+	assert!(witness.nodes.len() < nodes);
+	assert_eq!(witness.codes.len(), 1);
+	assert_eq!(witness.codes[0], wat::parse_str(CONTRACT).unwrap());
+	assert_eq!(provider::code_hash(&witness.codes[0]), provider.get(&contract()).unwrap().code_hash);
+	assert_eq!(witness.block_hashes.get(&41), Some(&H256::repeat_byte(41)));
+}
+
+#[test]
+fn rejects_other_state_root() {
+	let mut provider = provider();
+	let tx = transaction(H256::repeat_byte(1), H256::repeat_byte(2));
+	let (_, witness) = record(&mut provider, &tx);
+	match witness::execute(&witness, &provider.state_trie().unwrap().root(), &block(42), &block_hashes(), &tx) {
+		Err(Error::InvalidWitness { .. }) => {}
+		result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+	}
+}
+
+#[test]
+fn fails_on_incomplete_witness() {
+	let mut provider = provider();
+	let tx = transaction(H256::repeat_byte(1), H256::repeat_byte(2));
+	let (_, witness) = record(&mut provider, &tx);
+
+	for index in 0..witness.nodes.len() {
+		let mut incomplete = witness.clone();
+		incomplete.nodes.remove(index);
+		match witness::execute(&incomplete, &witness.state_root, &block(42), &block_hashes(), &tx) {
+			Err(Error::ProviderFailure { .. }) | Err(Error::MissingTrieNode { .. }) => {}
+			result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+		}
+	}
+
+	// a forged code doesn't match the hash of the contract code
+	let mut forged = witness.clone();
+	forged.codes[0].push(0);
+	match witness::execute(&forged, &witness.state_root, &block(42), &block_hashes(), &tx) {
+		Err(Error::ProviderFailure { .. }) => {}
+		result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+	}
+
+	let mut missing = witness.clone();
+	missing.block_hashes.clear();
+	match witness::execute(&missing, &witness.state_root, &block(42), &block_hashes(), &tx) {
+		Err(Error::ProviderFailure { .. }) => {}
+		result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+	}
+}
+
+#[test]
+fn rejects_forged_block_hashes() {
+	let mut provider = provider();
+	let tx = transaction(H256::repeat_byte(1), H256::repeat_byte(2));
+	let (_, witness) = record(&mut provider, &tx);
+
+	let mut forged = witness.clone();
+	forged.block_hashes.insert(41, H256::repeat_byte(0x66));
+	match witness::execute(&forged, &witness.state_root, &block(42), &block_hashes(), &tx) {
+		Err(Error::InvalidWitness { .. }) => {}
+		result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+	}
+
+	// the verifier doesn't know the block
+	let mut known = block_hashes();
+	known.remove(&41);
+	match witness::execute(&witness, &witness.state_root, &block(42), &known, &tx) {
+		Err(Error::InvalidWitness { .. }) => {}
+		result => panic!("unexpected result {:?}", result.map(|(_, root)| root)),
+	}
+}
@@ -8,14 +8,17 @@ pub struct Block {
     pub num: u64,
     pub prev: H256,
     pub time: SystemTime,
+    /// State root after the transactions of the block
+    pub state_root: H256,
 }
 
 impl Block {
-    pub fn new(num: u64, prev: H256) -> Block {
+    pub fn new(num: u64, prev: H256, state_root: H256) -> Block {
         Block {
             num: num,
             prev: prev,
             time: std::time::SystemTime::now(),
+            state_root,
         }
     }
 
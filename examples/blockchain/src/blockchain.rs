@@ -3,7 +3,8 @@ use crate::block::Block;
 use durian::address::Address;
 use durian::error::Error;
 use durian::execute::ResultData;
-use durian::provider::{Provider, StateAccount};
+use durian::provider::{self, Provider, StateAccount, StateChangeSet};
+use durian::state_trie::{AccountProof, StateTrie, TrieAccount};
 use durian::transaction::{Action, Transaction};
 use hex_literal::hex;
 use primitive_types::{H160, H256, U256};
@@ -11,12 +12,27 @@ use sha3::{Digest, Keccak256};
 use std::collections::HashMap;
 use std::time::SystemTime;
 
+/// Chain ID of the development chain
+pub const CHAIN_ID: u64 = 1337;
+
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     blocks: Vec<Block>,
     accounts: HashMap<String, Account>,
     counter: i32,
     transactions: HashMap<H256, (Transaction, ResultData)>,
+    codes: HashMap<H256, Vec<u8>>,
+    prepared_codes: HashMap<(H256, U256), Vec<u8>>,
+    state: StateTrie,
+}
+
+fn trie_account(acc: &Account) -> TrieAccount {
+    TrieAccount {
+        nonce: acc.nonce,
+        balance: acc.balance,
+        code_hash: provider::code_hash(&acc.code),
+        ..Default::default()
+    }
 }
 
 pub fn transaction_hash(transaction: &Transaction) -> H256 {
@@ -52,7 +68,6 @@ pub fn transaction_hash(transaction: &Transaction) -> H256 {
 
 impl Blockchain {
     pub fn new() -> Blockchain {
-        let gen = Block::new(0, H256::zero());
         let mut accounts = HashMap::new();
         let addr = Address::from_slice(&hex!("004ec07d2329997267ec62b4166639513386f32e")[..]);
 
@@ -77,21 +92,35 @@ impl Blockchain {
             Account::new(addr, U256::from(1000000), U256::zero(), vec![]),
         );
 
+        let mut state = StateTrie::new();
+        for acc in accounts.values() {
+            state.insert_account(&acc.address, &trie_account(acc)).unwrap();
+        }
+        let gen = Block::new(0, H256::zero(), state.root());
+
         Blockchain {
             blocks: vec![gen],
             accounts: accounts,
             counter: 0,
             transactions: HashMap::new(),
+            codes: HashMap::new(),
+            prepared_codes: HashMap::new(),
+            state,
         }
     }
 
     pub fn commit(&mut self) {
-        let block = Block::new(self.blocks.len() as u64, self.blocks.last().unwrap().hash());
+        let block = Block::new(
+            self.blocks.len() as u64,
+            self.blocks.last().unwrap().hash(),
+            self.state.root(),
+        );
 
         info!(
-            "Committing new block. num: {}, hash: {}",
+            "Committing new block. num: {}, hash: {}, state root: {}",
             block.num,
-            block.hash()
+            block.hash(),
+            block.state_root
         );
         self.blocks.push(block);
 
@@ -131,7 +160,16 @@ impl Blockchain {
 
     pub fn inc_nonce(&mut self, alias: &str) {
         let mut acc = self.accounts.get_mut(alias).unwrap();
-        acc.nonce = acc.nonce + U256::from(1)
+        acc.nonce = acc.nonce + U256::from(1);
+
+        let mut trie_acc = self.state.account(&acc.address).unwrap().unwrap();
+        trie_acc.nonce = acc.nonce;
+        self.state.insert_account(&acc.address, &trie_acc).unwrap();
+    }
+
+    /// Root of the state trie, committed in the next block
+    pub fn state_root(&self) -> H256 {
+        self.state.root()
     }
 
     fn account(&self, address: &Address) -> Result<&Account, Error> {
@@ -172,35 +210,61 @@ impl Blockchain {
     pub fn latest_block_hash(&self) -> H256 {
         self.blocks.last().unwrap().hash()
     }
+
+    pub fn block(&self, num: u64) -> Option<&Block> {
+        self.blocks.get(num as usize)
+    }
+
+    /// Sets the code of the account, creating the account if it doesn't exist
+    fn insert_code(&mut self, address: &Address, code: &[u8]) {
+        self.codes.insert(provider::code_hash(code), code.to_vec());
+        if let Ok(acc) = self.account_mut(address) {
+            acc.code = code.to_vec();
+            return;
+        }
+
+        let name = format!("contract_{}", self.counter + 1);
+        let acc = Account::new(*address, U256::zero(), U256::zero(), code.to_vec());
+        self.accounts.insert(name, acc);
+        self.counter += 1;
+    }
+
+    /// Proof of the account and its storage in the state of the `state_root`, e.g. of a block
+    pub fn get_proof(&self, state_root: &H256, address: &Address, storage_keys: &[H256]) -> Result<AccountProof, Error> {
+        self.state.get_proof_at(state_root, address, storage_keys)
+    }
 }
 
 impl Provider for Blockchain {
-    fn account(&self, address: &Address) -> Result<StateAccount, Error> {
-        let acc = self.account(address)?;
-        Ok(StateAccount {
+    fn account(&self, address: &Address) -> Result<Option<StateAccount>, Error> {
+        Ok(self.account(address).ok().map(|acc| StateAccount {
             balance: U256::from(acc.balance),
             nonce: U256::from(acc.nonce),
-            code: acc.code.clone(),
-        })
+            code_hash: provider::code_hash(&acc.code),
+        }))
     }
 
     fn create_contract(&mut self, address: &Address, code: &Vec<u8>) -> Result<(), Error> {
-        let name = format!("contract_{}", self.counter + 1);
-        let acc = Account::new(*address, U256::zero(), U256::zero(), code.clone());
-        self.accounts.insert(name, acc);
-        self.counter = self.counter + 1;
+        self.state.apply_changes(&StateChangeSet {
+            created: vec![(*address, code.clone())],
+            ..Default::default()
+        })?;
+        self.insert_code(address, code);
         Ok(())
     }
 
-    fn storage_at(&self, address: &Address, key: &H256) -> Result<H256, Error> {
-        let acc = self.account(address)?;
-        match acc.storage.get(key) {
-            Some(storage) => Ok(*storage),
-            _ => Err(Error::InvalidStorageKey { key: *key }),
-        }
+    fn storage_at(&self, address: &Address, key: &H256) -> Result<Option<H256>, Error> {
+        Ok(self
+            .account(address)
+            .ok()
+            .and_then(|acc| acc.storage.get(key).cloned()))
     }
 
     fn set_storage(&mut self, address: &Address, key: &H256, value: &H256) -> Result<(), Error> {
+        self.state.apply_changes(&StateChangeSet {
+            storage: vec![(*address, *key, *value)],
+            ..Default::default()
+        })?;
         let acc = self.account_mut(address).unwrap();
         let val = acc.storage.entry(*key).or_insert(*value);
         *val = *value;
@@ -230,21 +294,76 @@ impl Provider for Blockchain {
     }
 
     fn difficulty(&self) -> Result<U256, Error> {
-        Err(Error::NotSupported)
+        Ok(U256::zero())
     }
 
     fn gas_limit(&self) -> Result<U256, Error> {
         Ok(U256::from(1000000))
     }
 
-    fn exist(&self, address: &Address) -> bool {
-        self.account(address).is_ok()
+    fn chain_id(&self) -> u64 {
+        CHAIN_ID
+    }
+
+    fn prev_randao(&self) -> Result<Option<H256>, Error> {
+        Ok(Some(self.latest_block_hash()))
+    }
+
+    fn exist(&self, address: &Address) -> Result<bool, Error> {
+        Ok(self.account(address).is_ok())
     }
 
     fn update_account(&mut self, address: &Address, bal: &U256, nonce: &U256) -> Result<(), Error> {
+        self.state.apply_changes(&StateChangeSet {
+            updated: vec![(*address, *bal, *nonce)],
+            ..Default::default()
+        })?;
         let mut acc = self.account_mut(address).unwrap();
         acc.balance = *bal;
         acc.nonce = *nonce;
         Ok(())
     }
+
+    fn apply_changes(&mut self, changes: StateChangeSet) -> Result<(), Error> {
+        // the state trie rejects the whole change set before the accounts are changed
+        self.state.apply_changes(&changes)?;
+        for address in &changes.deleted {
+            self.accounts.retain(|_, acc| acc.address != *address);
+        }
+        for (address, code) in &changes.created {
+            self.insert_code(address, code);
+        }
+        for (address, balance, nonce) in &changes.updated {
+            let acc = self.account_mut(address)?;
+            acc.balance = *balance;
+            acc.nonce = *nonce;
+        }
+        for (address, key, value) in &changes.storage {
+            self.account_mut(address)?.storage.insert(*key, *value);
+        }
+        for (code_hash, version, code) in &changes.prepared_codes {
+            self.prepared_codes.insert((*code_hash, *version), code.clone());
+        }
+        Ok(())
+    }
+
+    fn delete_account(&mut self, address: &Address) -> Result<(), Error> {
+        self.apply_changes(StateChangeSet {
+            deleted: vec![*address],
+            ..Default::default()
+        })
+    }
+
+    fn code_by_hash(&self, code_hash: &H256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.codes.get(code_hash).cloned())
+    }
+
+    fn prepared_code(&self, code_hash: &H256, version: &U256) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.prepared_codes.get(&(*code_hash, *version)).cloned())
+    }
+
+    fn set_prepared_code(&mut self, code_hash: &H256, version: &U256, code: &Vec<u8>) -> Result<(), Error> {
+        self.prepared_codes.insert((*code_hash, *version), code.clone());
+        Ok(())
+    }
 }
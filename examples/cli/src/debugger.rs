@@ -0,0 +1,166 @@
+use durian::debugger::{Breakpoint, DebugState, Debugger, Location, Resume};
+use durian::outcome::ExecutionOutcome;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+s, step                 stop at the next instruction
+c, continue             run until the next breakpoint
+b, break <func>[:<off>] stop at the function or at the instruction offset
+d, delete <n>           delete the breakpoint
+i, info                 list the breakpoints
+l, locals               print the locals of the current function
+bt, backtrace           print the call stack
+m, memory <addr> <len>  print the linear memory
+g, gas                  print the gas left
+code [<func>]           print the instructions of the function
+q, quit                 delete all the breakpoints and run to the end";
+
+/// Interactive debugger, reads the commands from stdin
+pub struct CliDebugger {
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl CliDebugger {
+    pub fn new(breakpoints: Vec<Breakpoint>) -> Self {
+        CliDebugger { breakpoints }
+    }
+
+    fn read_command(&self) -> Option<Vec<String>> {
+        print!("(durian) ");
+        io::stdout().flush().ok()?;
+
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.split_whitespace().map(String::from).collect()),
+        }
+    }
+}
+
+/// Parses `<func>[:<offset>]`
+pub fn parse_breakpoint(arg: &str) -> Option<Breakpoint> {
+    let mut parts = arg.splitn(2, ':');
+    let function = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some(offset) => Some(Breakpoint::Instruction(Location {
+            function,
+            offset: offset.parse().ok()?,
+        })),
+        None => Some(Breakpoint::Function(function)),
+    }
+}
+
+fn describe(state: &DebugState, location: &Location) -> String {
+    let instruction = state.instruction(location).unwrap_or("");
+    match state.function_name(location.function) {
+        Some(name) => format!("{} <{}>: {}", location, name, instruction),
+        None => format!("{}: {}", location, instruction),
+    }
+}
+
+fn print_memory(state: &DebugState, addr: usize, len: usize) {
+    let end = addr.saturating_add(len).min(state.memory.len());
+    if addr >= end {
+        println!("out of memory bounds, memory size is {}", state.memory.len());
+        return;
+    }
+
+    for (row, chunk) in state.memory[addr..end].chunks(16).enumerate() {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        println!("{:08x}: {}", addr + row * 16, bytes.join(" "));
+    }
+}
+
+impl Debugger for CliDebugger {
+    fn start(&mut self) -> Resume {
+        println!("{}", HELP);
+        if self.breakpoints.is_empty() {
+            Resume::Step
+        } else {
+            Resume::Continue
+        }
+    }
+
+    fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    fn stop(&mut self, state: &DebugState) -> Resume {
+        println!("{}", describe(state, state.location));
+
+        loop {
+            let command = match self.read_command() {
+                Some(command) => command,
+                None => return Resume::Continue,
+            };
+            let args: Vec<&str> = command.iter().map(String::as_str).collect();
+
+            match args.as_slice() {
+                [] => {}
+                ["s"] | ["step"] => return Resume::Step,
+                ["c"] | ["continue"] => return Resume::Continue,
+                ["q"] | ["quit"] => {
+                    self.breakpoints.clear();
+                    return Resume::Continue;
+                }
+                ["b", arg] | ["break", arg] => match parse_breakpoint(arg) {
+                    Some(breakpoint) => self.breakpoints.push(breakpoint),
+                    None => println!("invalid breakpoint, expected <func>[:<offset>]"),
+                },
+                ["d", n] | ["delete", n] => match n.parse::<usize>() {
+                    Ok(n) if n < self.breakpoints.len() => {
+                        self.breakpoints.remove(n);
+                    }
+                    _ => println!("no breakpoint {}", n),
+                },
+                ["i"] | ["info"] => {
+                    for (n, breakpoint) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {:?}", n, breakpoint);
+                    }
+                }
+                ["l"] | ["locals"] => {
+                    for (idx, local) in state.locals.iter().enumerate() {
+                        println!("local[{}] = {}", idx, local);
+                    }
+                }
+                ["bt"] | ["backtrace"] => {
+                    for (depth, location) in state.call_stack.iter().rev().enumerate() {
+                        println!("#{} {}", depth, describe(state, location));
+                    }
+                }
+                ["m", addr, len] | ["memory", addr, len] => {
+                    match (addr.parse::<usize>(), len.parse::<usize>()) {
+                        (Ok(addr), Ok(len)) => print_memory(state, addr, len),
+                        _ => println!("invalid address or length"),
+                    }
+                }
+                ["g"] | ["gas"] => println!("gas left: {}", state.gas_left),
+                ["code"] | ["code", _] => {
+                    let function = match args.get(1) {
+                        Some(function) => function.parse().unwrap_or(state.location.function),
+                        None => state.location.function,
+                    };
+                    let code = state.code.get(&function).map(Vec::as_slice).unwrap_or(&[]);
+                    for (offset, instruction) in code.iter().enumerate() {
+                        let current = function == state.location.function
+                            && offset as u32 == state.location.offset;
+                        let marker = if current { "=>" } else { "  " };
+                        println!("{} {:5} {}", marker, offset, instruction);
+                    }
+                }
+                _ => println!("{}", HELP),
+            }
+        }
+    }
+
+    fn finish(&mut self, outcome: &ExecutionOutcome, state: Option<&DebugState>) {
+        println!("execution finished: {}", outcome);
+        if let Some(state) = state {
+            println!("last instruction: {}", describe(state, state.location));
+            for (depth, location) in state.call_stack.iter().rev().enumerate() {
+                println!("#{} {}", depth, describe(state, location));
+            }
+            println!("gas left: {}", state.gas_left);
+        }
+    }
+}
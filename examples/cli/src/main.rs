@@ -6,16 +6,102 @@ extern crate simple_logger;
 #[macro_use]
 extern crate log;
 
+mod debugger;
+
 use blockchain::blockchain::Blockchain;
-use durian::execute;
+use debugger::CliDebugger;
+use durian::debugger::Breakpoint;
+use durian::execute::{self, ResultData};
+use durian::profiler::GasProfile;
 use durian::transaction::Transaction;
 use primitive_types::{H256, U256};
 use log::Level;
 use std::fs::File;
+use std::env;
 use std::io::Read;
 
+const USAGE: &str = "usage: cli [--debug <tx>] [--break <func>[:<offset>]]... [--profile <tx>]
+
+Runs the token contract: deploy (1), transfer (2), total_supply (3) and balance_of (4).
+--debug runs the transaction <tx> in the debugger, which stops at the first instruction
+unless a breakpoint is given.
+--profile prints the gas used by the functions of the transaction <tx>
+and writes the folded stacks to gas-<tx>.folded.";
+
+/// Transactions to debug and profile, and the initial breakpoints
+struct Options {
+    debug: Option<usize>,
+    breakpoints: Vec<Breakpoint>,
+    profile: Option<usize>,
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        debug: None,
+        breakpoints: vec![],
+        profile: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--debug", Some(tx)) => match tx.parse() {
+                Ok(tx) => options.debug = Some(tx),
+                Err(_) => panic!("{}", USAGE),
+            },
+            ("--profile", Some(tx)) => match tx.parse() {
+                Ok(tx) => options.profile = Some(tx),
+                Err(_) => panic!("{}", USAGE),
+            },
+            ("--break", Some(breakpoint)) => match debugger::parse_breakpoint(&breakpoint) {
+                Some(breakpoint) => options.breakpoints.push(breakpoint),
+                None => panic!("{}", USAGE),
+            },
+            _ => panic!("{}", USAGE),
+        }
+    }
+    options
+}
+
+fn run(bc: &mut Blockchain, tx: &Transaction, number: usize, options: &Options) -> ResultData {
+    if options.debug == Some(number) {
+        let mut debugger = CliDebugger::new(options.breakpoints.clone());
+        execute::execute_with_debugger(bc, tx, &mut debugger).unwrap()
+    } else if options.profile == Some(number) {
+        let mut profile = GasProfile::new();
+        let result = execute::execute_with_profiler(bc, tx, &mut profile).unwrap();
+        write_profile(&profile, number);
+        result
+    } else {
+        execute::execute(bc, tx).unwrap()
+    }
+}
+
+fn write_profile(profile: &GasProfile, number: usize) {
+    println!("{:>12} {:>12}  function", "self", "total");
+    for function in profile.functions() {
+        println!(
+            "{:>12} {:>12}  {}",
+            function.self_gas.to_string(),
+            function.total_gas.to_string(),
+            function.name
+        );
+    }
+
+    let path = format!("gas-{}.folded", number);
+    let mut file = match File::create(&path) {
+        Ok(file) => file,
+        Err(err) => panic!("{}", err),
+    };
+    if let Err(err) = profile.write_folded(&mut file) {
+        panic!("{}", err);
+    }
+    info!("folded stacks are written to {}", path);
+}
+
 fn main() {
     simple_logger::init_with_level(Level::Debug).unwrap();
+    let options = parse_args();
 
     let mut bc = Blockchain::new();
 
@@ -40,14 +126,14 @@ fn main() {
     let tx1 = Transaction::make_create(
         bc.address_from_alias("alice"),
         U256::zero(),
-        U256::from(1000000),
+        U256::from(10000000),
         U256::zero(),
         code,
         params1,
         H256::zero(),
     );
 
-    let ret1 = execute::execute(&mut bc, &tx1).unwrap();
+    let ret1 = run(&mut bc, &tx1, 1, &options);
 
     //info!("ret1: {:?}", ret1);
     bc.inc_nonce("alice");
@@ -73,7 +159,7 @@ fn main() {
         params2,
     );
 
-    let ret2 = execute::execute(&mut bc, &tx2).unwrap();
+    let ret2 = run(&mut bc, &tx2, 2, &options);
     info!("ret2: {:?}", ret2);
     bc.inc_nonce("alice");
     bc.commit();
@@ -90,7 +176,7 @@ fn main() {
         U256::zero(),
         params3,
     );
-    let ret3 = execute::execute(&mut bc, &tx3).unwrap();
+    let ret3 = run(&mut bc, &tx3, 3, &options);
     info!("ret3: {:?}", ret3);
     bc.inc_nonce("alice");
     bc.commit();
@@ -109,7 +195,7 @@ fn main() {
         U256::zero(),
         params4,
     );
-    let ret4 = execute::execute(&mut bc, &tx4).unwrap();
+    let ret4 = run(&mut bc, &tx4, 4, &options);
     info!("ret4: {:?}", ret4);
     bc.inc_nonce("bob");
     bc.commit();
@@ -18,6 +18,10 @@ mod provider_impl;
 use blockchain::blockchain::Blockchain;
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
 use durian::address::Address;
+use durian::limits::Limit;
+use durian::log_entry::LogEntry;
+use durian::outcome::{ExecutionOutcome, TrapKind, TrapLocation};
+use durian::panic_payload::PanicPayload;
 use durian::transaction::{Action, Transaction};
 use durian_capnp::executor;
 use futures::task::LocalSpawn;
@@ -98,7 +102,7 @@ fn main() {
         let tx1 = Transaction::make_create(
             BC.lock()?.address_from_alias("alice"),
             U256::zero(),
-            U256::from(1000000),
+            U256::from(10000000),
             U256::zero(),
             code,
             params1,
@@ -214,9 +218,25 @@ impl<'a> From<durian_capnp::executor::execute_results::Reader<'a>> for durian::e
         let data = reader.get_result_data().unwrap().get_data().unwrap();
         let contract =
             Address::from_slice(reader.get_result_data().unwrap().get_contract().unwrap());
-        let logs = vec![];
+        let gas_used =
+            U256::from_little_endian(reader.get_result_data().unwrap().get_gas_used().unwrap());
+        let outcome = read_outcome(reader.get_result_data().unwrap().get_outcome());
+        let mut logs = vec![];
+        for log in reader.get_result_data().unwrap().get_logs().unwrap().iter() {
+            let mut topics = vec![];
+            for topic in log.get_topics().unwrap().iter() {
+                topics.push(H256::from_slice(topic.unwrap()));
+            }
+            logs.push(LogEntry {
+                address: Address::from_slice(log.get_address().unwrap()),
+                topics: topics,
+                data: log.get_data().unwrap().iter().map(|byte| byte as u8).collect(),
+            });
+        }
 
         durian::execute::ResultData {
+            outcome: outcome,
+            gas_used: gas_used,
             gas_left: gas_left,
             data: data.to_vec(),
             contract: contract,
@@ -225,6 +245,83 @@ impl<'a> From<durian_capnp::executor::execute_results::Reader<'a>> for durian::e
     }
 }
 
+fn read_outcome(reader: durian_capnp::result_data::outcome::Reader) -> ExecutionOutcome {
+    let non_empty = |text: &str| {
+        if text.is_empty() {
+            None
+        } else {
+            Some(text.to_string())
+        }
+    };
+    let non_zero = |num: u32| if num == 0 { None } else { Some(num) };
+
+    match reader.which().unwrap() {
+        durian_capnp::result_data::outcome::Success(()) => ExecutionOutcome::Success,
+        durian_capnp::result_data::outcome::Revert(data) => {
+            ExecutionOutcome::Revert(data.unwrap().to_vec())
+        }
+        durian_capnp::result_data::outcome::OutOfGas(()) => ExecutionOutcome::OutOfGas,
+        durian_capnp::result_data::outcome::Trap(trap) => {
+            let kind = match trap.get_kind().unwrap() {
+                durian_capnp::TrapKind::Unreachable => TrapKind::Unreachable,
+                durian_capnp::TrapKind::MemoryAccessOutOfBounds => TrapKind::MemoryAccessOutOfBounds,
+                durian_capnp::TrapKind::TableAccessOutOfBounds => TrapKind::TableAccessOutOfBounds,
+                durian_capnp::TrapKind::ElemUninitialized => TrapKind::ElemUninitialized,
+                durian_capnp::TrapKind::DivisionByZero => TrapKind::DivisionByZero,
+                durian_capnp::TrapKind::InvalidConversionToInt => TrapKind::InvalidConversionToInt,
+                durian_capnp::TrapKind::UnexpectedSignature => TrapKind::UnexpectedSignature,
+                durian_capnp::TrapKind::StackOverflow => TrapKind::StackOverflow,
+                durian_capnp::TrapKind::Host => {
+                    TrapKind::Host(trap.get_host_error().unwrap().to_string())
+                }
+            };
+            let location = if trap.has_location() {
+                let location = trap.get_location().unwrap();
+                Some(TrapLocation {
+                    function: location.get_function(),
+                    offset: if location.get_has_offset() {
+                        Some(location.get_offset())
+                    } else {
+                        None
+                    },
+                    function_name: non_empty(location.get_function_name().unwrap()),
+                })
+            } else {
+                None
+            };
+            ExecutionOutcome::Trap(kind, location)
+        }
+        durian_capnp::result_data::outcome::Panic(payload) => {
+            let payload = payload.unwrap();
+            ExecutionOutcome::Panic(PanicPayload {
+                msg: non_empty(payload.get_msg().unwrap()),
+                file: non_empty(payload.get_file().unwrap()),
+                line: non_zero(payload.get_line()),
+                col: non_zero(payload.get_col()),
+            })
+        }
+        durian_capnp::result_data::outcome::InvalidCode(msg) => {
+            ExecutionOutcome::InvalidCode(msg.unwrap().to_string())
+        }
+        durian_capnp::result_data::outcome::LimitExceeded(limit) => {
+            ExecutionOutcome::LimitExceeded(match limit.unwrap() {
+                durian_capnp::Limit::MemoryPages => Limit::MemoryPages,
+                durian_capnp::Limit::HostCalls => Limit::HostCalls,
+                durian_capnp::Limit::ReturnData => Limit::ReturnData,
+                durian_capnp::Limit::Deadline => Limit::Deadline,
+                durian_capnp::Limit::FetchRounds => Limit::FetchRounds,
+            })
+        }
+        durian_capnp::result_data::outcome::CodeSizeLimit(code_size_limit) => {
+            let code_size_limit = code_size_limit.unwrap();
+            ExecutionOutcome::CodeSizeLimit {
+                size: code_size_limit.get_size() as usize,
+                limit: code_size_limit.get_limit() as usize,
+            }
+        }
+    }
+}
+
 fn build_tx(builder: &mut durian_capnp::transaction::Builder, tx: &Transaction) {
     let mut tmp = Vec::new();
     tmp.resize(32, 0);
@@ -242,6 +339,18 @@ fn build_tx(builder: &mut durian_capnp::transaction::Builder, tx: &Transaction)
 
     builder.set_args(&tx.args);
 
+    let mut access_list_builder = builder
+        .reborrow()
+        .init_access_list(tx.access_list.len() as u32);
+    for (i, (address, keys)) in tx.access_list.iter().enumerate() {
+        let mut entry_builder = access_list_builder.reborrow().get(i as u32);
+        entry_builder.set_address(address.as_bytes());
+        let mut keys_builder = entry_builder.init_keys(keys.len() as u32);
+        for (j, key) in keys.iter().enumerate() {
+            keys_builder.set(j as u32, key.as_bytes());
+        }
+    }
+
     let action_builder = builder.reborrow().init_action();
     match &tx.action {
         Action::Create(code, salt) => {
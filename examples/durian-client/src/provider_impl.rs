@@ -2,8 +2,8 @@ use crate::durian_capnp::provider;
 use blockchain::blockchain::Blockchain;
 use capnp::capability::Promise;
 use durian::address::Address;
-use durian::provider::Provider;
-use primitive_types::H256;
+use durian::provider::{Provider, StateChangeSet};
+use primitive_types::{H256, U256};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -26,9 +26,15 @@ impl<'a> provider::Server for ProviderImpl<'a> {
         debug!("server called `exist` method.");
 
         let address = Address::from_slice(pry!(pry!(params.get()).get_address()));
-        let exist = self.bc.lock().unwrap().exist(&address);
-        results.get().set_exist(exist);
-        Promise::ok(())
+        match self.bc.lock().unwrap().exist(&address) {
+            Ok(exist) => {
+                results.get().set_exist(exist);
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
     }
 
     fn account(
@@ -41,7 +47,12 @@ impl<'a> provider::Server for ProviderImpl<'a> {
         let address = Address::from_slice(pry!(pry!(params.get()).get_address()));
 
         match self.bc.lock().unwrap().account(&address) {
-            Ok(account) => {
+            Ok(None) => {
+                results.get().set_exist(false);
+                return Promise::ok(());
+            }
+            Ok(Some(account)) => {
+                results.get().set_exist(true);
                 let mut account_result = results.get().init_account();
                 let mut tmp = Vec::new();
                 tmp.resize(32, 0);
@@ -51,7 +62,7 @@ impl<'a> provider::Server for ProviderImpl<'a> {
                 account.balance.to_little_endian(&mut tmp);
                 account_result.set_balance(&tmp);
 
-                account_result.set_code(&account.code);
+                account_result.set_code_hash(account.code_hash.as_bytes());
                 return Promise::ok(());
             }
             Err(e) => {
@@ -92,7 +103,9 @@ impl<'a> provider::Server for ProviderImpl<'a> {
 
         match self.bc.lock().unwrap().storage_at(&address, &key) {
             Ok(storage) => {
-                results.get().set_storage(storage.as_bytes());
+                // an empty slot is sent as empty data
+                let storage = storage.as_ref().map(H256::as_bytes).unwrap_or_default();
+                results.get().set_storage(storage);
                 return Promise::ok(());
             }
             Err(e) => {
@@ -121,4 +134,306 @@ impl<'a> provider::Server for ProviderImpl<'a> {
             }
         }
     }
+
+    fn code_by_hash(
+        &mut self,
+        params: provider::CodeByHashParams,
+        mut results: provider::CodeByHashResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `code_by_hash` method");
+
+        let code_hash = H256::from_slice(pry!(pry!(params.get()).get_code_hash()));
+
+        match self.bc.lock().unwrap().code_by_hash(&code_hash) {
+            Ok(Some(code)) => {
+                results.get().set_exist(true);
+                results.get().set_code(&code);
+                return Promise::ok(());
+            }
+            Ok(None) => {
+                results.get().set_exist(false);
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn prepared_code(
+        &mut self,
+        params: provider::PreparedCodeParams,
+        mut results: provider::PreparedCodeResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `prepared_code` method");
+
+        let code_hash = H256::from_slice(pry!(pry!(params.get()).get_code_hash()));
+        let version = U256::from_little_endian(pry!(pry!(params.get()).get_version()));
+
+        match self.bc.lock().unwrap().prepared_code(&code_hash, &version) {
+            Ok(Some(code)) => {
+                results.get().set_exist(true);
+                results.get().set_code(&code);
+                return Promise::ok(());
+            }
+            Ok(None) => {
+                results.get().set_exist(false);
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn set_prepared_code(
+        &mut self,
+        params: provider::SetPreparedCodeParams,
+        _: provider::SetPreparedCodeResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `set_prepared_code` method");
+
+        let code_hash = H256::from_slice(pry!(pry!(params.get()).get_code_hash()));
+        let version = U256::from_little_endian(pry!(pry!(params.get()).get_version()));
+        let code = pry!(pry!(params.get()).get_code()).to_vec();
+
+        match self
+            .bc
+            .lock()
+            .unwrap()
+            .set_prepared_code(&code_hash, &version, &code)
+        {
+            Ok(()) => {
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn chain_id(
+        &mut self,
+        _: provider::ChainIdParams,
+        mut results: provider::ChainIdResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `chain_id` method");
+
+        let chain_id = self.bc.lock().unwrap().chain_id();
+        results.get().set_chain_id(chain_id);
+        Promise::ok(())
+    }
+
+    fn base_fee(
+        &mut self,
+        _: provider::BaseFeeParams,
+        mut results: provider::BaseFeeResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `base_fee` method");
+
+        match self.bc.lock().unwrap().base_fee() {
+            Ok(Some(base_fee)) => {
+                let mut tmp = Vec::new();
+                tmp.resize(32, 0);
+                base_fee.to_little_endian(&mut tmp);
+                results.get().set_exist(true);
+                results.get().set_base_fee(&tmp);
+                return Promise::ok(());
+            }
+            Ok(None) => {
+                results.get().set_exist(false);
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn prev_randao(
+        &mut self,
+        _: provider::PrevRandaoParams,
+        mut results: provider::PrevRandaoResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `prev_randao` method");
+
+        match self.bc.lock().unwrap().prev_randao() {
+            Ok(Some(prev_randao)) => {
+                results.get().set_exist(true);
+                results.get().set_prev_randao(prev_randao.as_bytes());
+                return Promise::ok(());
+            }
+            Ok(None) => {
+                results.get().set_exist(false);
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn block_context(
+        &mut self,
+        _: provider::BlockContextParams,
+        mut results: provider::BlockContextResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `block_context` method");
+
+        match self.bc.lock().unwrap().block_context() {
+            Ok(block) => {
+                let mut context = results.get().init_context();
+                let mut tmp = Vec::new();
+                tmp.resize(32, 0);
+                context.set_number(block.number);
+                context.set_timestamp(block.timestamp);
+                context.set_author(block.author.as_bytes());
+
+                block.difficulty.to_little_endian(&mut tmp);
+                context.set_difficulty(&tmp);
+
+                block.gas_limit.to_little_endian(&mut tmp);
+                context.set_gas_limit(&tmp);
+
+                context.set_chain_id(block.chain_id);
+                if let Some(base_fee) = block.base_fee {
+                    base_fee.to_little_endian(&mut tmp);
+                    context.set_has_base_fee(true);
+                    context.set_base_fee(&tmp);
+                }
+                if let Some(prev_randao) = block.prev_randao {
+                    context.set_has_prev_randao(true);
+                    context.set_prev_randao(prev_randao.as_bytes());
+                }
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn accounts(
+        &mut self,
+        params: provider::AccountsParams,
+        mut results: provider::AccountsResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `accounts` method");
+
+        let mut addresses = Vec::new();
+        for address in pry!(pry!(params.get()).get_addresses()).iter() {
+            addresses.push(Address::from_slice(pry!(address)));
+        }
+
+        match self.bc.lock().unwrap().accounts(&addresses) {
+            Ok(accounts) => {
+                let mut tmp = Vec::new();
+                tmp.resize(32, 0);
+                {
+                    let mut exist = results.get().init_exist(accounts.len() as u32);
+                    for (i, account) in accounts.iter().enumerate() {
+                        exist.set(i as u32, account.is_some());
+                    }
+                }
+                let mut account_results = results.get().init_accounts(accounts.len() as u32);
+                for (i, account) in accounts.iter().enumerate() {
+                    if let Some(account) = account {
+                        let mut account_result = account_results.reborrow().get(i as u32);
+                        account.nonce.to_little_endian(&mut tmp);
+                        account_result.set_nonce(&tmp);
+
+                        account.balance.to_little_endian(&mut tmp);
+                        account_result.set_balance(&tmp);
+
+                        account_result.set_code_hash(account.code_hash.as_bytes());
+                    }
+                }
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn storages(
+        &mut self,
+        params: provider::StoragesParams,
+        mut results: provider::StoragesResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `storages` method");
+
+        let addresses = pry!(pry!(params.get()).get_addresses());
+        let storage_keys = pry!(pry!(params.get()).get_keys());
+        let mut keys = Vec::new();
+        for (address, key) in addresses.iter().zip(storage_keys.iter()) {
+            keys.push((
+                Address::from_slice(pry!(address)),
+                H256::from_slice(pry!(key)),
+            ));
+        }
+
+        match self.bc.lock().unwrap().storages(&keys) {
+            Ok(values) => {
+                let mut storages = results.get().init_storages(values.len() as u32);
+                for (i, value) in values.iter().enumerate() {
+                    storages.set(i as u32, value.as_ref().map(H256::as_bytes).unwrap_or_default());
+                }
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
+
+    fn apply_changes(
+        &mut self,
+        params: provider::ApplyChangesParams,
+        _: provider::ApplyChangesResults,
+    ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
+        debug!("server called `apply_changes` method");
+
+        let reader = pry!(pry!(params.get()).get_changes());
+        let mut changes = StateChangeSet::default();
+        for contract in pry!(reader.get_created()).iter() {
+            changes.created.push((
+                Address::from_slice(pry!(contract.get_address())),
+                pry!(contract.get_code()).to_vec(),
+            ));
+        }
+        for account in pry!(reader.get_updated()).iter() {
+            changes.updated.push((
+                Address::from_slice(pry!(account.get_address())),
+                U256::from_little_endian(pry!(account.get_balance())),
+                U256::from_little_endian(pry!(account.get_nonce())),
+            ));
+        }
+        for change in pry!(reader.get_storage()).iter() {
+            changes.storage.push((
+                Address::from_slice(pry!(change.get_address())),
+                H256::from_slice(pry!(change.get_key())),
+                H256::from_slice(pry!(change.get_value())),
+            ));
+        }
+        for prepared_code in pry!(reader.get_prepared_codes()).iter() {
+            changes.prepared_codes.push((
+                H256::from_slice(pry!(prepared_code.get_code_hash())),
+                U256::from_little_endian(pry!(prepared_code.get_version())),
+                pry!(prepared_code.get_code()).to_vec(),
+            ));
+        }
+        for address in pry!(reader.get_deleted()).iter() {
+            changes.deleted.push(Address::from_slice(pry!(address)));
+        }
+
+        match self.bc.lock().unwrap().apply_changes(changes) {
+            Ok(()) => {
+                return Promise::ok(());
+            }
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!("{}", e)));
+            }
+        }
+    }
 }
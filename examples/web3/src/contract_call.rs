@@ -30,7 +30,12 @@ pub fn transaction_recipit(bc: &mut Blockchain, params: H256) -> Result<TxReceip
 	let tx_details = bc.get_transaction_details(params);
 	let sender = tx_details.clone().unwrap().0.sender;
 	let contract_address = tx_details.clone().unwrap().1.contract;
-	let gas_left = tx_details.clone().unwrap().1.gas_left;
+	let gas_used = tx_details.clone().unwrap().1.gas_used;
+	let status = if tx_details.clone().unwrap().1.outcome.is_success() {
+		"0x01"
+	} else {
+		"0x00"
+	};
 	let u1 = U256::zero();
 	println!("the bc contains {:?}", bc);
 
@@ -40,11 +45,11 @@ pub fn transaction_recipit(bc: &mut Blockchain, params: H256) -> Result<TxReceip
 		from: sender,
 		to: H160::zero(),
 		block_number: bc.block_number() as u32,
-		gas_used: gas_left,
+		gas_used: gas_used,
 		contract_address: contract_address,
-		cumulative_gas_used: gas_left,
+		cumulative_gas_used: gas_used,
 		transaction_index: u1,
-		status: "0x01",
+		status: status,
 	};
 	Ok(receipt)
 }
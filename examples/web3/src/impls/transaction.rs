@@ -8,12 +8,12 @@ use durian::execute;
 use durian::transaction::Transaction;
 use primitive_types::{H160, H256, U256};
 use jsonrpc_core::futures::future;
-use jsonrpc_core::{BoxFuture, Result};
+use jsonrpc_core::{BoxFuture, Error, ErrorCode, Result};
 use metadata::Metadata;
 use std::sync::Mutex;
 use traits::TransactionRPC;
 use types::{BlockNumber, Bytes};
-use types::{CallRequest, TransactionRequest, TxReceipt};
+use types::{CallRequest, EthAccount, TransactionRequest, TxReceipt};
 
 pub struct TransactionRPCImpl {
 	bc: Mutex<Blockchain>,
@@ -78,4 +78,26 @@ impl TransactionRPC for TransactionRPCImpl {
 		let res = Bytes::new(ret3.data);
 		Ok(res)
 	}
+
+	//Get the account and its storage with their proofs against the state root of the block
+	fn proof(&self, address: H160, storage_keys: Vec<H256>, num: Option<BlockNumber>) -> Result<EthAccount> {
+		let bc = self.bc.lock().unwrap();
+		let block = match num.unwrap_or_default() {
+			BlockNumber::Num(num) => bc.block(num),
+			BlockNumber::Earliest => bc.block(0),
+			BlockNumber::Latest | BlockNumber::Pending => bc.block(bc.block_number()),
+			BlockNumber::Hash { .. } => return Err(Error::invalid_params("Block hash is not supported")),
+		};
+		let state_root = match block {
+			Some(block) => block.state_root,
+			None => return Err(Error::invalid_params("Unknown block")),
+		};
+
+		let proof = bc.get_proof(&state_root, &address, &storage_keys).map_err(|err| Error {
+			code: ErrorCode::InternalError,
+			message: err.to_string(),
+			data: None,
+		})?;
+		Ok(EthAccount::from(proof))
+	}
 }
@@ -4,7 +4,7 @@ use jsonrpc_core::{BoxFuture, Result};
 use jsonrpc_derive::rpc;
 use types::CallRequest;
 use types::{BlockNumber, Bytes};
-use types::{TransactionRequest, TxReceipt};
+use types::{EthAccount, TransactionRequest, TxReceipt};
 
 #[rpc(server)]
 pub trait TransactionRPC {
@@ -28,5 +28,9 @@ pub trait TransactionRPC {
 	#[rpc(name = "eth_call")]
 	fn call(&self, call: CallRequest, bn: Option<BlockNumber>) -> Result<Bytes>;
 
+	/// Returns the account and its storage at the given keys, with their Merkle proofs.
+	#[rpc(name = "eth_getProof")]
+	fn proof(&self, _: H160, _: Vec<H256>, _: Option<BlockNumber>) -> Result<EthAccount>;
+
 
 }
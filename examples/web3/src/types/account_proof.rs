@@ -0,0 +1,62 @@
+use crate::types::Bytes;
+use durian::provider::EMPTY_CODE_HASH;
+use durian::state_trie::AccountProof;
+use durian::trie::EMPTY_ROOT;
+use primitive_types::{H160, H256, U256};
+use serde_derive::Serialize;
+
+/// Account with its Merkle proof, returned by `eth_getProof`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthAccount {
+	pub address: H160,
+	pub balance: U256,
+	pub nonce: U256,
+	pub code_hash: H256,
+	/// Root of the storage trie
+	pub storage_hash: H256,
+	/// Nodes of the state trie on the path of the account, starting with the root
+	pub account_proof: Vec<Bytes>,
+	pub storage_proof: Vec<StorageProof>,
+}
+
+/// Storage value with its Merkle proof against the storage root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StorageProof {
+	pub key: H256,
+	pub value: U256,
+	/// Nodes of the storage trie on the path of the key, starting with the root
+	pub proof: Vec<Bytes>,
+}
+
+fn into_bytes(proof: Vec<Vec<u8>>) -> Vec<Bytes> {
+	proof.into_iter().map(Bytes::new).collect()
+}
+
+impl From<AccountProof> for EthAccount {
+	/// An account that doesn't exist is returned empty, with the proof that it doesn't exist
+	fn from(proof: AccountProof) -> Self {
+		let (balance, nonce, code_hash, storage_hash) = match proof.account {
+			Some(account) => (account.balance, account.nonce, account.code_hash, account.storage_root),
+			None => (U256::zero(), U256::zero(), EMPTY_CODE_HASH, EMPTY_ROOT),
+		};
+
+		EthAccount {
+			address: proof.address,
+			balance,
+			nonce,
+			code_hash,
+			storage_hash,
+			account_proof: into_bytes(proof.proof),
+			storage_proof: proof
+				.storage
+				.into_iter()
+				.map(|storage| StorageProof {
+					key: storage.key,
+					value: U256::from_big_endian(storage.value.as_bytes()),
+					proof: into_bytes(storage.proof),
+				})
+				.collect(),
+		}
+	}
+}
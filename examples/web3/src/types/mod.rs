@@ -4,6 +4,7 @@ mod provenance;
 mod transaction_recepits;
 mod block_number;
 mod call_request;
+mod account_proof;
 
 pub use self::bytes::Bytes;
 pub use self::transaction_request::{TransactionRequest,SignedTransaction};
@@ -11,3 +12,4 @@ pub use self::provenance::Origin;
 pub use self::transaction_recepits::TxReceipt;
 pub use self::block_number::BlockNumber;
 pub use self::call_request::CallRequest;
+pub use self::account_proof::{EthAccount, StorageProof};